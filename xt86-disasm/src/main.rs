@@ -0,0 +1,85 @@
+use xtreme86::cpu::Model;
+use xtreme86::disasm::analysis::{Analysis, SweepMode};
+use xtreme86::disasm::format::FormatOptions;
+use xtreme86::disasm::image::Image;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "Usage: xt86-disasm [--linear | --recursive] [--listing | --nasm] [--cpu MODEL] [-o OUTPUT] FILE
+
+Disassembles a DOS .COM or MZ .EXE file.
+
+  --recursive   Follow jumps and calls from the entry point, the rest is data (default)
+  --linear      Decode every byte in order
+  --listing     Print addresses, bytes and instructions (default)
+  --nasm        Print source that nasm can assemble
+  --cpu MODEL   Decode as an 8088, 8086, 186 or 286 (default)
+  -o OUTPUT     Write to OUTPUT instead of stdout";
+
+struct Args {
+    mode: SweepMode,
+    nasm: bool,
+    model: Model,
+    input: String,
+    output: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut mode = SweepMode::Recursive;
+    let mut nasm = false;
+    let mut model = Model::default();
+    let mut input = None;
+    let mut output = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--linear" => mode = SweepMode::Linear,
+            "--recursive" => mode = SweepMode::Recursive,
+            "--listing" => nasm = false,
+            "--nasm" => nasm = true,
+            "--cpu" => {
+                let name = args.next().ok_or("--cpu needs a model")?;
+                model = Model::from_name(&name).ok_or_else(|| format!("Unknown CPU {}", name))?;
+            }
+            "-o" => output = Some(args.next().ok_or("-o needs a file name")?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(String::from("Only one input file is supported"))
+        }
+    }
+
+    Ok(Args { mode, nasm, model, input: input.ok_or("No input file")?, output })
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let bytes = fs::read(&args.input).map_err(|err| format!("Couldn't read {}: {}", args.input, err))?;
+    let image = Image::load(bytes)?;
+    let analysis = Analysis::with_model(&image, args.mode, args.model);
+
+    let text = if args.nasm {
+        analysis.nasm_source()
+    } else {
+        analysis.listing(&FormatOptions { absolute_targets: true, ..FormatOptions::nasm() })
+    };
+
+    match args.output {
+        Some(output) => fs::write(&output, text).map_err(|err| format!("Couldn't write {}: {}", output, err)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    let result = parse_args().and_then(run);
+    if let Err(err) = result {
+        eprintln!("xt86-disasm: {}\n\n{}", err, USAGE);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,62 @@
+use std::process::Command;
+use std::fs;
+use xtreme86::asm::assemble;
+
+#[test]
+fn test_disassemble_com_file() {
+    let path = std::env::temp_dir().join("xt86_disasm_test.com");
+    // mov ah, 0x4C; int 0x21; jmp $
+    fs::write(&path, [0xB4, 0x4C, 0xCD, 0x21, 0xEB, 0xFE]).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_xt86-disasm")).arg("--nasm").arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               "bits 16\norg 0x100\n\nstart:\n    mov ah, 0x4C\n    int 0x21\n\nloc_0104:\n    jmp short loc_0104\n");
+}
+
+#[test]
+fn test_nasm_output_reassembles() {
+    let path = std::env::temp_dir().join("xt86_disasm_roundtrip.com");
+    let program = [
+        0xB9, 0x03, 0x00,       // mov cx, 3
+        0x81, 0xC0, 0x01, 0x00, // again: add ax, 1 with a word immediate
+        0x8B, 0x06, 0x20, 0x01, // mov ax, [0x120] without the accumulator form
+        0xE2, 0xF6,             // loop again
+        0x9A, 0x00, 0x00, 0x00, 0x00, // call 0:0
+        0xCC,                   // int3
+        0xCD, 0x20,             // int 0x20
+        0xCB,                   // retf
+    ];
+    fs::write(&path, program).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_xt86-disasm")).arg("--nasm").arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(assemble(&String::from_utf8(output.stdout).unwrap()).unwrap(), program);
+}
+
+#[test]
+fn test_cpu_model() {
+    let path = std::env::temp_dir().join("xt86_disasm_cpu.com");
+    // ret 2 on the 8086, rol al, 2 from the 186 on
+    fs::write(&path, [0xC0, 0xC0, 0x02]).unwrap();
+
+    let run = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_xt86-disasm")).args(args).arg(&path).output().unwrap();
+    let i8086 = run(&["--cpu", "8086"]);
+    let i286 = run(&[]);
+    let unknown = run(&["--cpu", "z80"]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(String::from_utf8(i8086.stdout).unwrap().contains("C0C002              ret 0x2C0\n"));
+    assert!(String::from_utf8(i286.stdout).unwrap().contains("C0C002              rol al, 0x2\n"));
+    assert!(!unknown.status.success());
+}
+
+#[test]
+fn test_missing_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_xt86-disasm")).arg("/nonexistent.com").output().unwrap();
+    assert!(!output.status.success());
+}
@@ -1,9 +1,5 @@
 use xtreme86::peripheral::Peripheral;
 use xtreme86::cpu::{CPU, Regs};
-use std::path::{Path};
-use std::fs::File;
-use std::fs;
-use std::io::Read;
 
 #[derive(Clone)]
 struct Printer;
@@ -34,29 +30,10 @@ impl Peripheral for Printer {
     fn handle_mem_write_word(&mut self, _: u16, _: u16) {}
 }
 
-fn load_file(path: &Path, comp: &mut CPU) {
-    let mut f = File::open(path).unwrap();
-    let metadata = fs::metadata(&path).unwrap();
-    let mut buf = vec![0u8; metadata.len() as usize];
-
-    f.read(&mut buf).unwrap();
-
-    comp.load(buf, 0x103F0);
-}
-
 fn main() {
-    let mut comp = CPU::new(0x7FFFFF);
-    comp.set_reg(Regs::SS, 0x003F);
-    comp.set_reg(Regs::CS, 0x103F);
-    comp.set_reg(Regs::DS, 0x203F);
-    comp.set_reg(Regs::SP, 0xFFFF);
-    comp.set_reg(Regs::IP, 0x0000);
-
-    load_file(Path::new("examples/print.out"), &mut comp);
+    let mut comp = CPU::new(0x7FFFF);
     comp.hook_peripheral(Box::new(Printer));
-
-    let string = "Hello, world\0";
-    comp.write_bytes(CPU::physical_address(0x4000, 0) as usize, Vec::from(string)).unwrap();
+    comp.load_com_file("examples/print.out", 0x1000, "").unwrap();
 
     comp.run_to_nop_from_ip();
 }
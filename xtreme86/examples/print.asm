@@ -1,16 +1,9 @@
 CPU 286
+org 0x100
 
-    mov ax, 0x4000
-    mov es, ax
-
-    mov bx, 0
-load_loop:
-    mov ch, [es:bx]
-    mov [ds:bx], ch
-    inc bx
-    cmp ch, 0
-    jne load_loop
-
-    mov dx, 0
+    mov dx, message
     int 0x21
     nop
+
+message:
+    db "Hello, world", 0
@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use xtreme86::asm::assemble;
+use xtreme86::cpu::instruction::InstructionDecoder;
+use xtreme86::cpu::instruction::opcode::Opcode;
+use xtreme86::disasm::Disassembler;
+use xtreme86::disasm::format::FormatOptions;
+
+// Decoding any bytes must not panic, and an instruction that decodes and assembles again has to
+// come back the same
+fuzz_target!(|data: &[u8]| {
+    if let Ok(instruction) = InstructionDecoder::new(Opcode::table(), data).get() {
+        assert!(instruction.length <= data.len());
+        instruction.semantics((0, 0x100));
+    }
+
+    let listing: Vec<_> = Disassembler::with_options(data, 0, 0x100, FormatOptions::nasm()).collect();
+    assert_eq!(listing.iter().map(|decoded| decoded.length).sum::<usize>(), data.len());
+
+    if let Some(decoded) = listing.first().filter(|decoded| !decoded.is_data()) {
+        let encoded = assemble(&format!("org 0x100\n{}\n", decoded.text)).expect("disassembly doesn't assemble");
+        let first = Disassembler::with_options(&encoded, 0, 0x100, FormatOptions::nasm()).decode_at(0).unwrap();
+        let again = assemble(&format!("org 0x100\n{}\n", first.text)).expect("disassembly doesn't assemble");
+        assert_eq!(first.bytes, again);
+    }
+});
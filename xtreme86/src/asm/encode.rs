@@ -0,0 +1,439 @@
+use crate::cpu::instruction::args::Size;
+use crate::cpu::Model;
+use crate::asm::expr::Value;
+use crate::asm::operand::{Operand, Kind, Memory, Distance};
+
+// What the encoder needs to know about where the instruction goes
+pub struct Context {
+    pub here: i64,
+    pub cpu: Model,
+    // A jump on this line didn't fit in a short jump in an earlier pass
+    pub near: bool,
+    // Set when a jump has to become a near jump, which moves everything after it
+    pub grow: bool,
+}
+
+const ALU: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const SHIFT: [&str; 8] = ["rol", "ror", "rcl", "rcr", "shl", "shr", "sal", "sar"];
+const UNARY: [&str; 6] = ["not", "neg", "mul", "imul", "div", "idiv"];
+
+const CONDITIONS: [(&str, u8); 32] = [
+    ("jo", 0x70), ("jno", 0x71), ("jb", 0x72), ("jc", 0x72), ("jnae", 0x72), ("jnb", 0x73), ("jnc", 0x73), ("jae", 0x73),
+    ("je", 0x74), ("jz", 0x74), ("jne", 0x75), ("jnz", 0x75), ("jbe", 0x76), ("jna", 0x76), ("ja", 0x77), ("jnbe", 0x77),
+    ("js", 0x78), ("jns", 0x79), ("jp", 0x7A), ("jpe", 0x7A), ("jnp", 0x7B), ("jpo", 0x7B), ("jl", 0x7C), ("jnge", 0x7C),
+    ("jge", 0x7D), ("jnl", 0x7D), ("jle", 0x7E), ("jng", 0x7E), ("jg", 0x7F), ("jnle", 0x7F), ("jcxz", 0xE3), ("loop", 0xE2),
+];
+
+const LOOPS: [(&str, u8); 4] = [("loopne", 0xE0), ("loopnz", 0xE0), ("loope", 0xE1), ("loopz", 0xE1)];
+
+// Instructions without operands, and the CPU they first appeared on
+const SIMPLE: [(&str, &[u8], Model); 44] = [
+    ("nop", &[0x90], Model::I8088), ("hlt", &[0xF4], Model::I8088), ("cmc", &[0xF5], Model::I8088),
+    ("clc", &[0xF8], Model::I8088), ("stc", &[0xF9], Model::I8088), ("cli", &[0xFA], Model::I8088),
+    ("sti", &[0xFB], Model::I8088), ("cld", &[0xFC], Model::I8088), ("std", &[0xFD], Model::I8088),
+    ("pushf", &[0x9C], Model::I8088), ("popf", &[0x9D], Model::I8088), ("sahf", &[0x9E], Model::I8088),
+    ("lahf", &[0x9F], Model::I8088), ("cbw", &[0x98], Model::I8088), ("cwd", &[0x99], Model::I8088),
+    ("wait", &[0x9B], Model::I8088), ("fwait", &[0x9B], Model::I8088), ("xlat", &[0xD7], Model::I8088),
+    ("xlatb", &[0xD7], Model::I8088), ("aaa", &[0x37], Model::I8088), ("aas", &[0x3F], Model::I8088),
+    ("daa", &[0x27], Model::I8088), ("das", &[0x2F], Model::I8088), ("movsb", &[0xA4], Model::I8088),
+    ("movsw", &[0xA5], Model::I8088), ("cmpsb", &[0xA6], Model::I8088), ("cmpsw", &[0xA7], Model::I8088),
+    ("stosb", &[0xAA], Model::I8088), ("stosw", &[0xAB], Model::I8088), ("lodsb", &[0xAC], Model::I8088),
+    ("lodsw", &[0xAD], Model::I8088), ("scasb", &[0xAE], Model::I8088), ("scasw", &[0xAF], Model::I8088),
+    ("into", &[0xCE], Model::I8088), ("int3", &[0xCC], Model::I8088), ("iret", &[0xCF], Model::I8088),
+    ("insb", &[0x6C], Model::I80186), ("insw", &[0x6D], Model::I80186), ("outsb", &[0x6E], Model::I80186),
+    ("outsw", &[0x6F], Model::I80186), ("pusha", &[0x60], Model::I80186), ("popa", &[0x61], Model::I80186),
+    ("leave", &[0xC9], Model::I80186), ("clts", &[0x0F, 0x06], Model::I80286),
+];
+
+// 286 system instructions that take a single ModR/M operand
+const SYSTEM: [(&str, u8, u8); 12] = [
+    ("sldt", 0x00, 0), ("str", 0x00, 1), ("lldt", 0x00, 2), ("ltr", 0x00, 3), ("verr", 0x00, 4), ("verw", 0x00, 5),
+    ("sgdt", 0x01, 0), ("sidt", 0x01, 1), ("lgdt", 0x01, 2), ("lidt", 0x01, 3), ("smsw", 0x01, 4), ("lmsw", 0x01, 6),
+];
+
+pub fn is_mnemonic(name: &str) -> bool {
+    let name = name.to_lowercase();
+    let name = name.as_str();
+    ALU.contains(&name) || SHIFT.contains(&name) || UNARY.contains(&name)
+        || CONDITIONS.iter().any(|(mnemonic, _)| *mnemonic == name)
+        || LOOPS.iter().any(|(mnemonic, _)| *mnemonic == name)
+        || SIMPLE.iter().any(|(mnemonic, _, _)| *mnemonic == name)
+        || SYSTEM.iter().any(|(mnemonic, _, _)| *mnemonic == name)
+        || ["mov", "test", "xchg", "inc", "dec", "push", "pop", "lea", "les", "lds", "bound", "jmp", "call", "ret",
+            "retn", "retf", "int", "in", "out", "enter", "aam", "aad", "lar", "lsl", "arpl"].contains(&name)
+}
+
+fn invalid() -> String {
+    String::from("invalid combination of opcode and operands")
+}
+
+fn require(ctx: &Context, level: Model, mnemonic: &str) -> Result<(), String> {
+    if ctx.cpu < level {
+        Err(format!("{} is not supported on the {}", mnemonic, ctx.cpu))
+    } else {
+        Ok(())
+    }
+}
+
+fn imm(value: Value, size: Size) -> Vec<u8> {
+    match size {
+        Size::Byte => vec![value.value as u8],
+        Size::Word => (value.value as u16).to_le_bytes().to_vec(),
+        Size::DWord => (value.value as u32).to_le_bytes().to_vec()
+    }
+}
+
+fn memory_bytes(reg: u8, memory: &Memory) -> Vec<u8> {
+    let reg = reg << 3;
+    match (memory.rm, memory.displacement) {
+        (None, Some(address)) => {
+            let mut bytes = vec![reg | 0b110];
+            bytes.extend(imm(address, Size::Word));
+            bytes
+        }
+        // [bp] has no mod 00 encoding, so it needs a zero displacement
+        (Some(rm), None) if rm != 0b110 => vec![reg | rm],
+        (Some(rm), Some(displacement)) if rm != 0b110 && displacement.constant && displacement.value == 0 => vec![reg | rm],
+        (Some(rm), displacement) => {
+            let displacement = displacement.unwrap_or(Value::constant(0));
+            if displacement.fits_i8() {
+                vec![0x40 | reg | rm, displacement.value as u8]
+            } else {
+                let mut bytes = vec![0x80 | reg | rm];
+                bytes.extend(imm(displacement, Size::Word));
+                bytes
+            }
+        }
+        (None, None) => unreachable!()
+    }
+}
+
+// The opcode followed by a ModR/M byte for `rm`, with its segment prefix in front
+fn with_modrm(opcode: &[u8], reg: u8, rm: &Operand) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let modrm = match &rm.kind {
+        Kind::Reg8(index) | Kind::Reg16(index) => vec![0xC0 | (reg << 3) | index],
+        Kind::Mem(memory) => {
+            bytes.extend(memory.segment);
+            memory_bytes(reg, memory)
+        }
+        _ => return Err(invalid())
+    };
+    bytes.extend_from_slice(opcode);
+    bytes.extend(modrm);
+    Ok(bytes)
+}
+
+fn reg_index(operand: &Operand) -> Option<u8> {
+    match operand.kind {
+        Kind::Reg8(index) | Kind::Reg16(index) => Some(index),
+        _ => None
+    }
+}
+
+fn is_reg(operand: &Operand) -> bool {
+    matches!(operand.kind, Kind::Reg8(_) | Kind::Reg16(_))
+}
+
+fn is_mem(operand: &Operand) -> bool {
+    matches!(operand.kind, Kind::Mem(_))
+}
+
+fn is_accumulator(operand: &Operand) -> bool {
+    matches!(operand.kind, Kind::Reg8(0) | Kind::Reg16(0))
+}
+
+// The size of an operation, from whichever operand says it
+fn size_of(operands: &[&Operand]) -> Result<Size, String> {
+    let mut size = None;
+    for operand in operands {
+        match (size, operand.size()) {
+            (Some(a), Some(b)) if a != b && !matches!(operand.kind, Kind::Imm(_)) => return Err(String::from("mismatch in operand sizes")),
+            (None, Some(b)) => size = Some(b),
+            _ => ()
+        }
+    }
+    match size {
+        Some(Size::DWord) => Err(String::from("32-bit operands are not supported")),
+        Some(size) => Ok(size),
+        None => Err(String::from("operation size not specified"))
+    }
+}
+
+fn w(size: Size) -> u8 {
+    if size == Size::Word { 1 } else { 0 }
+}
+
+fn relative(target: Value, ctx: &Context, length: i64) -> i64 {
+    ((target.value - (ctx.here + length)) as i16) as i64
+}
+
+fn short_jump(opcode: u8, target: Value, ctx: &Context) -> Result<Vec<u8>, String> {
+    let displacement = relative(target, ctx, 2);
+    if target.known && !(-128..=127).contains(&displacement) {
+        return Err(String::from("short jump is out of range"));
+    }
+    Ok(vec![opcode, displacement as u8])
+}
+
+fn near_branch(opcode: u8, target: Value, ctx: &Context) -> Vec<u8> {
+    let mut bytes = vec![opcode];
+    bytes.extend((relative(target, ctx, 3) as u16).to_le_bytes());
+    bytes
+}
+
+fn far_pointer(opcode: u8, segment: Value, offset: Value) -> Vec<u8> {
+    let mut bytes = vec![opcode];
+    bytes.extend(imm(offset, Size::Word));
+    bytes.extend(imm(segment, Size::Word));
+    bytes
+}
+
+fn alu(index: u8, dst: &Operand, src: &Operand) -> Result<Vec<u8>, String> {
+    let base = index * 8;
+    let size = size_of(&[dst, src])?;
+    match (&dst.kind, &src.kind) {
+        (_, Kind::Reg8(reg) | Kind::Reg16(reg)) if dst.is_rm() => with_modrm(&[base + w(size)], *reg, dst),
+        (Kind::Reg8(reg) | Kind::Reg16(reg), Kind::Mem(_)) => with_modrm(&[base + 2 + w(size)], *reg, src),
+        (_, Kind::Imm(value)) if dst.is_rm() => match size {
+            Size::Byte if is_accumulator(dst) => Ok(vec![base + 4, value.value as u8]),
+            Size::Byte => Ok([with_modrm(&[0x80], index, dst)?, imm(*value, Size::Byte)].concat()),
+            _ if value.fits_i8() => Ok([with_modrm(&[0x83], index, dst)?, imm(*value, Size::Byte)].concat()),
+            _ if is_accumulator(dst) => Ok([vec![base + 5], imm(*value, Size::Word)].concat()),
+            _ => Ok([with_modrm(&[0x81], index, dst)?, imm(*value, Size::Word)].concat())
+        },
+        _ => Err(invalid())
+    }
+}
+
+fn mov(dst: &Operand, src: &Operand) -> Result<Vec<u8>, String> {
+    match (&dst.kind, &src.kind) {
+        (Kind::Seg(seg), _) if src.is_rm() && src.size().unwrap_or(Size::Word) == Size::Word => with_modrm(&[0x8E], *seg, src),
+        (_, Kind::Seg(seg)) if dst.is_rm() && dst.size().unwrap_or(Size::Word) == Size::Word => with_modrm(&[0x8C], *seg, dst),
+        // The accumulator has shorter forms for direct addresses
+        (Kind::Reg8(0) | Kind::Reg16(0), Kind::Mem(Memory { rm: None, displacement: Some(address), segment })) => {
+            let size = size_of(&[dst, src])?;
+            Ok([segment.iter().copied().collect(), vec![0xA0 + w(size)], imm(*address, Size::Word)].concat())
+        }
+        (Kind::Mem(Memory { rm: None, displacement: Some(address), segment }), Kind::Reg8(0) | Kind::Reg16(0)) => {
+            let size = size_of(&[dst, src])?;
+            Ok([segment.iter().copied().collect(), vec![0xA2 + w(size)], imm(*address, Size::Word)].concat())
+        }
+        (_, Kind::Reg8(reg) | Kind::Reg16(reg)) if dst.is_rm() => with_modrm(&[0x88 + w(size_of(&[dst, src])?)], *reg, dst),
+        (Kind::Reg8(reg) | Kind::Reg16(reg), Kind::Mem(_)) => with_modrm(&[0x8A + w(size_of(&[dst, src])?)], *reg, src),
+        (Kind::Reg8(reg), Kind::Imm(value)) => Ok(vec![0xB0 + reg, value.value as u8]),
+        (Kind::Reg16(reg), Kind::Imm(value)) => Ok([vec![0xB8 + reg], imm(*value, Size::Word)].concat()),
+        (Kind::Mem(_), Kind::Imm(value)) => {
+            let size = size_of(&[dst, src])?;
+            Ok([with_modrm(&[0xC6 + w(size)], 0, dst)?, imm(*value, size)].concat())
+        }
+        _ => Err(invalid())
+    }
+}
+
+fn test(dst: &Operand, src: &Operand) -> Result<Vec<u8>, String> {
+    let size = size_of(&[dst, src])?;
+    match (&dst.kind, &src.kind) {
+        (_, Kind::Reg8(reg) | Kind::Reg16(reg)) if dst.is_rm() => with_modrm(&[0x84 + w(size)], *reg, dst),
+        (Kind::Reg8(reg) | Kind::Reg16(reg), Kind::Mem(_)) => with_modrm(&[0x84 + w(size)], *reg, src),
+        (_, Kind::Imm(value)) if is_accumulator(dst) => Ok([vec![0xA8 + w(size)], imm(*value, size)].concat()),
+        (_, Kind::Imm(value)) if dst.is_rm() => Ok([with_modrm(&[0xF6 + w(size)], 0, dst)?, imm(*value, size)].concat()),
+        _ => Err(invalid())
+    }
+}
+
+fn xchg(dst: &Operand, src: &Operand) -> Result<Vec<u8>, String> {
+    let size = size_of(&[dst, src])?;
+    match (&dst.kind, &src.kind) {
+        (Kind::Reg16(0), Kind::Reg16(reg)) | (Kind::Reg16(reg), Kind::Reg16(0)) => Ok(vec![0x90 + reg]),
+        // Like NASM, two registers put the first one in the reg field
+        (Kind::Reg8(reg) | Kind::Reg16(reg), Kind::Reg8(_) | Kind::Reg16(_)) => with_modrm(&[0x86 + w(size)], *reg, src),
+        (_, Kind::Reg8(reg) | Kind::Reg16(reg)) if dst.is_rm() => with_modrm(&[0x86 + w(size)], *reg, dst),
+        (Kind::Reg8(reg) | Kind::Reg16(reg), Kind::Mem(_)) => with_modrm(&[0x86 + w(size)], *reg, src),
+        _ => Err(invalid())
+    }
+}
+
+fn shift(index: u8, dst: &Operand, count: &Operand, ctx: &Context) -> Result<Vec<u8>, String> {
+    let size = size_of(&[dst])?;
+    match count.kind {
+        Kind::Imm(value) if value.constant && value.value == 1 => with_modrm(&[0xD0 + w(size)], index, dst),
+        Kind::Reg8(1) => with_modrm(&[0xD2 + w(size)], index, dst),
+        Kind::Imm(value) => {
+            require(ctx, Model::I80186, "shifting by an immediate")?;
+            Ok([with_modrm(&[0xC0 + w(size)], index, dst)?, imm(value, Size::Byte)].concat())
+        }
+        _ => Err(invalid())
+    }
+}
+
+fn jump(operand: &Operand, ctx: &mut Context) -> Result<Vec<u8>, String> {
+    match (&operand.kind, operand.distance) {
+        (Kind::Far(segment, offset), _) => Ok(far_pointer(0xEA, *segment, *offset)),
+        (Kind::Imm(_), Some(Distance::Far)) => Err(String::from("far jumps need a segment:offset target")),
+        (Kind::Imm(target), Some(Distance::Short)) => short_jump(0xEB, *target, ctx),
+        (Kind::Imm(target), Some(Distance::Near)) => Ok(near_branch(0xE9, *target, ctx)),
+        (Kind::Imm(target), None) => {
+            let fits = (-128..=127).contains(&relative(*target, ctx, 2));
+            if ctx.near || (target.known && !fits) {
+                ctx.grow |= !ctx.near;
+                Ok(near_branch(0xE9, *target, ctx))
+            } else {
+                short_jump(0xEB, *target, ctx)
+            }
+        }
+        (Kind::Mem(_), Some(Distance::Far)) => with_modrm(&[0xFF], 5, operand),
+        (Kind::Reg16(_) | Kind::Mem(_), _) => with_modrm(&[0xFF], 4, operand),
+        _ => Err(invalid())
+    }
+}
+
+fn call(operand: &Operand, ctx: &Context) -> Result<Vec<u8>, String> {
+    match (&operand.kind, operand.distance) {
+        (Kind::Far(segment, offset), _) => Ok(far_pointer(0x9A, *segment, *offset)),
+        (Kind::Imm(_), Some(Distance::Far)) => Err(String::from("far calls need a segment:offset target")),
+        (Kind::Imm(target), _) => Ok(near_branch(0xE8, *target, ctx)),
+        (Kind::Mem(_), Some(Distance::Far)) => with_modrm(&[0xFF], 3, operand),
+        (Kind::Reg16(_) | Kind::Mem(_), _) => with_modrm(&[0xFF], 2, operand),
+        _ => Err(invalid())
+    }
+}
+
+fn io(mnemonic: &str, port: &Operand, data: &Operand) -> Result<Vec<u8>, String> {
+    let base = if mnemonic == "in" { 0xE4 } else { 0xE6 };
+    let size = match data.kind {
+        Kind::Reg8(0) => Size::Byte,
+        Kind::Reg16(0) => Size::Word,
+        _ => return Err(invalid())
+    };
+    match port.kind {
+        Kind::Imm(value) => Ok(vec![base + w(size), value.value as u8]),
+        Kind::Reg16(2) => Ok(vec![base + 8 + w(size)]),
+        _ => Err(invalid())
+    }
+}
+
+pub fn encode(mnemonic: &str, operands: &[Operand], ctx: &mut Context) -> Result<Vec<u8>, String> {
+    let mnemonic = mnemonic.to_lowercase();
+    let mnemonic = mnemonic.as_str();
+
+    if let Some((_, bytes, level)) = SIMPLE.iter().find(|(name, _, _)| *name == mnemonic) {
+        require(ctx, *level, mnemonic)?;
+        return if operands.is_empty() { Ok(bytes.to_vec()) } else { Err(invalid()) };
+    }
+    if let Some(index) = ALU.iter().position(|name| *name == mnemonic) {
+        return match operands {
+            [dst, src] => alu(index as u8, dst, src),
+            _ => Err(invalid())
+        };
+    }
+    if let Some(index) = SHIFT.iter().position(|name| *name == mnemonic) {
+        // sal is another name for shl
+        let index = if mnemonic == "sal" { 4 } else if index > 5 { 7 } else { index as u8 };
+        return match operands {
+            [dst, count] if dst.is_rm() => shift(index, dst, count, ctx),
+            _ => Err(invalid())
+        };
+    }
+    if let Some((_, opcode)) = CONDITIONS.iter().chain(LOOPS.iter()).find(|(name, _)| *name == mnemonic) {
+        return match operands {
+            [Operand { kind: Kind::Imm(target), distance: None | Some(Distance::Short), .. }] => short_jump(*opcode, *target, ctx),
+            _ => Err(invalid())
+        };
+    }
+    if let Some((_, opcode, reg)) = SYSTEM.iter().find(|(name, _, _)| *name == mnemonic) {
+        require(ctx, Model::I80286, mnemonic)?;
+        return match operands {
+            // The descriptor table instructions only take memory
+            [operand] if is_mem(operand) || ((*opcode == 0x00 || *reg >= 4) && is_reg(operand)) => with_modrm(&[0x0F, *opcode], *reg, operand),
+            _ => Err(invalid())
+        };
+    }
+
+    match (mnemonic, operands) {
+        (_, [operand]) if UNARY.contains(&mnemonic) && operand.is_rm() => {
+            let size = size_of(&[operand])?;
+            with_modrm(&[0xF6 + w(size)], UNARY.iter().position(|name| *name == mnemonic).unwrap() as u8 + 2, operand)
+        }
+        ("imul", [dst, src, Operand { kind: Kind::Imm(value), .. }]) if matches!(dst.kind, Kind::Reg16(_)) && src.is_rm() => {
+            require(ctx, Model::I80186, "imul with an immediate")?;
+            let reg = reg_index(dst).unwrap();
+            if value.fits_i8() {
+                Ok([with_modrm(&[0x6B], reg, src)?, imm(*value, Size::Byte)].concat())
+            } else {
+                Ok([with_modrm(&[0x69], reg, src)?, imm(*value, Size::Word)].concat())
+            }
+        }
+        ("imul", [dst, value @ Operand { kind: Kind::Imm(_), .. }]) => encode("imul", &[dst.clone(), dst.clone(), value.clone()], ctx),
+        ("mov", [dst, src]) => mov(dst, src),
+        ("test", [dst, src]) => test(dst, src),
+        ("xchg", [dst, src]) => xchg(dst, src),
+        ("inc" | "dec", [operand]) => {
+            let reg = if mnemonic == "inc" { 0 } else { 1 };
+            match operand.kind {
+                Kind::Reg16(index) => Ok(vec![0x40 + reg * 8 + index]),
+                _ if operand.is_rm() => with_modrm(&[0xFE + w(size_of(&[operand])?)], reg, operand),
+                _ => Err(invalid())
+            }
+        }
+        ("push", [operand]) => match operand.kind {
+            Kind::Reg16(index) => Ok(vec![0x50 + index]),
+            Kind::Seg(index) => Ok(vec![0x06 + index * 8]),
+            Kind::Mem(_) if operand.size != Some(Size::Byte) => with_modrm(&[0xFF], 6, operand),
+            Kind::Imm(value) => {
+                require(ctx, Model::I80186, "push with an immediate")?;
+                if value.fits_i8() && operand.size != Some(Size::Word) {
+                    Ok(vec![0x6A, value.value as u8])
+                } else {
+                    Ok([vec![0x68], imm(value, Size::Word)].concat())
+                }
+            }
+            _ => Err(invalid())
+        },
+        ("pop", [operand]) => match operand.kind {
+            Kind::Reg16(index) => Ok(vec![0x58 + index]),
+            // Only the 8086 has pop cs, the 186 reuses its opcode
+            Kind::Seg(1) if ctx.cpu != Model::I8088 => Err(format!("pop cs is not supported on the {}", ctx.cpu)),
+            Kind::Seg(index) => Ok(vec![0x07 + index * 8]),
+            Kind::Mem(_) if operand.size != Some(Size::Byte) => with_modrm(&[0x8F], 0, operand),
+            _ => Err(invalid())
+        },
+        ("lea" | "les" | "lds" | "bound", [Operand { kind: Kind::Reg16(reg), .. }, src]) if is_mem(src) => {
+            let opcode = match mnemonic {
+                "lea" => 0x8D,
+                "les" => 0xC4,
+                "lds" => 0xC5,
+                _ => {
+                    require(ctx, Model::I80186, mnemonic)?;
+                    0x62
+                }
+            };
+            with_modrm(&[opcode], *reg, src)
+        }
+        ("lar" | "lsl", [Operand { kind: Kind::Reg16(reg), .. }, src]) if src.is_rm() => {
+            require(ctx, Model::I80286, mnemonic)?;
+            with_modrm(&[0x0F, if mnemonic == "lar" { 0x02 } else { 0x03 }], *reg, src)
+        }
+        ("arpl", [dst, Operand { kind: Kind::Reg16(reg), .. }]) if dst.is_rm() => {
+            require(ctx, Model::I80286, mnemonic)?;
+            with_modrm(&[0x63], *reg, dst)
+        }
+        ("jmp", [operand]) => jump(operand, ctx),
+        ("call", [operand]) => call(operand, ctx),
+        ("ret" | "retn", []) => Ok(vec![0xC3]),
+        ("ret" | "retn", [Operand { kind: Kind::Imm(value), .. }]) => Ok([vec![0xC2], imm(*value, Size::Word)].concat()),
+        ("retf", []) => Ok(vec![0xCB]),
+        ("retf", [Operand { kind: Kind::Imm(value), .. }]) => Ok([vec![0xCA], imm(*value, Size::Word)].concat()),
+        ("int", [Operand { kind: Kind::Imm(value), .. }]) => Ok(vec![0xCD, value.value as u8]),
+        ("in", [data, port]) => io(mnemonic, port, data),
+        ("out", [port, data]) => io(mnemonic, port, data),
+        ("enter", [Operand { kind: Kind::Imm(frame), .. }, Operand { kind: Kind::Imm(level), .. }]) => {
+            require(ctx, Model::I80186, mnemonic)?;
+            Ok([vec![0xC8], imm(*frame, Size::Word), imm(*level, Size::Byte)].concat())
+        }
+        ("aam" | "aad", []) => Ok(vec![if mnemonic == "aam" { 0xD4 } else { 0xD5 }, 0x0A]),
+        ("aam" | "aad", [Operand { kind: Kind::Imm(base), .. }]) => Ok(vec![if mnemonic == "aam" { 0xD4 } else { 0xD5 }, base.value as u8]),
+        _ if is_mnemonic(mnemonic) => Err(invalid()),
+        _ => Err(format!("unknown instruction {}", mnemonic))
+    }
+}
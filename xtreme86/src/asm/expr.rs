@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+// The result of an expression. `known` is false while it refers to a label that hasn't been
+// placed yet, and `constant` is false when it refers to a label at all, so its size can't be
+// picked from its value without changing between passes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Value {
+    pub value: i64,
+    pub known: bool,
+    pub constant: bool,
+}
+
+impl Value {
+    pub fn constant(value: i64) -> Self {
+        Self { value, known: true, constant: true }
+    }
+
+    fn combine(self, other: Value, value: i64) -> Self {
+        Self { value, known: self.known && other.known, constant: self.constant && other.constant }
+    }
+
+    pub fn fits_i8(self) -> bool {
+        self.constant && (-128..=127).contains(&(self.value as i16))
+    }
+}
+
+pub struct Scope<'a> {
+    pub labels: &'a HashMap<String, i64>,
+    // Address of the current line ($) and of the start of the section ($$)
+    pub here: i64,
+    pub start: i64,
+    // Prefix for labels that start with a dot
+    pub global: &'a str,
+}
+
+impl<'a> Scope<'a> {
+    pub fn qualify(&self, name: &str) -> String {
+        if name.starts_with('.') && !name.starts_with("..") {
+            format!("{}{}", self.global, name)
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Here,
+    Start,
+    Op(&'static str),
+    Open,
+    Close
+}
+
+const OPERATORS: [&str; 13] = ["<<", ">>", "//", "%%", "+", "-", "*", "/", "%", "&", "|", "^", "~"];
+
+pub fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.' || c == '?' || c == '@'
+}
+
+pub fn is_name_char(c: char) -> bool {
+    is_name_start(c) || c.is_ascii_digit() || c == '$' || c == '#'
+}
+
+pub fn parse_number(text: &str) -> Option<i64> {
+    let text = text.to_lowercase().replace('_', "");
+    let digits = |text: &str, radix: u32| if text.is_empty() { None } else { i64::from_str_radix(text, radix).ok() };
+
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0h")).or_else(|| text.strip_prefix('$')) {
+        digits(hex, 16)
+    } else if let Some(hex) = text.strip_suffix('h') {
+        digits(hex, 16)
+    } else if let Some(bin) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0y")) {
+        digits(bin, 2)
+    } else if let Some(oct) = text.strip_prefix("0o").or_else(|| text.strip_prefix("0q")) {
+        digits(oct, 8)
+    } else if let Some(dec) = text.strip_prefix("0d") {
+        digits(dec, 10)
+    } else if let Some(bin) = text.strip_suffix('b').or_else(|| text.strip_suffix('y')) {
+        digits(bin, 2)
+    } else if let Some(oct) = text.strip_suffix('q').or_else(|| text.strip_suffix('o')) {
+        digits(oct, 8)
+    } else if let Some(dec) = text.strip_suffix('d') {
+        digits(dec, 10)
+    } else {
+        digits(&text, 10)
+    }
+}
+
+// Reads a quoted string starting at the opening quote. Returns its bytes and the number of
+// characters consumed. Backquoted strings understand C-style escapes, like in NASM.
+pub fn parse_string(text: &str) -> Result<(Vec<u8>, usize), String> {
+    let mut chars = text.char_indices();
+    let quote = chars.next().map(|(_, c)| c).ok_or("expected a string")?;
+    let mut bytes = Vec::new();
+    let mut escaped = false;
+    for (index, c) in chars {
+        if escaped {
+            bytes.push(match c {
+                'n' => b'\n',
+                'r' => b'\r',
+                't' => b'\t',
+                '0' => 0,
+                'e' => 0x1B,
+                _ => c as u8
+            });
+            escaped = false;
+        } else if c == quote {
+            return Ok((bytes, index + 1));
+        } else if c == '\\' && quote == '`' {
+            escaped = true;
+        } else {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+        }
+    }
+    Err(format!("unterminated string {}", text))
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = rest.trim_start();
+            continue;
+        }
+        if c == '\'' || c == '"' || c == '`' {
+            // Character constants are stored little endian, like NASM does
+            let (bytes, length) = parse_string(rest)?;
+            if bytes.len() > 8 {
+                return Err(format!("character constant {} is too long", &rest[..length]));
+            }
+            tokens.push(Token::Number(bytes.iter().rev().fold(0, |value, byte| (value << 8) | *byte as i64)));
+            rest = &rest[length..];
+        } else if rest.starts_with("$$") {
+            tokens.push(Token::Start);
+            rest = &rest[2..];
+        } else if c.is_ascii_digit() || (c == '$' && rest[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            let length = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$')).unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..length]).ok_or_else(|| format!("invalid number {}", &rest[..length]))?));
+            rest = &rest[length..];
+        } else if c == '$' && !rest[1..].starts_with(is_name_start) {
+            tokens.push(Token::Here);
+            rest = &rest[1..];
+        } else if is_name_start(c) || c == '$' {
+            // A leading $ marks a name that would otherwise be a keyword
+            let name = rest.strip_prefix('$').unwrap_or(rest);
+            let length = name.find(|c: char| !is_name_char(c)).unwrap_or(name.len());
+            tokens.push(Token::Name(name[..length].to_string()));
+            rest = &name[length..];
+        } else if c == '(' {
+            tokens.push(Token::Open);
+            rest = &rest[1..];
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            rest = &rest[1..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("unexpected character '{}' in expression", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a, 'b> {
+    tokens: Vec<Token>,
+    position: usize,
+    scope: &'a Scope<'b>,
+    // Labels that were used before being defined
+    unknown: Vec<String>,
+}
+
+// Binary operators from the loosest binding to the tightest
+const PRECEDENCE: [&[&str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%", "//", "%%"]];
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Value, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if !PRECEDENCE[level].contains(&op) {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            if (op == "/" || op == "%" || op == "//" || op == "%%") && right.value == 0 {
+                if right.known {
+                    return Err(String::from("division by zero"));
+                }
+                left = left.combine(right, 0);
+                continue;
+            }
+            let value = match op {
+                "|" => left.value | right.value,
+                "^" => left.value ^ right.value,
+                "&" => left.value & right.value,
+                "<<" => left.value.wrapping_shl(right.value as u32),
+                ">>" => ((left.value as u64) >> (right.value as u32 & 63)) as i64,
+                "+" => left.value.wrapping_add(right.value),
+                "-" => left.value.wrapping_sub(right.value),
+                "*" => left.value.wrapping_mul(right.value),
+                "/" => ((left.value as u64) / (right.value as u64)) as i64,
+                "%" => ((left.value as u64) % (right.value as u64)) as i64,
+                "//" => left.value / right.value,
+                _ => left.value % right.value
+            };
+            left = left.combine(right, value);
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Value, String> {
+        match self.next() {
+            Some(Token::Op("-")) => self.unary().map(|value| Value { value: value.value.wrapping_neg(), ..value }),
+            Some(Token::Op("+")) => self.unary(),
+            Some(Token::Op("~")) => self.unary().map(|value| Value { value: !value.value, ..value }),
+            Some(Token::Number(value)) => Ok(Value::constant(value)),
+            Some(Token::Here) => Ok(Value { value: self.scope.here, known: true, constant: false }),
+            Some(Token::Start) => Ok(Value { value: self.scope.start, known: true, constant: false }),
+            Some(Token::Name(name)) => {
+                let name = self.scope.qualify(&name);
+                match self.scope.labels.get(&name) {
+                    Some(value) => Ok(Value { value: *value, known: true, constant: false }),
+                    None => {
+                        self.unknown.push(name);
+                        Ok(Value { value: 0, known: false, constant: false })
+                    }
+                }
+            }
+            Some(Token::Open) => {
+                let value = self.binary(0)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(value),
+                    _ => Err(String::from("expected ')'"))
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?} in expression", token)),
+            None => Err(String::from("expected an expression"))
+        }
+    }
+}
+
+// Evaluates `text`. Labels that aren't in the scope evaluate to 0 and are returned so the
+// caller can tell a forward reference from a typo.
+pub fn evaluate(text: &str, scope: &Scope) -> Result<(Value, Vec<String>), String> {
+    let mut parser = Parser { tokens: tokenize(text)?, position: 0, scope, unknown: Vec::new() };
+    let value = parser.binary(0)?;
+    if parser.position < parser.tokens.len() {
+        return Err(format!("unexpected {:?} in expression", parser.tokens[parser.position]));
+    }
+    Ok((value, parser.unknown))
+}
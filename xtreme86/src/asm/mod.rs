@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::cpu::Model;
+use crate::cpu::instruction::args::Size;
+use crate::asm::encode::{encode, is_mnemonic, Context};
+use crate::asm::expr::{evaluate, is_name_char, is_name_start, parse_string, Scope, Value};
+use crate::asm::operand::{parse, segment_prefix, split_top_level};
+use crate::symbols::SymbolTable;
+
+pub mod expr;
+pub mod operand;
+pub mod encode;
+
+// Label addresses can move every time a jump grows, so give up if they don't settle
+const MAX_PASSES: usize = 32;
+
+const PREFIXES: [(&str, u8); 6] = [("rep", 0xF3), ("repe", 0xF3), ("repz", 0xF3), ("repne", 0xF2), ("repnz", 0xF2), ("lock", 0xF0)];
+
+const DIRECTIVES: [&str; 14] = ["org", "cpu", "bits", "section", "segment", "global", "db", "dw", "dd", "resb", "resw", "resd", "times", "align"];
+
+// The argument of a CPU directive. Later CPUs are accepted, but only 286 instructions are known.
+fn cpu_model(name: &str) -> Option<Model> {
+    match name.to_lowercase().as_str() {
+        "386" | "486" | "586" | "686" | "any" | "all" => Some(Model::I80286),
+        name => Model::from_name(name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Assembly {
+    pub bytes: Vec<u8>,
+    // The address of the first byte, from ORG
+    pub origin: u32,
+    pub labels: BTreeMap<String, u32>,
+}
+
+impl Assembly {
+    pub fn label(&self, name: &str) -> Option<u32> {
+        self.labels.get(name).copied()
+    }
+
+    // The labels as offsets into `segment`, for a CPU that runs the code there
+    pub fn symbols(&self, segment: u16) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        for (name, address) in self.labels.iter() {
+            table.insert(name, segment, *address as u16);
+        }
+        table
+    }
+}
+
+// Assembles NASM-style 16-bit code into a flat binary, like `nasm -f bin`
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    Assembler::new().assemble(source).map(|assembly| assembly.bytes)
+}
+
+pub struct Assembler {
+    cpu: Model,
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Pass<'a> {
+    labels: &'a mut HashMap<String, i64>,
+    // Lines with a jump that had to be made near
+    near: &'a mut HashSet<usize>,
+    defined: HashSet<String>,
+    unknown: Vec<(String, usize)>,
+    output: Vec<u8>,
+    origin: i64,
+    cpu: Model,
+    global: String,
+    changed: bool,
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim_start()),
+        None => (text, "")
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, ';') => return &line[..index],
+            _ => ()
+        }
+    }
+    line
+}
+
+fn is_keyword(word: &str) -> bool {
+    let word = word.to_lowercase();
+    is_mnemonic(&word) || DIRECTIVES.contains(&word.as_str()) || PREFIXES.iter().any(|(prefix, _)| *prefix == word)
+        || segment_prefix(&word).is_some() || word == "equ"
+}
+
+fn is_name(text: &str) -> bool {
+    text.starts_with(is_name_start) && text.chars().all(is_name_char)
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self { cpu: Model::I80286 }
+    }
+
+    // Starts as if the source began with a CPU directive
+    pub fn with_cpu(cpu: Model) -> Self {
+        Self { cpu }
+    }
+
+    pub fn assemble(&self, source: &str) -> Result<Assembly, String> {
+        let mut labels = HashMap::new();
+        let mut near = HashSet::new();
+
+        for _ in 0..MAX_PASSES {
+            let grown = near.len();
+            let mut pass = Pass {
+                labels: &mut labels,
+                near: &mut near,
+                defined: HashSet::new(),
+                unknown: Vec::new(),
+                output: Vec::new(),
+                origin: 0,
+                cpu: self.cpu,
+                global: String::new(),
+                changed: false,
+            };
+            for (index, line) in source.lines().enumerate() {
+                pass.line(line, index + 1).map_err(|message| format!("line {}: {}", index + 1, message))?;
+            }
+
+            if let Some((name, line)) = pass.unknown.iter().find(|(name, _)| !pass.defined.contains(name)) {
+                return Err(format!("line {}: symbol {} is not defined", line, name));
+            }
+            if pass.unknown.is_empty() && !pass.changed && pass.near.len() == grown {
+                let origin = pass.origin as u32;
+                let bytes = pass.output;
+                let labels = labels.into_iter().map(|(name, value)| (name, value as u32)).collect();
+                return Ok(Assembly { bytes, origin, labels });
+            }
+        }
+        Err(String::from("label addresses didn't settle"))
+    }
+}
+
+impl<'a> Pass<'a> {
+    fn here(&self) -> i64 {
+        self.origin + self.output.len() as i64
+    }
+
+    fn evaluate(&mut self, text: &str, number: usize) -> Result<Value, String> {
+        let scope = Scope { labels: self.labels, here: self.here(), start: self.origin, global: &self.global };
+        let (value, unknown) = evaluate(text, &scope)?;
+        self.unknown.extend(unknown.into_iter().map(|name| (name, number)));
+        Ok(value)
+    }
+
+    // Expressions that decide how much output there is can't wait for a later pass
+    fn evaluate_now(&mut self, text: &str, number: usize) -> Result<i64, String> {
+        let value = self.evaluate(text, number)?;
+        if !value.known {
+            return Err(format!("{} must be known when it is used", text.trim()));
+        }
+        Ok(value.value)
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), String> {
+        let name = if name.starts_with('.') {
+            format!("{}{}", self.global, name)
+        } else {
+            self.global = name.to_string();
+            name.to_string()
+        };
+        if !self.defined.insert(name.clone()) {
+            return Err(format!("label {} is defined more than once", name));
+        }
+        if self.labels.insert(name, value) != Some(value) {
+            self.changed = true;
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, line: &str, number: usize) -> Result<(), String> {
+        let mut text = strip_comment(line).trim();
+        // NASM's primitive directives can be written in brackets, e.g. [org 0x100]
+        if text.starts_with('[') && text.ends_with(']') && is_keyword(split_word(&text[1..]).0) {
+            text = text[1..text.len() - 1].trim();
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let (first, rest) = split_word(text);
+        if let Some((label, after)) = text.split_once(':').filter(|(label, _)| is_name(label.trim()) && segment_prefix(label.trim()).is_none()) {
+            let after = after.trim();
+            if let ("equ", value) = split_word(after) {
+                let value = self.evaluate(value, number)?.value;
+                return self.define(label.trim(), value);
+            }
+            let here = self.here();
+            self.define(label.trim(), here)?;
+            text = after;
+        } else if let ("equ", value) = split_word(rest) {
+            let value = self.evaluate(value, number)?.value;
+            return self.define(first, value);
+        } else if is_name(first) && !is_keyword(first) && (rest.is_empty() || is_keyword(split_word(rest).0)) {
+            // A label without a colon, e.g. `message db 'hi'`
+            let here = self.here();
+            self.define(first, here)?;
+            text = rest;
+        }
+        self.statement(text, number)
+    }
+
+    fn statement(&mut self, text: &str, number: usize) -> Result<(), String> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let (word, rest) = split_word(text);
+        let lower = word.to_lowercase();
+
+        if let Some((_, prefix)) = PREFIXES.iter().find(|(name, _)| *name == lower) {
+            self.output.push(*prefix);
+            return self.statement(rest, number);
+        }
+        if let Some(prefix) = segment_prefix(&lower) {
+            self.output.push(prefix);
+            return self.statement(rest, number);
+        }
+
+        match lower.as_str() {
+            "org" => self.origin = self.evaluate_now(rest, number)?,
+            "cpu" => self.cpu = cpu_model(rest.trim()).ok_or_else(|| format!("unknown CPU {}", rest.trim()))?,
+            "bits" => if self.evaluate_now(rest, number)? != 16 {
+                return Err(String::from("only 16-bit code is supported"));
+            },
+            "section" | "segment" | "global" => (),
+            "db" => self.data(rest, Size::Byte, number)?,
+            "dw" => self.data(rest, Size::Word, number)?,
+            "dd" => self.data(rest, Size::DWord, number)?,
+            "resb" | "resw" | "resd" => {
+                let unit = match lower.as_str() { "resb" => 1, "resw" => 2, _ => 4 };
+                let count = self.evaluate_now(rest, number)?;
+                self.output.extend(vec![0; (count * unit).max(0) as usize]);
+            }
+            "align" => {
+                let alignment = self.evaluate_now(rest, number)?.max(1);
+                while self.here() % alignment != 0 {
+                    self.output.push(0x90);
+                }
+            }
+            "times" => {
+                // The count runs up to the instruction it repeats
+                let words: Vec<&str> = rest.split_whitespace().collect();
+                let split = words.iter().position(|word| is_keyword(word)).ok_or("times needs something to repeat")?;
+                let start = rest.find(words[split]).unwrap();
+                let count = self.evaluate_now(&rest[..start], number)?;
+                for _ in 0..count.max(0) {
+                    self.statement(&rest[start..], number)?;
+                }
+            }
+            _ => {
+                let scope = Scope { labels: self.labels, here: self.here(), start: self.origin, global: &self.global };
+                let mut unknown = Vec::new();
+                let operands = if rest.is_empty() {
+                    Vec::new()
+                } else {
+                    split_top_level(rest, ',').iter().map(|operand| parse(operand, &scope, &mut unknown)).collect::<Result<_, _>>()?
+                };
+                self.unknown.extend(unknown.into_iter().map(|name| (name, number)));
+
+                let mut ctx = Context { here: self.here(), cpu: self.cpu, near: self.near.contains(&number), grow: false };
+                let bytes = encode(word, &operands, &mut ctx)?;
+                if ctx.grow {
+                    self.near.insert(number);
+                }
+                self.output.extend(bytes);
+            }
+        }
+        Ok(())
+    }
+
+    fn data(&mut self, items: &str, size: Size, number: usize) -> Result<(), String> {
+        let width = match size { Size::Byte => 1, Size::Word => 2, Size::DWord => 4 };
+        for item in split_top_level(items, ',') {
+            let item = item.trim();
+            let is_string = item.starts_with(['\'', '"', '`']) && parse_string(item).is_ok_and(|(_, length)| length == item.len());
+            if is_string {
+                // Strings are padded to a whole number of units
+                let (mut bytes, _) = parse_string(item)?;
+                while bytes.len() % width != 0 {
+                    bytes.push(0);
+                }
+                self.output.extend(bytes);
+            } else if item == "?" {
+                self.output.extend(vec![0; width]);
+            } else {
+                let value = self.evaluate(item, number)?.value;
+                self.output.extend_from_slice(&value.to_le_bytes()[..width]);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use crate::cpu::instruction::args::Size;
+use crate::asm::expr::{evaluate, Scope, Value};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Distance {
+    Short,
+    Near,
+    Far
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Memory {
+    // The ModR/M rm field, None for a direct address
+    pub rm: Option<u8>,
+    pub displacement: Option<Value>,
+    // Prefix byte of a segment override
+    pub segment: Option<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    Reg8(u8),
+    Reg16(u8),
+    Seg(u8),
+    Mem(Memory),
+    Imm(Value),
+    // seg:offset of a far jump or call
+    Far(Value, Value)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operand {
+    pub kind: Kind,
+    // From a byte/word/dword keyword
+    pub size: Option<Size>,
+    pub distance: Option<Distance>,
+}
+
+const REG8: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+const REG16: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+const SEG: [&str; 4] = ["es", "cs", "ss", "ds"];
+
+pub fn segment_prefix(name: &str) -> Option<u8> {
+    SEG.iter().position(|seg| name.eq_ignore_ascii_case(seg)).map(|index| 0x26 + (index as u8) * 8)
+}
+
+// Splits on `separator` where it isn't inside brackets, parentheses or quotes
+pub fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[' | '(') => depth += 1,
+            (None, ']' | ')') => depth -= 1,
+            (None, _) if c == separator && depth == 0 => {
+                parts.push(&text[start..index]);
+                start = index + 1;
+            }
+            _ => ()
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+// Strips a keyword followed by whitespace (or a bracket) off the front of `text`
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = text.get(..keyword.len()).filter(|start| start.eq_ignore_ascii_case(keyword)).map(|_| &text[keyword.len()..])?;
+    if rest.starts_with(|c: char| c.is_whitespace() || c == '[') {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+fn value(text: &str, scope: &Scope, unknown: &mut Vec<String>) -> Result<Value, String> {
+    let (value, names) = evaluate(text, scope)?;
+    unknown.extend(names);
+    Ok(value)
+}
+
+fn memory(inner: &str, segment: Option<u8>, scope: &Scope, unknown: &mut Vec<String>) -> Result<Memory, String> {
+    let mut segment = segment;
+    let mut inner = inner.trim();
+    if let Some((seg, rest)) = inner.split_once(':') {
+        segment = Some(segment_prefix(seg.trim()).ok_or_else(|| format!("invalid segment register {}", seg.trim()))?);
+        inner = rest.trim();
+    }
+
+    // Pick out base and index registers from the terms and leave the rest as the displacement
+    let mut bases = Vec::new();
+    let mut displacement = String::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut terms = Vec::new();
+    for (index, c) in inner.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '+' | '-' if depth == 0 && index > 0 => {
+                terms.push(&inner[start..index]);
+                start = index;
+            }
+            _ => ()
+        }
+    }
+    terms.push(&inner[start..]);
+
+    for term in terms {
+        let (sign, name) = match term.trim() {
+            term if term.starts_with('+') => ("+", term[1..].trim()),
+            term if term.starts_with('-') => ("-", term[1..].trim()),
+            term => ("+", term)
+        };
+        match name.to_lowercase().as_str() {
+            "bx" | "bp" | "si" | "di" => {
+                if sign == "-" {
+                    return Err(format!("can't subtract register {}", name));
+                }
+                bases.push(name.to_lowercase());
+            }
+            _ => {
+                displacement += sign;
+                displacement += name;
+            }
+        }
+    }
+
+    bases.sort();
+    let rm = match bases.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        [] => None,
+        ["bx", "si"] => Some(0),
+        ["bx", "di"] => Some(1),
+        ["bp", "si"] => Some(2),
+        ["bp", "di"] => Some(3),
+        ["si"] => Some(4),
+        ["di"] => Some(5),
+        ["bp"] => Some(6),
+        ["bx"] => Some(7),
+        _ => return Err(format!("invalid effective address [{}]", inner))
+    };
+    let displacement = if displacement.is_empty() { None } else { Some(value(&displacement, scope, unknown)?) };
+    if rm.is_none() && displacement.is_none() {
+        return Err(String::from("empty effective address"));
+    }
+    Ok(Memory { rm, displacement, segment })
+}
+
+pub fn parse(text: &str, scope: &Scope, unknown: &mut Vec<String>) -> Result<Operand, String> {
+    let mut text = text.trim();
+    let mut size = None;
+    let mut distance = None;
+    loop {
+        if let Some(rest) = strip_keyword(text, "byte") {
+            size = Some(Size::Byte);
+            text = rest;
+        } else if let Some(rest) = strip_keyword(text, "word") {
+            size = Some(Size::Word);
+            text = rest;
+        } else if let Some(rest) = strip_keyword(text, "dword") {
+            size = Some(Size::DWord);
+            text = rest;
+        } else if let Some(rest) = strip_keyword(text, "short") {
+            distance = Some(Distance::Short);
+            text = rest;
+        } else if let Some(rest) = strip_keyword(text, "near") {
+            distance = Some(Distance::Near);
+            text = rest;
+        } else if let Some(rest) = strip_keyword(text, "far") {
+            distance = Some(Distance::Far);
+            text = rest;
+        } else {
+            break;
+        }
+    }
+
+    let lower = text.to_lowercase();
+    let kind = if let Some(index) = REG8.iter().position(|reg| *reg == lower) {
+        Kind::Reg8(index as u8)
+    } else if let Some(index) = REG16.iter().position(|reg| *reg == lower) {
+        Kind::Reg16(index as u8)
+    } else if let Some(index) = SEG.iter().position(|reg| *reg == lower) {
+        Kind::Seg(index as u8)
+    } else if text.starts_with('[') && text.ends_with(']') {
+        Kind::Mem(memory(&text[1..text.len() - 1], None, scope, unknown)?)
+    } else if let Some((seg, rest)) = text.split_once(':').filter(|(seg, rest)| segment_prefix(seg.trim()).is_some() && rest.trim().starts_with('[')) {
+        // es:[di] as well as [es:di]
+        let rest = rest.trim();
+        if !rest.ends_with(']') {
+            return Err(format!("expected ']' in {}", text));
+        }
+        Kind::Mem(memory(&rest[1..rest.len() - 1], segment_prefix(seg.trim()), scope, unknown)?)
+    } else {
+        match split_top_level(text, ':').as_slice() {
+            [segment, offset] => Kind::Far(value(segment, scope, unknown)?, value(offset, scope, unknown)?),
+            _ => Kind::Imm(value(text, scope, unknown)?)
+        }
+    };
+
+    Ok(Operand { kind, size, distance })
+}
+
+impl Operand {
+    // The size given by a register, or by a size keyword
+    pub fn size(&self) -> Option<Size> {
+        match self.kind {
+            Kind::Reg8(_) => Some(Size::Byte),
+            Kind::Reg16(_) | Kind::Seg(_) => Some(Size::Word),
+            _ => self.size
+        }
+    }
+
+    pub fn is_rm(&self) -> bool {
+        matches!(self.kind, Kind::Reg8(_) | Kind::Reg16(_) | Kind::Mem(_))
+    }
+}
@@ -1,19 +1,36 @@
 mod reg;
-mod instruction;
-
-use std::collections::HashMap;
+pub mod instruction;
+pub mod profiler;
+pub mod backtrace;
+pub mod trace;
+pub mod registers;
+pub mod model;
+pub mod prefetch;
+pub mod timing;
+pub mod com;
+
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug};
+use std::path::Path;
 use crate::cpu::instruction::actions::{int, alu};
-use crate::cpu::instruction::{InstructionDecoder};
+use crate::cpu::instruction::{InstructionDecoder, DecodeError};
 use crate::cpu::instruction::args::{SrcArg, DstArg, Size};
 use crate::cpu::instruction::opcode::OpcodeFlags;
 use crate::peripheral::Peripheral;
+use crate::cpu::profiler::{Profiler, CallKind, ReturnKind};
+use crate::cpu::backtrace::{ShadowStack, Frame};
+use crate::cpu::trace::{Trace, TraceEntry};
+use crate::cpu::prefetch::PrefetchQueue;
+use crate::symbols::SymbolTable;
+
+pub use crate::cpu::registers::{Reg8, Reg16, SegReg, Flag, Flags, Registers, RegisterDiff};
+pub use crate::cpu::model::Model;
 
 pub struct CPUFlags ;
 
 impl CPUFlags {
     pub const CARRY: u16 = 0x0001;
-    pub const PARITY: u16 = 0x0040;
+    pub const PARITY: u16 = 0x0004;
     pub const AUX_CARRY: u16 = 0x0010;
     pub const ZERO: u16 = 0x0040;
     pub const SIGN: u16 = 0x0080;
@@ -32,7 +49,10 @@ pub mod exceptions {
     pub const BOUND: u8 = 0x05;
     pub const INVALID_OPCODE: u8 = 0x06;
     pub const NO_EXTENSION: u8 = 0x07;
-    pub const IVT_TOO_SMALL: u8 = 0x08;
+    // Also raised for a vector past the limit of the interrupt table
+    pub const DOUBLE_FAULT: u8 = 0x08;
+    #[deprecated(note = "use DOUBLE_FAULT")]
+    pub const IVT_TOO_SMALL: u8 = DOUBLE_FAULT;
 }
 
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
@@ -74,35 +94,16 @@ impl Regs {
     }
 
     fn id_8_bit_to_text(id: u8) -> String {
-        String::from(match id {
-            0 => "AL",
-            1 => "CL",
-            2 => "DL",
-            3 => "BL",
-            4 => "AH",
-            5 => "CH",
-            6 => "DH",
-            7 => "BH",
-            _ => ""
-        })
+        Reg8::from_index(id).map_or(String::new(), |reg| reg.to_string())
     }
 
     fn translate_reg16(num: u8) -> Option<Self> {
-        match num {
-            0 => Some(Regs::AX),
-            1 => Some(Regs::CX),
-            2 => Some(Regs::DX),
-            3 => Some(Regs::BX),
-            4 => Some(Regs::SP),
-            5 => Some(Regs::BP),
-            6 => Some(Regs::SI),
-            7 => Some(Regs::DI),
-            _ => None
-        }
+        Some(Reg16::from_index(num)?.into())
     }
 
     fn translate_reg8(num: u8) -> Option<(Self, WordPart)> {
-        Some((Self::translate_reg16(num % 4)?, if num / 4 == 0 { WordPart::Low } else { WordPart::High }))
+        let reg = Reg8::from_index(num)?;
+        Some((reg.reg16().into(), reg.part()))
     }
 }
 
@@ -115,17 +116,38 @@ pub enum WordPart {
 pub struct CPU {
     ram: Vec<u8>,
     regs: HashMap<Regs, reg::Reg>,
-    opcodes: [Option<instruction::opcode::Opcode>; 256],
+    opcodes: &'static [Option<instruction::opcode::Opcode>; 256],
+    model: Model,
     instruction: Option<instruction::Instruction>,
     next_cycles: usize,
     irq: Option<u8>,
     opcode_address: (u16, u16),
     io_devices: Vec<Box<dyn Peripheral>>,
     io_memory_hooks: HashMap<u16, usize>,
+    profiler: Option<Profiler>,
+    shadow_stack: ShadowStack,
+    symbols: SymbolTable,
+    breakpoints: HashSet<(u16, u16)>,
+    trace: Option<Trace>,
+    decode_error: Option<DecodeError>,
+    prefetch: Option<PrefetchQueue>,
+    cycles: usize,
+    // Base and limit of the interrupt table, only LIDT on the 286 moves it
+    idt: (u32, u16),
+    shutdown: bool,
+    // The vector on the INTR line, taken at an instruction boundary when IF is set
+    intr: Option<u8>,
+    interrupt_shadow: bool,
+    // Where a mapped ROM starts, programs can't write from there to the end of the first megabyte
+    rom_start: Option<usize>,
 }
 
 impl CPU {
     pub fn new(ram_size: usize) -> Self {
+        Self::with_model(ram_size, Model::default())
+    }
+
+    pub fn with_model(ram_size: usize, model: Model) -> Self {
         // Create and allocate the ram
         let ram: Vec<u8> = vec![0; ram_size];
 
@@ -149,41 +171,362 @@ impl CPU {
         Self {
             ram,
             regs,
-            opcodes: instruction::opcode::Opcode::get_opcode_data(),
+            opcodes: instruction::opcode::Opcode::table(),
+            model,
             instruction: None,
             next_cycles: 0,
             irq: None,
             opcode_address: (0, 0),
             io_devices: Vec::new(),
             io_memory_hooks: HashMap::new(),
+            profiler: None,
+            shadow_stack: ShadowStack::new(),
+            symbols: SymbolTable::new(),
+            breakpoints: HashSet::new(),
+            trace: None,
+            decode_error: None,
+            prefetch: None,
+            cycles: 0,
+            idt: (0, 0x3FF),
+            shutdown: false,
+            intr: None,
+            interrupt_shadow: false,
+            rom_start: None,
         }
     }
 
     pub fn step(&mut self) {
+        self.cycles += 1;
+        if self.shutdown {
+            return;
+        }
+        if let Some(queue) = self.prefetch.as_mut() {
+            queue.clock(&self.ram);
+        }
+
         if self.next_cycles > 0 {
             self.next_cycles -= 1;
         } else if let Some(opcode) = self.instruction.clone() {
-            opcode.exec(self);
+            self.interrupt_shadow = opcode.inhibits_interrupts();
+            self.next_cycles += opcode.exec(self);
             self.instruction = None;
+            self.tick_peripherals();
         } else if let Some(_) = self.irq {
             self.next_cycles += int::int(self);
+        } else if self.intr.is_some() && !self.interrupt_shadow && self.check_flag(CPUFlags::INTERRUPT) {
+            self.irq = self.intr.take();
+            // This step is a clock of it as well
+            self.next_cycles += int::int(self) + timing::interrupt_acknowledge(self.model).saturating_sub(1);
         } else {
             let opcode_address =  (self.regs[&Regs::CS].value, self.regs[&Regs::IP].value);
             self.opcode_address = opcode_address;
-            let physical_address = Self::physical_address(opcode_address.0, opcode_address.1) as usize;
-            if let Some(ins) = instruction::InstructionDecoder::new(self.opcodes.clone(), &self.ram[physical_address..]).get() {
-                self.instruction.replace(ins);
-                self.next_cycles += self.instruction.clone().unwrap().next_cycles;
-                self.regs.get_mut(&Regs::IP).unwrap().value += self.instruction.clone().unwrap().length as u16;
-            } else {
-                self.except(exceptions::INVALID_OPCODE).unwrap();
-                self.regs.get_mut(&Regs::IP).unwrap().value += 1;
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.record_instruction(opcode_address);
+            }
+            let decoded = match self.prefetch.as_mut() {
+                Some(queue) => {
+                    // Anything that moved CS:IP without a jump, like the debugger, starts a new queue
+                    if queue.head() != opcode_address {
+                        queue.flush(opcode_address);
+                    }
+                    let code = queue.code(&self.ram);
+                    let decoded = InstructionDecoder::with_model(self.opcodes, &code, self.model).get();
+                    if let Ok(ins) = decoded.as_ref() {
+                        queue.consume(ins.length);
+                        queue.hold(&ins.semantics(opcode_address).memory);
+                    }
+                    decoded
+                }
+                None => {
+                    let physical_address = Self::physical_address(opcode_address.0, opcode_address.1) as usize;
+                    InstructionDecoder::with_model(self.opcodes, &self.ram[physical_address..], self.model).get()
+                }
+            };
+            match decoded {
+                Ok(ins) => {
+                    if let Some(trace) = self.trace.as_mut() {
+                        trace.push(TraceEntry {
+                            address: opcode_address,
+                            symbol: self.symbols.describe(opcode_address.0, opcode_address.1),
+                            text: ins.to_string(),
+                        });
+                    }
+                    // This step and the one that executes the instruction are clocks of it as well
+                    self.next_cycles += ins.next_cycles.saturating_sub(2);
+                    self.instruction.replace(ins);
+                    self.regs.get_mut(&Regs::IP).unwrap().value += self.instruction.clone().unwrap().length as u16;
+                }
+                Err(error) => {
+                    // The return address is the faulting instruction, so a handler can look at it
+                    self.decode_error = Some(error);
+                    self.except(exceptions::INVALID_OPCODE).unwrap();
+                }
+            }
+        }
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record_cycle(self.opcode_address);
+        }
+    }
+
+    fn tick_peripherals(&mut self) {
+        self.each_peripheral(|dev, comp, _| dev.tick(comp));
+    }
+
+    fn each_peripheral(&mut self, mut f: impl FnMut(&mut Box<dyn Peripheral>, &mut CPU, usize)) {
+        if self.io_devices.is_empty() {
+            return;
+        }
+        let mut devices = std::mem::take(&mut self.io_devices);
+        for (index, dev) in devices.iter_mut().enumerate() {
+            f(dev, self, index);
+        }
+        self.io_devices = devices;
+    }
+
+    // What the RESET pin does: the registers are cleared and the CPU starts at the top of the
+    // first megabyte, FFFF:0000 or the 286's F000:FFF0. Only a mapped ROM survives `clear_ram`.
+    pub fn reset(&mut self, clear_ram: bool) {
+        for reg in self.regs.values_mut() {
+            reg.value = 0;
+        }
+        let (cs, ip) = match self.model {
+            Model::I80286 => (0xF000, 0xFFF0),
+            _ => (0xFFFF, 0x0000)
+        };
+        self.regs.get_mut(&Regs::CS).unwrap().value = cs;
+        self.regs.get_mut(&Regs::IP).unwrap().value = ip;
+
+        if clear_ram {
+            let rom = self.rom_start.map_or(self.ram.len()..self.ram.len(), |start| start..0x100000);
+            self.ram[..rom.start].fill(0);
+            self.ram[rom.end..].fill(0);
+        }
+
+        self.clear_execution_state();
+        self.idt = (0, 0x3FF);
+
+        self.each_peripheral(|dev, comp, index| dev.reset(comp, index));
+    }
+
+    // Drops whatever the previous program left half done, so the next step starts fresh at CS:IP
+    fn clear_execution_state(&mut self) {
+        self.instruction = None;
+        self.next_cycles = 0;
+        self.irq = None;
+        self.intr = None;
+        self.interrupt_shadow = false;
+        self.decode_error = None;
+        self.shutdown = false;
+        self.shadow_stack.clear();
+        self.flush_prefetch_queue();
+    }
+
+    // Puts a BIOS image at the top of the first megabyte, so it ends at FFFFF where the CPU
+    // starts after a reset. RAM grows to a megabyte if it's smaller.
+    pub fn map_rom(&mut self, image: &[u8]) -> Result<(), String> {
+        if image.is_empty() || image.len() > 0x10000 {
+            return Err(format!("A ROM has to fit in F0000-FFFFF, this one is {} bytes", image.len()));
+        }
+        if self.ram.len() < 0x100000 {
+            self.ram.resize(0x100000, 0);
+        }
+        let start = 0x100000 - image.len();
+        self.ram[start..0x100000].copy_from_slice(image);
+        self.rom_start = Some(start);
+        Ok(())
+    }
+
+    fn store_byte(&mut self, address: usize, val: u8) {
+        match self.rom_start {
+            Some(start) if (start..0x100000).contains(&address) => (),
+            _ => self.ram[address] = val
+        }
+    }
+
+    // Called once the return address has been pushed and CS:IP points at the target
+    fn trace_call(&mut self, kind: CallKind, return_address: (u16, u16)) {
+        let target = (self.regs[&Regs::CS].value, self.regs[&Regs::IP].value);
+        let discarded = self.shadow_stack.push(Frame {
+            kind,
+            call_site: self.opcode_address,
+            target,
+            return_address,
+            stack: self.stack_address(),
+            routine: None,
+        });
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record_return(discarded);
+            profiler.record_call(kind, target);
+        }
+    }
+
+    // Called once the return has popped, with the SS:SP it popped the return address from
+    fn trace_return(&mut self, kind: ReturnKind, stack: (u16, u16)) {
+        let return_address = (self.regs[&Regs::CS].value, self.regs[&Regs::IP].value);
+        let unwound = self.shadow_stack.pop(kind, stack, return_address);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record_return(unwound);
+        }
+    }
+
+    // SP goes down before the word is written, and both wrap within SS
+    fn push_word(&mut self, val: u16) {
+        let sp = self.regs[&Regs::SP].value.wrapping_sub(2);
+        self.regs.get_mut(&Regs::SP).unwrap().value = sp;
+        self.write_mem_word_seg(sp, Regs::SS, val);
+    }
+
+    fn pop_word(&mut self) -> u16 {
+        let sp = self.regs[&Regs::SP].value;
+        let val = self.read_mem_word_seg(sp, Regs::SS).unwrap();
+        self.regs.get_mut(&Regs::SP).unwrap().value = sp.wrapping_add(2);
+        val
+    }
+
+    fn stack_address(&self) -> (u16, u16) {
+        (self.regs[&Regs::SS].value, self.regs[&Regs::SP].value)
+    }
+
+    pub fn backtrace(&self) -> Vec<Frame> {
+        let mut frames = self.shadow_stack.frames();
+        for frame in frames.iter_mut() {
+            frame.routine = self.symbols.describe(frame.target.0, frame.target.1);
+        }
+        frames
+    }
+
+    pub fn load_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols.extend(symbols);
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn add_breakpoint(&mut self, seg: u16, offset: u16) {
+        self.breakpoints.insert((seg, offset));
+    }
+
+    // Accepts anything SymbolTable::resolve does, e.g. "load_loop", "load_loop+4" or "103F:0010"
+    pub fn add_breakpoint_at(&mut self, location: &str) -> Result<(u16, u16), String> {
+        let address = self.symbols.resolve(location).ok_or(format!("Unknown location: {}", location))?;
+        self.breakpoints.insert(address);
+        Ok(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, seg: u16, offset: u16) -> bool {
+        self.breakpoints.remove(&(seg, offset))
+    }
+
+    // Runs until CS:IP reaches a breakpoint on an instruction boundary, executing at least one
+    // instruction first. Returns None if max_instructions ran out before that.
+    pub fn run_to_breakpoint(&mut self, max_instructions: usize) -> Option<(u16, u16)> {
+        for _ in 0..max_instructions {
+            self.execute_next();
+            let address = (self.regs[&Regs::CS].value, self.regs[&Regs::IP].value);
+            if self.irq.is_none() && self.breakpoints.contains(&address) {
+                return Some(address);
             }
         }
+        None
+    }
+
+    pub fn enable_trace(&mut self) {
+        self.enable_trace_with_capacity(Trace::DEFAULT_CAPACITY);
+    }
+
+    // Keeps only the last `capacity` instructions, so a long run doesn't use up memory
+    pub fn enable_trace_with_capacity(&mut self, capacity: usize) {
+        if self.trace.is_none() {
+            self.trace = Some(Trace::with_capacity(capacity));
+        }
+    }
+
+    pub fn take_trace(&mut self) -> Vec<TraceEntry> {
+        self.trace.take().map(Trace::into_entries).unwrap_or_default()
+    }
+
+    // Decodes through a model of the prefetch queue instead of straight from memory
+    pub fn enable_prefetch_queue(&mut self) {
+        if self.prefetch.is_none() {
+            let address = (self.regs[&Regs::CS].value, self.regs[&Regs::IP].value);
+            let mut queue = PrefetchQueue::for_model(self.model);
+            queue.flush(address);
+            self.prefetch = Some(queue);
+        }
+    }
+
+    pub fn prefetch_queue(&self) -> Option<&PrefetchQueue> {
+        self.prefetch.as_ref()
+    }
+
+    // Called by every jump, call, return and interrupt once CS:IP points at the target
+    fn flush_prefetch_queue(&mut self) {
+        let address = (self.regs[&Regs::CS].value, self.regs[&Regs::IP].value);
+        if let Some(queue) = self.prefetch.as_mut() {
+            queue.flush(address);
+        }
+    }
+
+    pub fn interrupt_table(&self) -> (u32, u16) {
+        self.idt
+    }
+
+    // Set after a fault while delivering a double fault. The CPU does nothing until the embedder
+    // notices, like the chipset of an AT would, and recovers it.
+    pub fn is_shut_down(&self) -> bool {
+        self.shutdown
+    }
+
+    // What a reset does to the state shutdown depends on, the registers are left to the embedder
+    pub fn recover_from_shutdown(&mut self) {
+        self.shutdown = false;
+        self.idt = (0, 0x3FF);
+    }
+
+    fn shut_down(&mut self) {
+        self.shutdown = true;
+        self.instruction = None;
+        self.next_cycles = 0;
+        self.irq = None;
+    }
+
+    // Where the vector of interrupt `num` is, None if it's past the limit of the interrupt table
+    fn vector_address(&self, num: u8) -> Option<u32> {
+        let offset = num as u32 * 4;
+        if self.model >= Model::I80286 && offset + 3 > self.idt.1 as u32 {
+            None
+        } else {
+            Some(self.idt.0 + offset)
+        }
+    }
+
+    // Nothing drives the bus past the end of RAM, so it reads as all ones
+    fn read_physical_word(&mut self, address: u32) -> u16 {
+        self.next_cycles += timing::word_penalty(self.model, address);
+        let byte = |address: u32| self.ram.get(address as usize).copied().unwrap_or(0xFF) as u16;
+        byte(address) | (byte(address + 1) << 8)
+    }
+
+    // Clocks run since the CPU was created
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    // Why the last invalid opcode exception was raised
+    pub fn decode_error(&self) -> Option<DecodeError> {
+        self.decode_error
     }
 
     fn except(&mut self, code: u8) -> Result<(), String> {
         match code {
+            // The 8086 and 8088 push the address of the instruction after the divide
+            exceptions::DIVIDE_BY_ZERO if self.model <= Model::I8086 => (),
             exceptions::DIVIDE_BY_ZERO | exceptions::BOUND | exceptions::INVALID_OPCODE | exceptions::NO_EXTENSION => {
                 let opcode_start = self.opcode_address;
                 self.regs.get_mut(&Regs::CS).unwrap().value = opcode_start.0;
@@ -217,56 +560,59 @@ impl CPU {
         }
     }
 
+    // The physical address of seg:ptr, if RAM backs it
+    fn backed_address(&self, seg: Regs, ptr: u16) -> Option<usize> {
+        let address = Self::physical_address(self.read_reg(seg).unwrap(), ptr) as usize;
+        if address < self.ram.len() { Some(address) } else { None }
+    }
+
     fn read_mem_byte_mut(&mut self, ptr: u16) -> Option<u8> {
-        if ptr > self.ram.len() as u16 {
-            None
-        } else {
-            self.next_cycles += 1;
-            Some(self.ram[Self::physical_address(self.read_reg(self.instruction.clone().unwrap().segment).unwrap(), ptr) as usize])
-        }
+        let address = self.backed_address(self.instruction.clone().unwrap().segment, ptr)?;
+        Some(self.ram[address])
     }
 
     fn read_mem_word_mut(&mut self, ptr: u16) -> Option<u16> {
-        Some((self.read_mem_byte_mut(ptr)? as u16) | ((self.read_mem_byte_mut(ptr + 1)? as u16) << 8))
+        self.next_cycles += timing::word_penalty(self.model, ptr as u32);
+        Some((self.read_mem_byte_mut(ptr)? as u16) | ((self.read_mem_byte_mut(ptr.wrapping_add(1))? as u16) << 8))
     }
 
     fn read_mem_dword_mut(&mut self, ptr: u16) -> Option<u32> {
-        Some((self.read_mem_word_mut(ptr)? as u32) | ((self.read_mem_word_mut(ptr + 2)? as u32) << 16))
+        Some((self.read_mem_word_mut(ptr)? as u32) | ((self.read_mem_word_mut(ptr.wrapping_add(2))? as u32) << 16))
     }
 
     fn write_mem_byte(&mut self, ptr: u16, val: u8) -> Result<(), &str> {
-        if ptr > self.ram.len() as u16 {
-            Err("Write out of bounds")
-        } else {
-            let seg_val = self.read_reg(self.instruction.clone().unwrap().segment).unwrap();
-            self.ram[Self::physical_address(seg_val, ptr) as usize] = (val & 0xFF) as u8;
-            self.next_cycles += 1;
-            Ok(())
-        }
+        let address = self.backed_address(self.instruction.clone().unwrap().segment, ptr).ok_or("Write out of bounds")?;
+        self.store_byte(address, val);
+        Ok(())
     }
 
     fn write_mem_word(&mut self, ptr: u16, val: u16) -> Result<(), &str> {
+        self.next_cycles += timing::word_penalty(self.model, ptr as u32);
         self.write_mem_byte(ptr, (val & 0x00FF) as u8).unwrap();
-        self.write_mem_byte(ptr + 1, ((val & 0xFF00) >> 8) as u8)
+        self.write_mem_byte(ptr.wrapping_add(1), ((val & 0xFF00) >> 8) as u8)
     }
 
 
     fn write_mem_dword(&mut self, ptr: u16, val: u32) -> Result<(), &str> {
         self.write_mem_word(ptr, (val &0xFFFF) as u16).unwrap();
-        self.write_mem_word(ptr + 2, ((val & 0xFFFF0000) >> 16) as u16)
+        self.write_mem_word(ptr.wrapping_add(2), ((val & 0xFFFF0000) >> 16) as u16)
     }
 
     fn read_mem_byte_seg(&mut self, ptr: u16, seg: Regs) -> Option<u8> {
-        if ptr > self.ram.len() as u16 {
-            None
-        } else {
-            self.next_cycles += 1;
-            Some(self.ram[Self::physical_address(self.read_reg(seg).unwrap(), ptr) as usize])
-        }
+        let address = self.backed_address(seg, ptr)?;
+        Some(self.ram[address])
     }
 
     fn read_mem_word_seg(&mut self, ptr: u16, seg: Regs) -> Option<u16> {
-        Some((self.read_mem_byte_seg(ptr, seg)? as u16) | ((self.read_mem_byte_seg(ptr + 1, seg)? as u16) << 8))
+        self.next_cycles += timing::word_penalty(self.model, ptr as u32);
+        Some((self.read_mem_byte_seg(ptr, seg)? as u16) | ((self.read_mem_byte_seg(ptr.wrapping_add(1), seg)? as u16) << 8))
+    }
+
+    fn write_mem_word_seg(&mut self, ptr: u16, seg: Regs, val: u16) {
+        self.next_cycles += timing::word_penalty(self.model, ptr as u32);
+        let seg_val = self.read_reg(seg).unwrap();
+        self.store_byte(Self::physical_address(seg_val, ptr) as usize, val as u8);
+        self.store_byte(Self::physical_address(seg_val, ptr.wrapping_add(1)) as usize, (val >> 8) as u8);
     }
 
     fn read_io_mem(&mut self, address: u16, size: Size) -> SrcArg {
@@ -397,8 +743,8 @@ impl CPU {
         let instruction = {
             let mut tmp = instruction::Instruction::new();
 
-            let data = InstructionDecoder::get_opcode_from_slice(&self.opcodes, opcode).unwrap();
-            tmp.action = Some(data.action);
+            let data = self.opcodes[opcode as usize].clone().unwrap();
+            tmp.action = data.action_for(reg_bits);
             tmp.src = src;
             tmp.dst = dst;
             tmp.reg_bits = reg_bits;
@@ -423,7 +769,12 @@ impl CPU {
 
 
     fn do_opcode(&mut self, opcode: u8) {
-        let instruction = InstructionDecoder::new(self.opcodes.clone(), self.ram.as_slice()).decode(opcode).unwrap();
+        let mut instruction = InstructionDecoder::new(self.opcodes, self.ram.as_slice()).decode(opcode).unwrap();
+        // The repeated instruction goes through the segment override of the REP
+        if let Some(outer) = &self.instruction {
+            instruction.segment = outer.segment;
+            instruction.segment_override = outer.segment_override;
+        }
 
         let tmp_instruction = self.instruction.clone();
         self.instruction.replace(instruction.clone());
@@ -459,6 +810,9 @@ impl CPU {
         if Self::check_flag_in_reg(flags, CPUFlags::SIGN) {
             self.check_sign(result);
         }
+        if Self::check_flag_in_reg(flags, CPUFlags::PARITY) {
+            self.check_parity(result);
+        }
     }
 
     fn check_flag_in_reg(flags: u16, flag: u16) -> bool {
@@ -469,6 +823,11 @@ impl CPU {
         self.set_flag_if(CPUFlags::ZERO, Self::check_src_arg(result, |val| val == 0, |val| val == 0));
     }
 
+    // Set for an even number of ones in the low byte, even for word results
+    fn check_parity(&mut self, result: &SrcArg) {
+        self.set_flag_if(CPUFlags::PARITY, Self::check_src_arg(result, |val| val.count_ones().is_multiple_of(2), |val| (val as u8).count_ones().is_multiple_of(2)));
+    }
+
     fn check_sign(&mut self, result: &SrcArg) {
         self.set_flag_if(CPUFlags::SIGN, Self::check_src_arg(result, |val| (val & 0x80) != 0, |val| (val & 0x80) != 0));
     }
@@ -546,10 +905,39 @@ impl CPU {
         }
     }
 
+    pub fn enable_profiler(&mut self) {
+        if self.profiler.is_none() {
+            self.profiler = Some(Profiler::new());
+        }
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
     pub fn get_peripheral(&self, dev_index: usize) -> Option<&Box<dyn Peripheral>> {
         self.io_devices.get(dev_index)
     }
 
+    // Raises INTR with the vector an interrupt controller would answer INTA with. It stays pending
+    // while IF is clear and a later request replaces it.
+    pub fn request_interrupt(&mut self, num: u8) {
+        self.intr = Some(num);
+    }
+
+    pub fn pending_interrupt(&self) -> Option<u8> {
+        self.intr
+    }
+
+    // An INT or exception the last instruction raised, taken on the next step
+    pub fn raised_interrupt(&self) -> Option<u8> {
+        self.irq
+    }
+
     pub fn hook_peripheral(&mut self, dev: Box<dyn Peripheral>) -> usize {
         let index = self.io_devices.len();
         dev.init(self, index);
@@ -561,9 +949,15 @@ impl CPU {
         self.io_memory_hooks.insert(address, dev_index);
     }
 
+    // A vector past the end of RAM can't be hooked, it already reads as FFFF:FFFF, a hook
+    // without a peripheral that returns straight away
     pub fn hook_interrupt(&mut self, dev_index: usize, int_num: u8) {
-        self.write_word((int_num as usize) * 4 + 2, 0xFFFF).unwrap();
-        self.write_word((int_num as usize) * 4, dev_index as u16).unwrap();
+        let vector = (self.idt.0 + int_num as u32 * 4) as usize;
+        if vector + 4 > self.ram.len() {
+            return;
+        }
+        self.write_word(vector + 2, 0xFFFF).unwrap();
+        self.write_word(vector, dev_index as u16).unwrap();
     }
 
     pub fn read_reg(&self, reg: Regs) -> Option<u16> {
@@ -589,6 +983,58 @@ impl CPU {
         }
     }
 
+    pub fn read_reg8(&self, reg: Reg8) -> u8 {
+        self.read_reg_part(reg.reg16().into(), reg.part())
+    }
+
+    pub fn set_reg8(&mut self, reg: Reg8, val: u8) {
+        self.set_reg_part(reg.reg16().into(), reg.part(), val)
+    }
+
+    pub fn read_reg16(&self, reg: Reg16) -> u16 {
+        self.regs[&reg.into()].value
+    }
+
+    pub fn set_reg16(&mut self, reg: Reg16, val: u16) {
+        self.set_reg(reg.into(), val)
+    }
+
+    pub fn read_seg(&self, reg: SegReg) -> u16 {
+        self.regs[&reg.into()].value
+    }
+
+    pub fn set_seg(&mut self, reg: SegReg, val: u16) {
+        self.set_reg(reg.into(), val)
+    }
+
+    pub fn flags(&self) -> Flags {
+        Flags::from_bits(self.regs[&Regs::FLAGS].value)
+    }
+
+    // Reserved FLAGS bits are left as they are
+    pub fn set_flags(&mut self, flags: Flags) {
+        let reserved = self.regs[&Regs::FLAGS].value & !Flags::from_bits(0xFFFF).bits();
+        self.set_reg(Regs::FLAGS, reserved | flags.bits())
+    }
+
+    pub fn registers(&self) -> Registers {
+        let mut registers = Registers::default();
+        for reg in Registers::ALL.iter() {
+            registers.set(*reg, self.regs[reg].value);
+        }
+        registers
+    }
+
+    pub fn set_registers(&mut self, registers: &Registers) {
+        for reg in Registers::ALL.iter() {
+            if *reg == Regs::FLAGS {
+                self.set_flags(registers.flags);
+            } else {
+                self.set_reg(*reg, registers.get(*reg));
+            }
+        }
+    }
+
     pub fn probe_mem(&self, loc: usize) -> u8 {
         self.ram[loc]
     }
@@ -640,6 +1086,41 @@ impl CPU {
         }
     }
 
+    // Sets up `segment` the way DOS starts a .COM program: the PSP, the image at 0x100, every
+    // segment register on the PSP, SP at 0xFFFE over a zero word, so RET ends up at int 0x20, and
+    // the other registers and FLAGS cleared. Anything the CPU was in the middle of is dropped.
+    pub fn load_com(&mut self, image: &[u8], segment: u16, command_tail: &str) -> Result<(), String> {
+        let start = Self::physical_address(segment, 0) as usize;
+        if start + 0x10000 > self.ram.len() {
+            return Err(format!("Segment {:04X} doesn't fit in {:X} bytes of RAM", segment, self.ram.len()));
+        }
+        if image.len() > 0x10000 - com::PSP_SIZE - 2 {
+            return Err(format!("A .COM image has to fit in a segment with its stack, this one is {} bytes", image.len()));
+        }
+        let memory_top = (self.ram.len() / 16).min(0xA000) as u16;
+        let psp = com::psp(memory_top, command_tail)?;
+
+        self.ram[start..start + com::PSP_SIZE].copy_from_slice(&psp);
+        self.ram[start + com::PSP_SIZE..start + com::PSP_SIZE + image.len()].copy_from_slice(image);
+        self.ram[start + 0xFFFE..start + 0x10000].fill(0);
+
+        for reg in [Regs::CS, Regs::DS, Regs::ES, Regs::SS] {
+            self.regs.get_mut(&reg).unwrap().value = segment;
+        }
+        for reg in [Regs::AX, Regs::BX, Regs::CX, Regs::DX, Regs::BP, Regs::SI, Regs::DI, Regs::FLAGS] {
+            self.regs.get_mut(&reg).unwrap().value = 0;
+        }
+        self.regs.get_mut(&Regs::IP).unwrap().value = com::PSP_SIZE as u16;
+        self.regs.get_mut(&Regs::SP).unwrap().value = 0xFFFE;
+        self.clear_execution_state();
+        Ok(())
+    }
+
+    pub fn load_com_file<P: AsRef<Path>>(&mut self, path: P, segment: u16, command_tail: &str) -> Result<(), String> {
+        let image = std::fs::read(path.as_ref()).map_err(|error| format!("{}: {}", path.as_ref().display(), error))?;
+        self.load_com(&image, segment, command_tail)
+    }
+
     pub fn execute_next(&mut self) {
         self.step();
         while match self.instruction { Some(_) => true, None => false } || self.next_cycles > 0 {
@@ -655,7 +1136,7 @@ impl CPU {
     pub fn run_to_nop(&mut self, loc: u16) {
         self.regs.get_mut(&Regs::IP).unwrap().value = loc;
         self.step();
-        while match self.instruction.clone() { Some(instruction) => !instruction.has_flag(OpcodeFlags::Nop), None => true } {
+        while !self.shutdown && match self.instruction.clone() { Some(instruction) => !instruction.has_flag(OpcodeFlags::Nop), None => true } {
             self.step();
         }
         while self.next_cycles > 0 {
@@ -678,9 +1159,25 @@ impl CPU {
     }
 
     pub fn get_instruction_text(&self, loc: usize) -> Option<String> {
-        let decoder = instruction::InstructionDecoder::new(self.opcodes.clone(), &self.ram[loc..]);
+        let decoder = InstructionDecoder::with_model(self.opcodes, &self.ram[loc..], self.model);
 
-        Some(decoder.get()?.to_string())
+        Some(decoder.get().ok()?.to_string())
+    }
+
+    // Like get_instruction_text, but labels the address and branch targets with loaded symbols
+    pub fn get_instruction_text_at(&self, seg: u16, offset: u16) -> Option<String> {
+        let decoder = InstructionDecoder::with_model(self.opcodes, &self.ram[Self::physical_address(seg, offset) as usize..], self.model);
+        let instruction = decoder.get().ok()?;
+
+        let mut text = instruction.to_string();
+        if let Some(target) = instruction.relative_target(offset.wrapping_add(instruction.length as u16)) {
+            let name = self.symbols.describe(seg, target).unwrap_or_else(|| format!("{:04X}", target));
+            text = format!("{} ; {}", text, name);
+        }
+        Some(match self.symbols.name_at(seg, offset) {
+            Some(label) => format!("{}: {}", label, text),
+            None => text
+        })
     }
 
     pub fn physical_address(seg: u16, offset: u16) -> u32 {
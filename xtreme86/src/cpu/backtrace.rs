@@ -0,0 +1,85 @@
+use std::fmt::Formatter;
+use crate::cpu::profiler::{CallKind, ReturnKind};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub kind: CallKind,
+    pub call_site: (u16, u16),
+    pub target: (u16, u16),
+    pub return_address: (u16, u16),
+    pub stack: (u16, u16),
+    pub routine: Option<String>,
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            CallKind::Near => String::from("call"),
+            CallKind::Far => String::from("call far"),
+            CallKind::Interrupt(num) => format!("int {:02X}h", num)
+        };
+        if let Some(routine) = &self.routine {
+            write!(f, "{} ", routine)?;
+        }
+        write!(f, "{:04X}:{:04X} ({} from {:04X}:{:04X}, returns to {:04X}:{:04X})",
+               self.target.0, self.target.1, kind, self.call_site.0, self.call_site.1, self.return_address.0, self.return_address.1)
+    }
+}
+
+// Mirrors the guest's CALL/INT nesting. Frames are keyed by the SS:SP the return address was
+// pushed at, so returns are matched by where they pop from rather than by simple nesting.
+#[derive(Clone, Debug, Default)]
+pub struct ShadowStack {
+    frames: Vec<Frame>,
+}
+
+impl ShadowStack {
+    pub fn new() -> Self {
+        Self { frames: Vec::new() }
+    }
+
+    // Returns how many stale frames were discarded before the new one was pushed
+    pub fn push(&mut self, frame: Frame) -> usize {
+        // Anything pushed at or below the new return address was abandoned by the guest
+        // (longjmp style unwinding, or a routine that reset SP and never returned)
+        let live = self.frames.iter().rposition(|old| old.stack.0 != frame.stack.0 || old.stack.1 > frame.stack.1)
+            .map_or(0, |index| index + 1);
+        let discarded = self.frames.len() - live;
+        self.frames.truncate(live);
+        self.frames.push(frame);
+        discarded
+    }
+
+    // Returns how many frames the return unwound, 0 if it didn't match any frame (e.g. a RET
+    // used as an indirect jump)
+    pub fn pop(&mut self, kind: ReturnKind, stack: (u16, u16), return_address: (u16, u16)) -> usize {
+        let index = self.frames.iter().rposition(|frame| frame.stack == stack)
+            .or_else(|| self.frames.iter().rposition(|frame| frame.return_address == return_address && Self::matches(frame.kind, kind)));
+
+        match index {
+            Some(index) => {
+                let unwound = self.frames.len() - index;
+                self.frames.truncate(index);
+                unwound
+            }
+            None => 0
+        }
+    }
+
+    fn matches(call: CallKind, ret: ReturnKind) -> bool {
+        matches!((call, ret), (CallKind::Near, ReturnKind::Near) | (CallKind::Far, ReturnKind::Far) | (CallKind::Interrupt(_), ReturnKind::Interrupt))
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Innermost frame first
+    pub fn frames(&self) -> Vec<Frame> {
+        self.frames.iter().rev().cloned().collect()
+    }
+}
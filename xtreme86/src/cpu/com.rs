@@ -0,0 +1,62 @@
+// The program segment prefix DOS puts in front of a .COM image. The program starts at offset
+// 0x100 of the same segment, with the command tail doubling as the default disk transfer area.
+pub const PSP_SIZE: usize = 0x100;
+
+// Longest tail that still leaves room for the carriage return ending it at 0xFF
+pub const MAX_COMMAND_TAIL: usize = 126;
+
+// `memory_top` is the first segment past the memory the program owns. DOS keeps the separator
+// after the program name, so the tail of `prog foo.txt` is " foo.txt".
+pub fn psp(memory_top: u16, command_tail: &str) -> Result<[u8; PSP_SIZE], String> {
+    let tail = command_tail.as_bytes();
+    if tail.len() > MAX_COMMAND_TAIL {
+        return Err(format!("The command tail is {} bytes, it can have at most {}", tail.len(), MAX_COMMAND_TAIL));
+    }
+
+    let mut psp = [0u8; PSP_SIZE];
+    // int 0x20, which a RET from the program reaches through the zero on the stack
+    psp[0x00..0x02].copy_from_slice(&[0xCD, 0x20]);
+    psp[0x02..0x04].copy_from_slice(&memory_top.to_le_bytes());
+    // int 0x21 / retf, the dispatcher programs can far call
+    psp[0x50..0x53].copy_from_slice(&[0xCD, 0x21, 0xCB]);
+
+    let mut args = command_tail.split_whitespace();
+    psp[0x5C..0x6C].copy_from_slice(&fcb(args.next().unwrap_or("")));
+    psp[0x6C..0x7C].copy_from_slice(&fcb(args.next().unwrap_or("")));
+
+    psp[0x80] = tail.len() as u8;
+    psp[0x81..0x81 + tail.len()].copy_from_slice(tail);
+    psp[0x81 + tail.len()] = 0x0D;
+    Ok(psp)
+}
+
+// The unopened FCB DOS parses an argument into: drive (0 is the default one), the name padded
+// to 8 and the extension to 3 with spaces, a * filled out with ?
+fn fcb(arg: &str) -> [u8; 16] {
+    let mut fcb = [0u8; 16];
+    fcb[1..12].fill(b' ');
+
+    let arg = arg.to_ascii_uppercase();
+    let mut name = arg.as_bytes();
+    if let [drive @ b'A'..=b'Z', b':', rest @ ..] = name {
+        fcb[0] = drive - b'A' + 1;
+        name = rest;
+    }
+    let (base, extension) = match name.iter().position(|byte| *byte == b'.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, &name[name.len()..])
+    };
+    fill_field(&mut fcb[1..9], base);
+    fill_field(&mut fcb[9..12], extension);
+    fcb
+}
+
+fn fill_field(field: &mut [u8], text: &[u8]) {
+    for (i, byte) in text.iter().take(field.len()).enumerate() {
+        if *byte == b'*' {
+            field[i..].fill(b'?');
+            return;
+        }
+        field[i] = *byte;
+    }
+}
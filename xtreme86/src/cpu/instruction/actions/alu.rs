@@ -1,8 +1,7 @@
-use crate::cpu::instruction::actions::{stack, jmp};
-use crate::cpu::{CPU, Regs, CPUFlags, exceptions};
-use crate::cpu::instruction::actions::flags::{cmp, test};
+use crate::cpu::{CPU, Regs, CPUFlags, exceptions, timing};
 use crate::cpu::instruction::args::{SrcArg, DstArg};
 use crate::cpu::instruction::Instruction;
+use crate::cpu::model::Model;
 
 pub fn add_with_carry_16_bit(arg1: u16, arg2: u16) -> u16 {
     let sum = ((arg1 as u32) + (arg2 as u32)) % 65536;
@@ -39,33 +38,19 @@ pub fn twos_compliment_byte(arg: u8) -> u8 {
 }
 
 fn rotate_left_byte(arg: u8, times: u8) -> u8 {
-    let last = arg >> (8 - times);
-    (arg << times) | last
+    arg.rotate_left(times as u32)
 }
 
 fn rotate_left_word(arg: u16, times: u16) -> u16 {
-    let last = arg >> (16 - times);
-    (arg << times) | last
+    arg.rotate_left(times as u32)
 }
 
 fn rotate_right_byte(arg: u8, times: u8) -> u8 {
-    let mut mask = 0;
-    for _ in 0..times {
-        mask <<= 1;
-        mask |= 1;
-    }
-    let first = arg & mask;
-    (arg >> times) | (first << (8 - times))
+    arg.rotate_right(times as u32)
 }
 
 fn rotate_right_word(arg: u16, times: u16) -> u16 {
-    let mut mask = 0;
-    for _ in 0..times {
-        mask <<= 1;
-        mask |= 1;
-    }
-    let first = arg & mask;
-    (arg >> times) | (first << (16 - times))
+    arg.rotate_right(times as u32)
 }
 
 fn rotate_left_carry_byte(arg: u8, times: u8, carry: u8) -> (u8, u8) {
@@ -73,8 +58,8 @@ fn rotate_left_carry_byte(arg: u8, times: u8, carry: u8) -> (u8, u8) {
     let mut new_carry = carry;
     for _ in 0..times {
         let tmp_carry = new_carry;
-        new_carry = (num & 0x01) as u8;
-        num = (num << 1) | (tmp_carry << 7);
+        new_carry = num >> 7;
+        num = (num << 1) | tmp_carry;
     }
     (num, new_carry)
 }
@@ -84,8 +69,8 @@ fn rotate_left_carry_word(arg: u16, times: u16, carry: u8) -> (u16, u8) {
     let mut new_carry = carry;
     for _ in 0..times {
         let tmp_carry = new_carry;
-        new_carry = (num & 0x01) as u8;
-        num = (num << 1) | ((tmp_carry as u16) << 15);
+        new_carry = (num >> 15) as u8;
+        num = (num << 1) | (tmp_carry as u16);
     }
     (num, new_carry)
 }
@@ -112,109 +97,6 @@ fn rotate_right_carry_word(arg: u16, times: u16, carry: u8) -> (u16, u8) {
     (num, new_carry)
 }
 
-pub fn alu_dispatch_two_args(comp: &mut CPU, instruction: Instruction) -> usize {
-    match comp.instruction.clone().unwrap().reg_bits {
-        0b000 => add(comp, instruction),
-        0b001 => or(comp, instruction),
-        0b010 => adc(comp, instruction),
-        0b011 => sbb(comp, instruction),
-        0b100 => and(comp, instruction),
-        0b101 => sub(comp, instruction),
-        0b110 => xor(comp, instruction),
-        0b111 => cmp(comp, instruction),
-        _ => 0
-    }
-}
-
-pub fn alu_dispatch_two_args_mnemonic(instruction: Instruction) -> String {
-    String::from(match instruction.reg_bits {
-        0b000 => "add",
-        0b001 => "or",
-        0b010 => "adc",
-        0b011 => "sbb",
-        0b100 => "and",
-        0b101 => "sub",
-        0b110 => "xor",
-        _ => panic!("invalid reg_bits value")
-    })
-}
-
-pub fn alu_dispatch_one_arg(comp: &mut CPU, instruction: Instruction) -> usize {
-    match comp.instruction.clone().unwrap().reg_bits {
-        0b000 => inc(comp, instruction),
-        0b001 => dec(comp, instruction),
-        0b010 => stack::near_call(comp, instruction),
-        0b011 => stack::far_call(comp, instruction),
-        0b100 => jmp::jmp(comp, instruction),
-        0b101 => jmp::jmp_far(comp, instruction),
-        0b110 => stack::push(comp, instruction),
-        _ => 0
-    }
-}
-
-pub fn alu_dispatch_one_arg_mnemonic(instruction: Instruction) -> String {
-    String::from(match instruction.reg_bits {
-        0b000 => "inc",
-        0b001 => "dec",
-        0b010 | 0b011 => "call",
-        0b100 | 0b101 => "jmp",
-        0b110 => "push",
-        _ => panic!("invalid reg_bits value")
-    })
-}
-
-pub fn mul_dispatch(comp: &mut CPU, instruction: Instruction) -> usize {
-    match comp.instruction.clone().unwrap().reg_bits {
-        0b000 => test(comp, instruction),
-        0b010 => not(comp, instruction),
-        0b011 => neg(comp, instruction),
-        0b100 => mul(comp, instruction),
-        0b101 => imul(comp, instruction),
-        0b110 => div(comp, instruction),
-        0b111 => idiv(comp, instruction),
-        _ => 0
-    }
-}
-
-pub fn mul_dispatch_mnemonic(instruction: Instruction) -> String {
-    String::from(match instruction.reg_bits {
-        0b000 => "test",
-        0b010 => "not",
-        0b011 => "neg",
-        0b100 => "mul",
-        0b101 => "imul",
-        0b110 => "div",
-        0b111 => "idiv",
-        _ => panic!("invalid reg_bits value")
-    })
-}
-
-pub fn rotate_dispatch(comp: &mut CPU, instruction: Instruction) -> usize {
-    match instruction.reg_bits {
-        0b000 => rol(comp, instruction),
-        0b001 => ror(comp, instruction),
-        0b010 => rcl(comp, instruction),
-        0b011 => rcr(comp, instruction),
-        0b100 => sal(comp, instruction),
-        0b101 => shr(comp, instruction),
-        0b111 => sar(comp, instruction),
-        _ => 0
-    }
-}
-
-pub fn rotate_dispatch_mnemonic(instruction: Instruction) -> String {
-    match instruction.reg_bits {
-        0b000 => "rol",
-        0b001 => "ror",
-        0b010 => "rcl",
-        0b011 => "rcr",
-        0b100 => "sal",
-        0b101 => "shr",
-        0b111 => "sar",
-        _ => ""
-    }.to_string()
-}
-
 pub fn add(comp: &mut CPU, instruction: Instruction) -> usize {
     let src = instruction.src.clone().unwrap().to_src_arg(comp).unwrap();
     comp.check_carry_add(src);
@@ -378,28 +260,43 @@ pub fn imul(comp: &mut CPU, instruction: Instruction) -> usize {
     0
 }
 
+// imul r16, r/m16, imm keeps the low word of the product, and sets CF and OF when the high word
+// is more than its sign extension
+pub fn imul_imm(comp: &mut CPU, instruction: Instruction) -> usize {
+    let multiplier = match instruction.imm.unwrap() {
+        DstArg::Imm8(val) => val as i8 as i32,
+        DstArg::Imm16(val) => val as i16 as i32,
+        _ => panic!("imul needs an immediate as third operand")
+    };
+    let result = match instruction.src.clone().unwrap().to_src_arg(comp).unwrap() {
+        SrcArg::Word(val) => (val as i16 as i32) * multiplier,
+        _ => panic!("imul with an immediate only multiplies words")
+    };
+    comp.write_to_arg(instruction.dst.unwrap(), SrcArg::Word(result as u16)).unwrap();
+    comp.set_flag_if(CPUFlags::CARRY | CPUFlags::OVERFLOW, result != (result as i16) as i32);
+    0
+}
+
 pub fn div(comp: &mut CPU, instruction: Instruction) -> usize {
     match instruction.dst.clone().unwrap().to_src_arg(comp).unwrap() {
         SrcArg::Byte(val) => {
-            if val == 0 {
-                comp.except(exceptions::DIVIDE_BY_ZERO).unwrap();
-            } else {
-                let operand = comp.get_reg_16(0).unwrap();
-                let result_div = (operand / (val as u16)) as u8;
-                let result_mod = (operand % (val as u16)) as u8;
-                let result = SrcArg::Word((result_div as u16) | ((result_mod as u16) << 8));
-                comp.write_to_arg(DstArg::Reg16(0), result).unwrap();
+            let operand = comp.get_reg_16(0).unwrap() as u32;
+            match unsigned_divide(operand, val as u32, 0xFF) {
+                Some((result_div, result_mod)) => {
+                    let result = SrcArg::Word((result_div as u16) | ((result_mod as u16) << 8));
+                    comp.write_to_arg(DstArg::Reg16(0), result).unwrap();
+                }
+                None => comp.except(exceptions::DIVIDE_BY_ZERO).unwrap()
             }
         },
         SrcArg::Word(val) => {
-            if val == 0 {
-                comp.except(exceptions::DIVIDE_BY_ZERO).unwrap();
-            } else {
-                let operand = (comp.get_reg_16(0).unwrap() as u32) | ((comp.get_reg_16(2).unwrap() as u32) << 16);
-                let result_div = (operand / (val as u32)) as u16;
-                let result_mod = (operand % (val as u32)) as u16;
-                comp.write_to_arg(DstArg::Reg16(0), SrcArg::Word(result_div)).unwrap();
-                comp.write_to_arg(DstArg::Reg16(2), SrcArg::Word(result_mod)).unwrap();
+            let operand = (comp.get_reg_16(0).unwrap() as u32) | ((comp.get_reg_16(2).unwrap() as u32) << 16);
+            match unsigned_divide(operand, val as u32, 0xFFFF) {
+                Some((result_div, result_mod)) => {
+                    comp.write_to_arg(DstArg::Reg16(0), SrcArg::Word(result_div as u16)).unwrap();
+                    comp.write_to_arg(DstArg::Reg16(2), SrcArg::Word(result_mod as u16)).unwrap();
+                }
+                None => comp.except(exceptions::DIVIDE_BY_ZERO).unwrap()
             }
         }
         SrcArg::DWord(_) => {
@@ -410,27 +307,26 @@ pub fn div(comp: &mut CPU, instruction: Instruction) -> usize {
 }
 
 pub fn idiv(comp: &mut CPU, instruction: Instruction) -> usize {
+    let model = comp.model();
     match instruction.dst.clone().unwrap().to_src_arg(comp).unwrap() {
         SrcArg::Byte(val) => {
-            if val == 0 {
-                comp.except(exceptions::DIVIDE_BY_ZERO).unwrap();
-            } else {
-                let operand = comp.get_reg_16(0).unwrap() as i16;
-                let result_div = (operand / (val as i16)) as i8;
-                let result_mod = (operand % (val as i16)) as i8;
-                let result = SrcArg::Word(((result_div as i16) | ((result_mod as i16) << 8)) as u16);
-                comp.write_to_arg(DstArg::Reg16(0), result).unwrap();
+            let operand = comp.get_reg_16(0).unwrap() as i16 as i64;
+            match signed_divide(model, operand, val as i8 as i64, 8) {
+                Some((result_div, result_mod)) => {
+                    let result = SrcArg::Word((result_div as u8 as u16) | ((result_mod as u8 as u16) << 8));
+                    comp.write_to_arg(DstArg::Reg16(0), result).unwrap();
+                }
+                None => comp.except(exceptions::DIVIDE_BY_ZERO).unwrap()
             }
         },
         SrcArg::Word(val) => {
-            if val == 0 {
-                comp.except(exceptions::DIVIDE_BY_ZERO).unwrap();
-            } else {
-                let operand = (comp.get_reg_16(0).unwrap() as i32) | ((comp.get_reg_16(2).unwrap() as i32) << 16);
-                let result_div = (operand / (val as i32)) as u16;
-                let result_mod = (operand % (val as i32)) as u16;
-                comp.write_to_arg(DstArg::Reg16(0), SrcArg::Word(result_div)).unwrap();
-                comp.write_to_arg(DstArg::Reg16(2), SrcArg::Word(result_mod)).unwrap();
+            let operand = ((comp.get_reg_16(0).unwrap() as u32) | ((comp.get_reg_16(2).unwrap() as u32) << 16)) as i32 as i64;
+            match signed_divide(model, operand, val as i16 as i64, 16) {
+                Some((result_div, result_mod)) => {
+                    comp.write_to_arg(DstArg::Reg16(0), SrcArg::Word(result_div as u16)).unwrap();
+                    comp.write_to_arg(DstArg::Reg16(2), SrcArg::Word(result_mod as u16)).unwrap();
+                }
+                None => comp.except(exceptions::DIVIDE_BY_ZERO).unwrap()
             }
         }
         SrcArg::DWord(_) => {
@@ -440,6 +336,31 @@ pub fn idiv(comp: &mut CPU, instruction: Instruction) -> usize {
     0
 }
 
+// Quotient and remainder, None for the divide error a zero divisor or a quotient over `max` raises
+fn unsigned_divide(dividend: u32, divisor: u32, max: u32) -> Option<(u32, u32)> {
+    if divisor == 0 || dividend / divisor > max {
+        None
+    } else {
+        Some((dividend / divisor, dividend % divisor))
+    }
+}
+
+// Rounds toward zero and gives the remainder the dividend's sign. The 8086 and 8088 microcode
+// also faults on the most negative quotient, -128 or -32768, that later models return.
+fn signed_divide(model: Model, dividend: i64, divisor: i64, bits: u32) -> Option<(i64, i64)> {
+    if divisor == 0 {
+        return None;
+    }
+    let max = (1i64 << (bits - 1)) - 1;
+    let min = if model <= Model::I8086 { -max } else { -max - 1 };
+    let quotient = dividend / divisor;
+    if quotient < min || quotient > max {
+        None
+    } else {
+        Some((quotient, dividend % divisor))
+    }
+}
+
 pub fn aaa(comp: &mut CPU, _: Instruction) -> usize {
     let al = comp.get_reg_8(0).unwrap();
     if al & 0x0F > 9 || comp.check_flag(CPUFlags::AUX_CARRY) {
@@ -511,11 +432,15 @@ pub fn daa(comp: &mut CPU, _: Instruction) -> usize {
 
 pub fn aam(comp: &mut CPU, instruction: Instruction) -> usize {
     let al = comp.regs.get(&Regs::AX).unwrap().get_low();
-    let ax = comp.regs.get_mut(&Regs::AX).unwrap();
     let base = match instruction.dst {
         Some(DstArg::Imm8(val)) => val,
         _ => panic!("AAM can only get a byte immediate value")
     };
+    if base == 0 {
+        comp.except(exceptions::DIVIDE_BY_ZERO).unwrap();
+        return 0;
+    }
+    let ax = comp.regs.get_mut(&Regs::AX).unwrap();
 
     ax.set_high(al / base);
     ax.set_low(al % base);
@@ -552,28 +477,48 @@ pub fn das(comp: &mut CPU, _: Instruction) -> usize {
 }
 
 pub fn ror(comp: &mut CPU, instruction: Instruction) -> usize {
-    let res = comp.operation_2_args(|src, dst| rotate_right_byte(dst, src), |src, dst| rotate_right_word(dst, src));
+    let times = shift_count(comp, &instruction);
+    let res = comp.operation_2_args(|_, dst| rotate_right_byte(dst, times), |_, dst| rotate_right_word(dst, times as u16));
+    if times != 0 {
+        comp.set_flag_if(CPUFlags::CARRY, CPU::check_src_arg(&res, |res| res & 0x80 != 0, |res| res & 0x8000 != 0));
+    }
+    // A single rotate overflows when the two top bits of the result differ
+    if times == 1 {
+        comp.set_flag_if(CPUFlags::OVERFLOW, CPU::check_src_arg(&res, |res| ((res >> 7) ^ (res >> 6)) & 1 != 0, |res| ((res >> 15) ^ (res >> 14)) & 1 != 0));
+    }
     comp.write_to_arg(*instruction.dst.as_ref().unwrap(), res).unwrap();
     0
 }
 
 pub fn rol(comp: &mut CPU, instruction: Instruction) -> usize {
-    let res = comp.operation_2_args(|src, dst| rotate_left_byte(dst, src), |src, dst| rotate_left_word(dst, src));
+    let times = shift_count(comp, &instruction);
+    let res = comp.operation_2_args(|_, dst| rotate_left_byte(dst, times), |_, dst| rotate_left_word(dst, times as u16));
+    if times != 0 {
+        comp.set_flag_if(CPUFlags::CARRY, CPU::check_src_arg(&res, |res| res & 0x01 != 0, |res| res & 0x01 != 0));
+    }
+    // A single rotate overflows when the top bit of the result differs from CF
+    if times == 1 {
+        comp.set_flag_if(CPUFlags::OVERFLOW, CPU::check_src_arg(&res, |res| ((res >> 7) ^ res) & 1 != 0, |res| ((res >> 15) ^ res) & 1 != 0));
+    }
     comp.write_to_arg(*instruction.dst.as_ref().unwrap(), res).unwrap();
     0
 }
 
-fn get_times(src: SrcArg) -> u8 {
-   match src {
-       SrcArg::Byte(val) => val,
-       SrcArg::Word(val) => val as u8,
-       SrcArg::DWord(val) => val as u8
-   }
+// The 8086 shifts as many times as the count says, the 186 and later only look at its low five bits
+fn shift_count(comp: &mut CPU, instruction: &Instruction) -> u8 {
+    let times = match instruction.src.as_ref().unwrap().to_src_arg(comp).unwrap() {
+        SrcArg::Byte(val) => val,
+        _ => panic!("shift operation is only allowed byte as src arg")
+    };
+    let times = if comp.model() >= Model::I80186 { times & 0x1F } else { times };
+    // Every bit shifted by CL or an immediate costs clocks of its own
+    comp.next_cycles += timing::shift_count(comp.model(), instruction, times);
+    times
 }
 
 pub fn rcr(comp: &mut CPU, instruction: Instruction) -> usize {
     let carry = if comp.check_flag(CPUFlags::CARRY) { 1 } else { 0 };
-    let times = get_times(instruction.src.as_ref().unwrap().to_src_arg(comp).unwrap());
+    let times = shift_count(comp, &instruction);
 
     let src = match instruction.dst.as_ref().unwrap().to_src_arg(comp).unwrap() {
         SrcArg::Byte(dst) => {
@@ -603,7 +548,7 @@ pub fn rcr(comp: &mut CPU, instruction: Instruction) -> usize {
 
 pub fn rcl(comp: &mut CPU, instruction: Instruction) -> usize {
     let carry = if comp.check_flag(CPUFlags::CARRY) { 1 } else { 0 };
-    let times = get_times(instruction.src.as_ref().unwrap().to_src_arg(comp).unwrap());
+    let times = shift_count(comp, &instruction);
 
     let src = match instruction.dst.as_ref().unwrap().to_src_arg(comp).unwrap() {
         SrcArg::Byte(dst) => {
@@ -631,50 +576,28 @@ pub fn rcl(comp: &mut CPU, instruction: Instruction) -> usize {
     0
 }
 
+// CF gets the last bit shifted out, which is a zero once the count goes past the operand size
 fn shift_check_carry(comp: &mut CPU, instruction: &Instruction, times: u8, left: bool) {
-    let mut mask = if left { 0x01 } else { 0x80 };
-
-    for _ in 1..times {
-        if left {
-            mask <<= 1;
-        } else {
-            mask >>= 1;
-        }
-    }
-
-    if CPU::check_src_arg(&instruction.dst.as_ref().unwrap().to_src_arg(comp).unwrap(),
-                          |dst| dst & mask != 0,
-                          |dst| dst & (mask as u16) != 0) {
-        comp.set_flag(CPUFlags::CARRY);
-    } else {
-        comp.clear_flag(CPUFlags::CARRY);
-    }
-}
-fn shift_get_times(comp: &mut CPU, instruction: &Instruction) -> u8 {
-    match instruction.src.as_ref().unwrap().to_src_arg(comp).unwrap() {
-        SrcArg::Byte(val) => val,
-        _ => panic!("shift operation is only allowed byte as src arg")
+    if times == 0 {
+        return;
     }
+    let shifted = (times - 1) as u32;
+    let carry = CPU::check_src_arg(&instruction.dst.as_ref().unwrap().to_src_arg(comp).unwrap(),
+                                   |dst| if left { dst.checked_shl(shifted).unwrap_or(0) & 0x80 != 0 } else { dst.checked_shr(shifted).unwrap_or(0) & 0x01 != 0 },
+                                   |dst| if left { dst.checked_shl(shifted).unwrap_or(0) & 0x8000 != 0 } else { dst.checked_shr(shifted).unwrap_or(0) & 0x01 != 0 });
+    comp.set_flag_if(CPUFlags::CARRY, carry);
 }
 
 pub fn sal(comp: &mut CPU, instruction: Instruction) -> usize {
-    let times = match instruction.src.unwrap().to_src_arg(comp).unwrap() {
-        SrcArg::Byte(times) => if times == 1 {
-            if CPU::check_src_arg(&instruction.dst.as_ref().unwrap().to_src_arg(comp).unwrap(),
-                                  |dst| ((dst & 0x80) >> 7) == ((dst & 0x40) >> 6),
-                                  |dst| ((dst & 0x8000) >> 15) == ((dst & 0x4000) >> 14)) {
-                comp.set_flag(CPUFlags::OVERFLOW);
-            } else {
-                comp.clear_flag(CPUFlags::OVERFLOW);
-            }
-            times
-        } else {
-            times
-        }
-        _ => panic!("sal can only get a byte arg for times")
-    };
+    let times = shift_count(comp, &instruction);
+    if times == 1 {
+        let overflow = CPU::check_src_arg(&instruction.dst.as_ref().unwrap().to_src_arg(comp).unwrap(),
+                                          |dst| ((dst & 0x80) >> 7) != ((dst & 0x40) >> 6),
+                                          |dst| ((dst & 0x8000) >> 15) != ((dst & 0x4000) >> 14));
+        comp.set_flag_if(CPUFlags::OVERFLOW, overflow);
+    }
 
-    let res = comp.operation_2_args(|src, dst| dst << src, |src, dst| dst << src);
+    let res = comp.operation_2_args(|_, dst| dst.checked_shl(times as u32).unwrap_or(0), |_, dst| dst.checked_shl(times as u32).unwrap_or(0));
 
     shift_check_carry(comp, &instruction, times, true);
 
@@ -684,11 +607,15 @@ pub fn sal(comp: &mut CPU, instruction: Instruction) -> usize {
 }
 
 pub fn shr(comp: &mut CPU, instruction: Instruction) -> usize {
-    comp.set_flag(CPUFlags::OVERFLOW);
+    let times = shift_count(comp, &instruction);
+    if times == 1 {
+        let overflow = CPU::check_src_arg(&instruction.dst.as_ref().unwrap().to_src_arg(comp).unwrap(),
+                                          |dst| dst & 0x80 != 0, |dst| dst & 0x8000 != 0);
+        comp.set_flag_if(CPUFlags::OVERFLOW, overflow);
+    }
 
-    let res = comp.operation_2_args(|src, dst| dst >> src, |dst, src| dst >> src);
+    let res = comp.operation_2_args(|_, dst| dst.checked_shr(times as u32).unwrap_or(0), |_, dst| dst.checked_shr(times as u32).unwrap_or(0));
 
-    let times = shift_get_times(comp, &instruction);
     shift_check_carry(comp, &instruction, times, false);
 
     comp.write_to_arg(instruction.dst.unwrap(), res).unwrap();
@@ -696,26 +623,42 @@ pub fn shr(comp: &mut CPU, instruction: Instruction) -> usize {
     0
 }
 
-fn arithmetic_right_shift_word(times: u16, arg: u16) -> u16 {
-    ((arg & (0x7FFF)) >> times) | (arg & 0x8000)
+// The 8086 runs the unused /6 of the shift group as SETMO, which sets every bit of the operand
+// unless the count is zero. The flags come out as from an OR with all ones.
+pub fn setmo(comp: &mut CPU, instruction: Instruction) -> usize {
+    if shift_count(comp, &instruction) == 0 {
+        return 0;
+    }
+    let res = comp.operation_2_args(|_, _| 0xFF, |_, _| 0xFFFF);
+    comp.clear_flag(CPUFlags::CARRY | CPUFlags::OVERFLOW | CPUFlags::AUX_CARRY | CPUFlags::ZERO);
+    comp.set_flag(CPUFlags::SIGN | CPUFlags::PARITY);
+    comp.write_to_arg(instruction.dst.unwrap(), res).unwrap();
+    0
+}
+
+fn arithmetic_right_shift_word(times: u8, arg: u16) -> u16 {
+    ((arg as i16) >> times.min(15)) as u16
 }
 
 fn arithmetic_right_shift_byte(times: u8, arg: u8) -> u8 {
-    ((arg & (0x7F)) >> times) | (arg & 0x80)
+    ((arg as i8) >> times.min(7)) as u8
 }
 
 pub fn sar(comp: &mut CPU, instruction: Instruction) -> usize {
-    if CPU::check_src_arg(&instruction.dst.as_ref().unwrap().to_src_arg(comp).unwrap(),
-                          |dst| dst & 0x80 != 0, |dst| dst & 0x8000 != 0) {
-        comp.set_flag(CPUFlags::OVERFLOW);
-    } else {
+    let times = shift_count(comp, &instruction);
+    if times == 1 {
         comp.clear_flag(CPUFlags::OVERFLOW);
-    };
+    }
 
-    let res = comp.operation_2_args(arithmetic_right_shift_byte, arithmetic_right_shift_word);
+    let res = comp.operation_2_args(|_, dst| arithmetic_right_shift_byte(times, dst), |_, dst| arithmetic_right_shift_word(times, dst));
 
-    let times = shift_get_times(comp, &instruction);
-    shift_check_carry(comp, &instruction, times, false);
+    // The sign bit is shifted out once the count reaches the operand size
+    if times != 0 {
+        let carry = CPU::check_src_arg(&instruction.dst.as_ref().unwrap().to_src_arg(comp).unwrap(),
+                                       |dst| arithmetic_right_shift_byte(times - 1, dst) & 0x01 != 0,
+                                       |dst| arithmetic_right_shift_word(times - 1, dst) & 0x01 != 0);
+        comp.set_flag_if(CPUFlags::CARRY, carry);
+    }
 
     comp.write_to_arg(instruction.dst.unwrap(), res).unwrap();
 
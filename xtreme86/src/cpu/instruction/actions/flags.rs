@@ -1,4 +1,4 @@
-use crate::cpu::{CPU, CPUFlags, Regs};
+use crate::cpu::{CPU, CPUFlags, Regs, timing};
 use crate::cpu::instruction::actions::alu::{sub_with_carry_8_bit, sub_with_carry_16_bit};
 use crate::cpu::instruction::args::{SrcArg, DstArg, Size};
 use crate::cpu::instruction::Instruction;
@@ -14,7 +14,7 @@ pub fn cld(comp: &mut CPU, _: Instruction) -> usize {
 }
 
 pub fn cli(comp: &mut CPU, _: Instruction) -> usize {
-    comp.set_flag(CPUFlags::INTERRUPT);
+    comp.clear_flag(CPUFlags::INTERRUPT);
     0
 }
 
@@ -137,11 +137,18 @@ pub fn sahf(comp: &mut CPU, _: Instruction) -> usize {
     0
 }
 
+// Undocumented 8086 opcode 0xD6, AL becomes 0xFF if CF is set and 0 if not
+pub fn salc(comp: &mut CPU, _: Instruction) -> usize {
+    let new_al = if comp.check_flag(CPUFlags::CARRY) { 0xFF } else { 0x00 };
+    comp.regs.get_mut(&Regs::AX).unwrap().set_low(new_al);
+    0
+}
+
 pub fn rep(comp: &mut CPU, instruction: Instruction) -> usize {
     let cmp;
     let op = match instruction.dst.as_ref().unwrap() {
         DstArg::Opcode(opcode) => match opcode {
-            0x6C | 0x7D | 0xA4 | 0xA5 | 0x6E | 0x6F | 0xAA | 0xAB => {
+            0x6C | 0x6D | 0xA4 | 0xA5 | 0x6E | 0x6F | 0xAA | 0xAB | 0xAC | 0xAD => {
                 cmp = false;
                 *opcode
             },
@@ -153,41 +160,44 @@ pub fn rep(comp: &mut CPU, instruction: Instruction) -> usize {
         },
         _ => panic!("rep only accepts string operation opcodes")
     };
-    loop {
+    // Nothing is repeated when CX starts out as 0
+    let mut iterations = 0;
+    while comp.regs.get(&Regs::CX).unwrap().value != 0 {
         comp.do_opcode(op);
+        iterations += 1;
         comp.regs.get_mut(&Regs::CX).unwrap().value -= 1;
-        if comp.regs.get(&Regs::CX).unwrap().value == 0 || if cmp { !comp.check_flag(CPUFlags::ZERO) } else { false } {
+        if cmp && !comp.check_flag(CPUFlags::ZERO) {
             break;
         }
     }
-    0
+    iterations * timing::rep_iteration(comp.model(), op)
 }
 
 pub fn rep_mnemonic(instruction: Instruction) -> String {
     match instruction.dst {
         Some(DstArg::Opcode(op)) => match op {
-            0x6C | 0x7D | 0xA4 | 0xA5 | 0x6E | 0x6F | 0xAA | 0xAB => "rep",
+            0x6C | 0x6D | 0xA4 | 0xA5 | 0x6E | 0x6F | 0xAA | 0xAB | 0xAC | 0xAD => "rep",
             0xA6 | 0xA7 | 0xAE | 0xAF => "repe",
-            _ => panic!("rep only accepts string operation opcodes")
+            _ => ""
         }
-        _ => panic!("rep only accepts string operation opcodes")
+        _ => ""
     }.to_string()
 }
 
 pub fn repne(comp: &mut CPU, instruction: Instruction) -> usize {
-    let op = match instruction.dst.as_ref().unwrap() {
-        DstArg::Opcode(opcode) => match opcode {
-            0xA6 | 0xA7 | 0xAE | 0xAF => *opcode,
-            _ => panic!("repne only accepts string comparison opcodes")
-        },
-        _ => panic!("repne only accepts string comparison opcodes")
+    // Only the comparisons stop on ZF, the other string instructions repeat as with rep
+    let (op, cmp) = match instruction.dst.as_ref().unwrap() {
+        DstArg::Opcode(opcode) => (*opcode, matches!(opcode, 0xA6 | 0xA7 | 0xAE | 0xAF)),
+        _ => panic!("repne only accepts string operation opcodes")
     };
-    loop {
+    let mut iterations = 0;
+    while comp.regs.get(&Regs::CX).unwrap().value != 0 {
         comp.do_opcode(op);
+        iterations += 1;
         comp.regs.get_mut(&Regs::CX).unwrap().value -= 1;
-        if comp.regs.get(&Regs::CX).unwrap().value == 0 || comp.check_flag(CPUFlags::ZERO) {
+        if cmp && comp.check_flag(CPUFlags::ZERO) {
             break;
         }
     }
-    0
+    iterations * timing::rep_iteration(comp.model(), op)
 }
@@ -1,6 +1,7 @@
-use crate::cpu::{CPU, Regs, exceptions, CPUFlags};
+use crate::cpu::{CPU, Regs, exceptions, CPUFlags, timing};
 use crate::cpu::instruction::args::{SrcArg, DstArg};
 use crate::cpu::instruction::Instruction;
+use crate::cpu::profiler::{CallKind, ReturnKind};
 
 pub fn int_req(comp: &mut CPU, instruction: Instruction) -> usize {
     let num = get_int_num(comp, instruction);
@@ -16,19 +17,39 @@ fn get_int_num(comp: &mut CPU, instruction: Instruction) -> u8 {
 }
 
 pub fn int(comp: &mut CPU) -> usize {
-    let tmp_es = comp.read_reg(Regs::ES).unwrap();
+    let return_address = (comp.read_reg(Regs::CS).unwrap(), comp.read_reg(Regs::IP).unwrap());
 
-    comp.set_reg(Regs::ES, 0x0000);
+    let mut num = comp.irq.take().unwrap();
+    // A vector past the limit LIDT set raises interrupt 8 instead, and if that one isn't there
+    // either the processor shuts down. That is the only fault delivery can run into: segment
+    // limits aren't checked, so pushing the frame wraps around within SS instead of faulting.
+    let vector = match comp.vector_address(num) {
+        Some(vector) => vector,
+        None if num == exceptions::DOUBLE_FAULT => {
+            comp.shut_down();
+            return 0;
+        }
+        None => {
+            num = exceptions::DOUBLE_FAULT;
+            match comp.vector_address(num) {
+                Some(vector) => vector,
+                None => {
+                    comp.shut_down();
+                    return 0;
+                }
+            }
+        }
+    };
 
     comp.sub_command(0xFF, None, Some(DstArg::Reg(Regs::FLAGS)), 0b110);
     comp.sub_command(0xFF, None, Some(DstArg::Reg(Regs::CS)), 0b110);
     comp.sub_command(0xFF, None, Some(DstArg::Reg(Regs::IP)), 0b110);
+    let flags = comp.read_reg(Regs::FLAGS).unwrap();
+    comp.clear_flag(CPUFlags::INTERRUPT);
+    comp.clear_flag(CPUFlags::TRAP);
 
-    let num = comp.irq.unwrap();
-    comp.irq = None;
-
-    let new_cs = comp.read_mem_word_seg((num as u16) * 4 + 2, Regs::ES).unwrap();
-    let new_ip = comp.read_mem_word_seg((num as u16) * 4, Regs::ES).unwrap();
+    let new_cs = comp.read_physical_word(vector + 2);
+    let new_ip = comp.read_physical_word(vector);
 
     if new_cs == 0xFFFF {
         let dev_opt = comp.io_devices.get_mut(new_ip as usize).map(|s| s.clone());
@@ -41,43 +62,70 @@ pub fn int(comp: &mut CPU) -> usize {
             None => 0
         };
         comp.next_cycles += new_cycles;
+        // The peripheral stands in for the handler, which returns as if by RETF 2 so flags it
+        // set stay set, apart from the IF and TF the interrupt cleared
+        comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::IP)), 0);
+        comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::CS)), 0);
+        comp.pop_word();
+        comp.set_flag_if(CPUFlags::INTERRUPT, flags & CPUFlags::INTERRUPT != 0);
+        comp.set_flag_if(CPUFlags::TRAP, flags & CPUFlags::TRAP != 0);
     } else {
         comp.write_to_arg(DstArg::Reg(Regs::CS), SrcArg::Word(new_cs)).unwrap();
         comp.write_to_arg(DstArg::Reg(Regs::IP), SrcArg::Word(new_ip)).unwrap();
+        comp.flush_prefetch_queue();
+        comp.trace_call(CallKind::Interrupt(num), return_address);
     }
-
-    comp.set_reg(Regs::ES, tmp_es);
     0
 }
 
-pub fn into(comp: &mut CPU, _: Instruction) -> usize {
+pub fn into(comp: &mut CPU, instruction: Instruction) -> usize {
     if comp.check_flag(CPUFlags::OVERFLOW) {
         comp.except(exceptions::INTO).unwrap();
+        return timing::taken(comp.model(), instruction.opcode);
     }
     0
 }
 
 pub fn iret(comp: &mut CPU, _: Instruction) -> usize {
-    comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::IP)), 0b110);
-    comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::CS)), 0b110);
-    comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::FLAGS)), 0b110);
+    let stack = comp.stack_address();
+    comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::IP)), 0b000);
+    comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::CS)), 0b000);
+    comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::FLAGS)), 0b000);
+    comp.flush_prefetch_queue();
+    comp.trace_return(ReturnKind::Interrupt, stack);
     0
 }
 
 pub fn bound(comp: &mut CPU, instruction: Instruction) -> usize {
+    // The bounds are signed, lower bound first
     if let Some(SrcArg::DWord(bounds)) = instruction.src.clone().unwrap().to_src_arg(comp) {
-        match instruction.dst.clone().unwrap() {
-            DstArg::Reg16(_) | DstArg::Reg(_) => (),
-            _ => comp.except(exceptions::INVALID_OPCODE).unwrap()
-        }
-        let lower_bound = (bounds & 0xFFFF) as u16;
-        let upper_bound = (bounds >> 16) as u16;
-        let arg = instruction.dst.clone().unwrap().to_src_arg(comp);
-        if let Some(SrcArg::Word(val)) = arg {
-            if val > upper_bound || val < lower_bound {
+        let lower_bound = bounds as u16 as i16;
+        let upper_bound = (bounds >> 16) as u16 as i16;
+        if let Some(SrcArg::Word(val)) = instruction.dst.clone().unwrap().to_src_arg(comp) {
+            if (val as i16) > upper_bound || (val as i16) < lower_bound {
                 comp.except(exceptions::BOUND).unwrap();
             }
         }
     }
     0
 }
+
+// The limit word and 24 bit base of the interrupt table
+pub fn lidt(comp: &mut CPU, instruction: Instruction) -> usize {
+    let ptr = instruction.dst.unwrap().to_ptr(comp).unwrap();
+    let limit = comp.read_mem_word_mut(ptr).unwrap();
+    let base = comp.read_mem_word_mut(ptr.wrapping_add(2)).unwrap() as u32
+        | (comp.read_mem_byte_mut(ptr.wrapping_add(4)).unwrap() as u32) << 16;
+    comp.idt = (base, limit);
+    0
+}
+
+// The 286 stores the unused top byte of the base as 0xFF
+pub fn sidt(comp: &mut CPU, instruction: Instruction) -> usize {
+    let ptr = instruction.dst.unwrap().to_ptr(comp).unwrap();
+    let (base, limit) = comp.idt;
+    comp.write_mem_word(ptr, limit).unwrap();
+    comp.write_mem_word(ptr.wrapping_add(2), base as u16).unwrap();
+    comp.write_mem_word(ptr.wrapping_add(4), 0xFF00 | (base >> 16) as u16).unwrap();
+    0
+}
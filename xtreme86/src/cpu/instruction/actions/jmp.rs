@@ -1,7 +1,8 @@
-use crate::cpu::{CPU, Regs};
-use std::rc::Rc;
+use crate::cpu::{CPU, Regs, timing};
+use std::sync::Arc;
 use crate::cpu::instruction::args::{DstArg, SrcArg, Size};
 use crate::cpu::instruction::Instruction;
+use crate::cpu::instruction::opcode::OpcodeAction;
 
 
 pub fn jmp(comp: &mut CPU, instruction: Instruction) -> usize {
@@ -15,6 +16,7 @@ pub fn jmp(comp: &mut CPU, instruction: Instruction) -> usize {
     } else {
         comp.regs.get_mut(&Regs::IP).unwrap().value -= -val as u16
     }
+    comp.flush_prefetch_queue();
     0
 }
 
@@ -32,24 +34,27 @@ pub fn jmp_far(comp: &mut CPU, instruction: Instruction) -> usize {
         comp.regs.get_mut(&Regs::CS).unwrap().value = cs;
         comp.regs.get_mut(&Regs::IP).unwrap().value = ip;
     }
+    comp.flush_prefetch_queue();
     0
 }
 
-pub fn cond_jmp(condition: Box<dyn Fn(&CPU) -> bool>) -> Rc<dyn Fn(&mut CPU, Instruction) -> usize> {
-    Rc::new(move |this, instruction| {
+pub fn cond_jmp(condition: Box<dyn Fn(&CPU) -> bool + Send + Sync>) -> OpcodeAction {
+    Arc::new(move |this, instruction| {
         if condition(this) {
             this.sub_command(0xE9, instruction.src.clone(), instruction.dst.clone(), 0);
+            return timing::taken(this.model(), instruction.opcode);
         }
         0
     })
 }
 
-pub fn lop(condition: Box<dyn Fn(&CPU) -> bool>) -> Rc<dyn Fn(&mut CPU, Instruction) -> usize> {
-    Rc::new(move |this, instruction| {
+pub fn lop(condition: Box<dyn Fn(&CPU) -> bool + Send + Sync>) -> OpcodeAction {
+    Arc::new(move |this, instruction| {
         let new_cx = this.regs.get(&Regs::CX).unwrap().value.wrapping_sub(1);
         this.regs.get_mut(&Regs::CX).unwrap().value = new_cx;
         if this.regs.get(&Regs::CX).unwrap().value != 0 && condition(this) {
             this.sub_command(0xE9, None, instruction.dst.clone(), 0);
+            return timing::taken(this.model(), instruction.opcode);
         }
         0
     })
@@ -1,17 +1,24 @@
-use crate::cpu::{CPU, Regs};
-use crate::cpu::instruction::args::{SrcArg, DstArg, Size};
+use crate::cpu::{CPU, Regs, timing};
+use crate::cpu::model::Model;
+use crate::cpu::instruction::args::{SrcArg, DstArg};
 use crate::cpu::instruction::Instruction;
+use crate::cpu::profiler::{CallKind, ReturnKind};
 
 pub fn push(comp: &mut CPU, instruction: Instruction) -> usize {
-    let tmp_arg = instruction.dst.clone().unwrap().to_src_arg(comp).unwrap();
-    let arg = match tmp_arg {
-        SrcArg::Byte(val) => SrcArg::Word(CPU::sign_extend(val)),
-        arg => arg
+    let dst = instruction.dst.unwrap();
+    let val = match dst.to_src_arg(comp).unwrap() {
+        SrcArg::Byte(val) => CPU::sign_extend(val),
+        SrcArg::Word(val) => val,
+        _ => panic!("push only takes words")
     };
-    comp.instruction.as_mut().map(|mut s| { s.segment = Regs::SS });
-    comp.write_to_arg(DstArg::Ptr(comp.read_reg(Regs::SP).unwrap() - 1, Size::Word), arg).expect("Err");
-    comp.regs.get_mut(&Regs::SP).unwrap().value -= 2;
-    1
+    // Before the 286, PUSH SP pushes the value SP has after the decrement
+    let val = if comp.model() < Model::I80286 && matches!(dst, DstArg::Reg16(4) | DstArg::Reg(Regs::SP)) {
+        val.wrapping_sub(2)
+    } else {
+        val
+    };
+    comp.push_word(val);
+    0
 }
 
 pub fn pusha(comp: &mut CPU, _: Instruction) -> usize {
@@ -28,17 +35,18 @@ pub fn pusha(comp: &mut CPU, _: Instruction) -> usize {
 }
 
 pub fn pop(comp: &mut CPU, instruction: Instruction) -> usize {
-    let val = SrcArg::Word(comp.read_mem_word_seg(comp.read_reg(Regs::SP).unwrap() + 1, Regs::SS).unwrap());
-    comp.write_to_arg(instruction.dst.clone().unwrap(), val).unwrap();
-    comp.regs.get_mut(&Regs::SP).unwrap().value += 2;
-    1
+    // SP has gone up before the destination is written, so POP SP keeps the popped value
+    let val = comp.pop_word();
+    comp.write_to_arg(instruction.dst.unwrap(), SrcArg::Word(val)).unwrap();
+    0
 }
 
 pub fn popa(comp: &mut CPU, _: Instruction) -> usize {
     comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::DI)), 0);
     comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::SI)), 0);
     comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::BP)), 0);
-    comp.regs.get_mut(&Regs::SP).unwrap().value += 2;
+    let sp = comp.regs.get(&Regs::SP).unwrap().value.wrapping_add(2);
+    comp.regs.get_mut(&Regs::SP).unwrap().value = sp;
     comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::BX)), 0);
     comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::DX)), 0);
     comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::CX)), 0);
@@ -47,14 +55,17 @@ pub fn popa(comp: &mut CPU, _: Instruction) -> usize {
 }
 
 pub fn far_call(comp: &mut CPU, instruction: Instruction) -> usize {
+    let return_address = (comp.read_reg(Regs::CS).unwrap(), comp.read_reg(Regs::IP).unwrap());
     comp.sub_command(0xFF, None, Some(DstArg::Reg(Regs::CS)), 0b110);
     comp.sub_command(0xFF, None, Some(DstArg::Reg(Regs::IP)), 0b110);
     let arg = instruction.dst;
     comp.sub_command(0xFF, None, arg, 0b101);
+    comp.trace_call(CallKind::Far, return_address);
     0
 }
 
 pub fn near_call(comp: &mut CPU, instruction: Instruction) -> usize {
+    let return_address = (comp.read_reg(Regs::CS).unwrap(), comp.read_reg(Regs::IP).unwrap());
     comp.sub_command(0xFF, None, Some(DstArg::Reg(Regs::IP)), 0b110);
     match instruction.dst.clone().unwrap() {
         DstArg::Imm16(val) => {
@@ -66,29 +77,40 @@ pub fn near_call(comp: &mut CPU, instruction: Instruction) -> usize {
             if let Some(src) = val_src {
                 comp.write_to_arg(DstArg::Reg(Regs::IP), src).unwrap();
             }
+            comp.flush_prefetch_queue();
         }
     }
+    comp.trace_call(CallKind::Near, return_address);
     0
 }
 
+// ret imm16 releases that many bytes of arguments as well
 fn pop_dst(comp: &mut CPU, instruction: Instruction) {
-    comp.regs.get_mut(&Regs::SP).unwrap().value -= match instruction.dst {
+    let released = match instruction.dst {
         Some(DstArg::Imm16(val)) => val,
         Some(_) => panic!("ret can only get immediate word as arg"),
         None => 0
     };
+    let sp = comp.regs.get(&Regs::SP).unwrap().value.wrapping_add(released);
+    comp.regs.get_mut(&Regs::SP).unwrap().value = sp;
 }
 
 pub fn near_ret(comp: &mut CPU, instruction: Instruction) -> usize {
+    let stack = comp.stack_address();
     comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::IP)), 0b000);
     pop_dst(comp, instruction);
+    comp.flush_prefetch_queue();
+    comp.trace_return(ReturnKind::Near, stack);
     0
 }
 
 pub fn far_ret(comp: &mut CPU, instruction: Instruction) -> usize {
+    let stack = comp.stack_address();
     comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::IP)), 0b000);
     comp.sub_command(0x8F, None, Some(DstArg::Reg(Regs::CS)), 0b000);
     pop_dst(comp, instruction);
+    comp.flush_prefetch_queue();
+    comp.trace_return(ReturnKind::Far, stack);
     0
 }
 
@@ -98,24 +120,27 @@ pub fn enter(comp: &mut CPU, instruction: Instruction) -> usize {
         Some(SrcArg::Word(val)) => val,
         _ => panic!("First operand for ENTER must be a word")
     };
+    // Only the low five bits of the nesting level count
     let level = match instruction.src.clone().unwrap().to_src_arg(comp) {
-        Some(SrcArg::Byte(val)) => val % 13,
+        Some(SrcArg::Byte(val)) => val & 0x1F,
         _ => panic!("Second operand for ENTER must be a byte")
     };
-    comp.sub_command(0xFE, None, Some(DstArg::Reg(Regs::BP)), 0b110);
+    let old_bp = comp.regs.get(&Regs::BP).unwrap().value;
+    comp.sub_command(0xFF, None, Some(DstArg::Reg(Regs::BP)), 0b110);
     let frame_ptr = comp.regs.get(&Regs::SP).unwrap().value;
     if level > 0 {
-        for _ in 1..level {
-            let new_bp = comp.regs.get(&Regs::BP).unwrap().value - 2;
-            comp.regs.get_mut(&Regs::BP).unwrap().value = new_bp;
-            comp.sub_command(0xFE, None, Some(DstArg::Ptr(new_bp, Size::Word)), 0b110);
+        // Copies the frame pointers of the enclosing procedures from the old frame
+        for i in 1..level {
+            let slot = old_bp.wrapping_sub(2 * i as u16);
+            let display = comp.read_mem_word_seg(slot, Regs::SS).unwrap();
+            comp.sub_command(0xFF, None, Some(DstArg::Imm16(display)), 0b110);
         }
-        comp.sub_command(0xFE, None, Some(DstArg::Imm16(frame_ptr)), 0b110);
+        comp.sub_command(0xFF, None, Some(DstArg::Imm16(frame_ptr)), 0b110);
     }
     comp.regs.get_mut(&Regs::BP).unwrap().value = frame_ptr;
-    let new_sp = comp.regs.get(&Regs::SP).unwrap().value - dst;
+    let new_sp = comp.regs.get(&Regs::SP).unwrap().value.wrapping_sub(dst);
     comp.regs.get_mut(&Regs::SP).unwrap().value = new_sp;
-    0
+    timing::enter_levels(comp.model(), level)
 }
 
 pub fn leave(comp: &mut CPU, _: Instruction) -> usize {
@@ -2,7 +2,7 @@ use crate::cpu::{Regs, CPU};
 use std::fmt::Formatter;
 use crate::cpu::instruction::opcode::{Opcode, Mnemonic};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Size {
     Byte,
     Word,
@@ -76,7 +76,7 @@ impl DstArg {
             0 => Regs::ES,
             1 => Regs::CS,
             2 => Regs::SS,
-            3 => Regs::CS,
+            3 => Regs::DS,
             _ => return None
         }))
     }
@@ -90,9 +90,9 @@ impl DstArg {
             DstArg::Imm32(val) => Some(SrcArg::DWord(val)),
             DstArg::Ptr(ptr, size) => Some(size.get_comp_ptr(comp, ptr)),
             DstArg::RegPtr(reg, size) => Some({ let ptr = comp.read_reg(reg).unwrap(); size.get_comp_ptr(comp, ptr) }),
-            DstArg::RegPtrImm(reg, imm, size) => Some({ let ptr = comp.read_reg(reg).unwrap() + imm; size.get_comp_ptr(comp, ptr) }),
-            DstArg::RegPtrOff(reg1, reg2, size) => Some({ let ptr = comp.read_reg(reg1).unwrap() + comp.read_reg(reg2).unwrap(); size.get_comp_ptr(comp, ptr) }),
-            DstArg::RegPtrOffImm(reg1, reg2, imm, size) => Some({ let ptr = comp.read_reg(reg1).unwrap() + comp.read_reg(reg2).unwrap() + imm; size.get_comp_ptr(comp, ptr) }),
+            DstArg::RegPtrImm(reg, imm, size) => Some({ let ptr = comp.read_reg(reg).unwrap().wrapping_add(imm); size.get_comp_ptr(comp, ptr) }),
+            DstArg::RegPtrOff(reg1, reg2, size) => Some({ let ptr = comp.read_reg(reg1).unwrap().wrapping_add(comp.read_reg(reg2).unwrap()); size.get_comp_ptr(comp, ptr) }),
+            DstArg::RegPtrOffImm(reg1, reg2, imm, size) => Some({ let ptr = comp.read_reg(reg1).unwrap().wrapping_add(comp.read_reg(reg2).unwrap()).wrapping_add(imm); size.get_comp_ptr(comp, ptr) }),
             DstArg::Reg(reg) => Some(SrcArg::Word(comp.regs.get(&reg)?.value)),
             DstArg::Opcode(op) => Some(SrcArg::Byte(op))
         }
@@ -102,18 +102,20 @@ impl DstArg {
         match self {
             DstArg::Ptr(val, _) => Some(*val),
             DstArg::RegPtr(reg, _) => comp.read_reg(*reg),
-            DstArg::RegPtrImm(reg, imm, _) => Some(comp.read_reg(*reg).unwrap() + imm),
-            DstArg::RegPtrOff(reg1, reg2, _) => Some(comp.read_reg(*reg1).unwrap() + comp.read_reg(*reg2).unwrap()),
-            DstArg::RegPtrOffImm(reg1, reg2, imm, _) => Some(comp.read_reg(*reg1).unwrap() + comp.read_reg(*reg2).unwrap() + imm),
+            DstArg::RegPtrImm(reg, imm, _) => Some(comp.read_reg(*reg).unwrap().wrapping_add(*imm)),
+            DstArg::RegPtrOff(reg1, reg2, _) => Some(comp.read_reg(*reg1).unwrap().wrapping_add(comp.read_reg(*reg2).unwrap())),
+            DstArg::RegPtrOffImm(reg1, reg2, imm, _) => Some(comp.read_reg(*reg1).unwrap().wrapping_add(comp.read_reg(*reg2).unwrap()).wrapping_add(*imm)),
             _ => None
         }
     }
 }
 
-fn get_opcode_mnemonic(op: u8) -> String {
-    match Opcode::get_opcode_data().get(op as usize).unwrap().clone().unwrap().mnemonic {
-        Mnemonic::Static(mnemonic) => mnemonic,
-        Mnemonic::Dynamic(_) => panic!("can't get dynamic opcode mnemonic")
+// Mnemonic of an opcode that follows a prefix. Opcodes whose mnemonic depends on a ModR/M byte
+// can't be named from the opcode alone, so they are shown as a number.
+pub fn get_opcode_mnemonic(op: u8) -> Option<String> {
+    match &Opcode::table()[op as usize] {
+        Some(Opcode { mnemonic: Mnemonic::Static(mnemonic), group: None, .. }) => Some(mnemonic.clone()),
+        _ => None
     }
 }
 
@@ -131,7 +133,7 @@ impl std::fmt::Display for DstArg {
             DstArg::RegPtrOff(reg, off_reg, size) => format!("{} [{} + {}]", size, reg.to_text(), off_reg.to_text()),
             DstArg::RegPtrOffImm(reg, off_reg, imm, size) => format!("{} [{} + {} + {}]", size, reg.to_text(), off_reg.to_text(), imm),
             DstArg::Reg(reg) => reg.to_text(),
-            DstArg::Opcode(op) => get_opcode_mnemonic(*op)
+            DstArg::Opcode(op) => get_opcode_mnemonic(*op).unwrap_or_else(|| format!("0x{:02X}", op))
         })
     }
 }
@@ -1,272 +1,350 @@
-/// This file was generated by the Python script in utils/opcode_generator
-
-use crate::cpu::instruction::opcode::{Opcode, Mnemonic, NumArgs, Placeholder};
-use crate::cpu::{Regs, CPU, CPUFlags};
+use crate::cpu::instruction::opcode::{Opcode, Mnemonic, NumArgs, Placeholder, SubOpcode, Group};
+use crate::cpu::instruction::opcode::NumArgs::{Zero, One, Two};
+use crate::cpu::instruction::opcode::Placeholder::{Reg8, Reg16, Reg, RegEnum, Byte, Word, Imm, Ptr};
+use crate::cpu::instruction::opcode::OpcodeFlags::{Immediate, SizeMismatch, Nop, ForceWord, ForceByte, ForceDWord,
+                                                   ForceDirection, ForceNotDirection, Segment};
 use crate::cpu::instruction::actions::{alu, flags, int, io, jmp, mem, stack};
-use enumflags2::make_bitflags;
-use crate::cpu::instruction::opcode::OpcodeFlags;
-use std::rc::Rc;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::{Regs, CPU, CPUFlags};
+use std::sync::Arc;
 
-impl Opcode {
-	pub fn get_opcode_data() -> [Option<Opcode>; 256] {
-		[
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::add), mnemonic: Mnemonic::Static(String::from("add")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::add), mnemonic: Mnemonic::Static(String::from("add")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::RegEnum(Regs::ES)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::RegEnum(Regs::ES)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::or), mnemonic: Mnemonic::Static(String::from("or")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::or), mnemonic: Mnemonic::Static(String::from("or")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::RegEnum(Regs::CS)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::adc), mnemonic: Mnemonic::Static(String::from("adc")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::adc), mnemonic: Mnemonic::Static(String::from("adc")), shorthand1: Some(Placeholder::Reg8(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::RegEnum(Regs::SS)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::RegEnum(Regs::SS)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::sbb), mnemonic: Mnemonic::Static(String::from("sbb")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::sbb), mnemonic: Mnemonic::Static(String::from("sbb")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::RegEnum(Regs::DS)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::RegEnum(Regs::DS)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::and), mnemonic: Mnemonic::Static(String::from("and")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::and), mnemonic: Mnemonic::Static(String::from("and")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(alu::daa), mnemonic: Mnemonic::Static(String::from("daa")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::sub), mnemonic: Mnemonic::Static(String::from("sub")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::sub), mnemonic: Mnemonic::Static(String::from("sub")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(alu::das), mnemonic: Mnemonic::Static(String::from("das")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::xor), mnemonic: Mnemonic::Static(String::from("xor")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::xor), mnemonic: Mnemonic::Static(String::from("xor")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(alu::aaa), mnemonic: Mnemonic::Static(String::from("aaa")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(flags::cmp), mnemonic: Mnemonic::Static(String::from("cmp")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ SizeMismatch }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(flags::cmp), mnemonic: Mnemonic::Static(String::from("cmp")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(alu::aas), mnemonic: Mnemonic::Static(String::from("aas")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::inc), mnemonic: Mnemonic::Static(String::from("inc")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::inc), mnemonic: Mnemonic::Static(String::from("inc")), shorthand1: Some(Placeholder::Reg16(1)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::inc), mnemonic: Mnemonic::Static(String::from("inc")), shorthand1: Some(Placeholder::Reg16(2)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::inc), mnemonic: Mnemonic::Static(String::from("inc")), shorthand1: Some(Placeholder::Reg16(3)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::inc), mnemonic: Mnemonic::Static(String::from("inc")), shorthand1: Some(Placeholder::Reg16(4)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::inc), mnemonic: Mnemonic::Static(String::from("inc")), shorthand1: Some(Placeholder::Reg16(5)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::inc), mnemonic: Mnemonic::Static(String::from("inc")), shorthand1: Some(Placeholder::Reg16(6)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::inc), mnemonic: Mnemonic::Static(String::from("inc")), shorthand1: Some(Placeholder::Reg16(7)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::dec), mnemonic: Mnemonic::Static(String::from("dec")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::dec), mnemonic: Mnemonic::Static(String::from("dec")), shorthand1: Some(Placeholder::Reg16(1)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::dec), mnemonic: Mnemonic::Static(String::from("dec")), shorthand1: Some(Placeholder::Reg16(2)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::dec), mnemonic: Mnemonic::Static(String::from("dec")), shorthand1: Some(Placeholder::Reg16(3)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::dec), mnemonic: Mnemonic::Static(String::from("dec")), shorthand1: Some(Placeholder::Reg16(4)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::dec), mnemonic: Mnemonic::Static(String::from("dec")), shorthand1: Some(Placeholder::Reg16(5)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::dec), mnemonic: Mnemonic::Static(String::from("dec")), shorthand1: Some(Placeholder::Reg16(6)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::dec), mnemonic: Mnemonic::Static(String::from("dec")), shorthand1: Some(Placeholder::Reg16(7)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Reg16(1)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Reg16(2)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Reg16(3)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Reg16(4)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Reg16(5)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Reg16(6)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Reg16(7)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::Reg16(1)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::Reg16(2)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::Reg16(3)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::Reg16(4)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::Reg16(5)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::Reg16(6)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: Some(Placeholder::Reg16(7)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(stack::pusha), mnemonic: Mnemonic::Static(String::from("pusha")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(stack::popa), mnemonic: Mnemonic::Static(String::from("popa")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(int::bound), mnemonic: Mnemonic::Static(String::from("bound")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ ForceDWord }), segment: None }),
-			None,
-			None,
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Imm), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceWord }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("push")), shorthand1: Some(Placeholder::Imm), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceByte }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(io::ins), mnemonic: Mnemonic::Static(String::from("insb")), shorthand1: Some(Placeholder::Byte(0)), shorthand2: Some(Placeholder::RegEnum(Regs::DX)), flags: make_bitflags!(OpcodeFlags::{ SizeMismatch | ForceNotDirection }), segment: Some(Regs::ES) }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(io::ins), mnemonic: Mnemonic::Static(String::from("insw")), shorthand1: Some(Placeholder::Word(0)), shorthand2: Some(Placeholder::RegEnum(Regs::DX)), flags: make_bitflags!(OpcodeFlags::{ SizeMismatch | ForceNotDirection }), segment: Some(Regs::ES) }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(io::outs), mnemonic: Mnemonic::Static(String::from("outsb")), shorthand1: Some(Placeholder::RegEnum(Regs::DX)), shorthand2: Some(Placeholder::Byte(0)), flags: make_bitflags!(OpcodeFlags::{ SizeMismatch | ForceNotDirection }), segment: Some(Regs::DS) }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(io::outs), mnemonic: Mnemonic::Static(String::from("outsw")), shorthand1: Some(Placeholder::RegEnum(Regs::DX)), shorthand2: Some(Placeholder::Word(0)), flags: make_bitflags!(OpcodeFlags::{ SizeMismatch | ForceNotDirection }), segment: Some(Regs::DS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.check_flag(CPUFlags::OVERFLOW))), mnemonic: Mnemonic::Static(String::from("jo")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| !this.check_flag(CPUFlags::OVERFLOW))), mnemonic: Mnemonic::Static(String::from("jno")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.check_flag(CPUFlags::CARRY))), mnemonic: Mnemonic::Static(String::from("jc")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| !this.check_flag(CPUFlags::CARRY))), mnemonic: Mnemonic::Static(String::from("jnc")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.check_flag(CPUFlags::ZERO))), mnemonic: Mnemonic::Static(String::from("je")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| !this.check_flag(CPUFlags::ZERO))), mnemonic: Mnemonic::Static(String::from("jne")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.check_flag(CPUFlags::CARRY) || this.check_flag(CPUFlags::ZERO))), mnemonic: Mnemonic::Static(String::from("jbe")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| !this.check_flag(CPUFlags::CARRY) && !this.check_flag(CPUFlags::ZERO))), mnemonic: Mnemonic::Static(String::from("ja")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.check_flag(CPUFlags::SIGN))), mnemonic: Mnemonic::Static(String::from("js")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| !this.check_flag(CPUFlags::SIGN))), mnemonic: Mnemonic::Static(String::from("jns")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.check_flag(CPUFlags::PARITY))), mnemonic: Mnemonic::Static(String::from("jp")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| !this.check_flag(CPUFlags::PARITY))), mnemonic: Mnemonic::Static(String::from("jnp")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.check_flags_not_equal(CPUFlags::SIGN, CPUFlags::OVERFLOW))), mnemonic: Mnemonic::Static(String::from("jl")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| !this.check_flags_not_equal(CPUFlags::SIGN, CPUFlags::OVERFLOW))), mnemonic: Mnemonic::Static(String::from("jge")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.check_flags_not_equal(CPUFlags::SIGN, CPUFlags::OVERFLOW) || this.check_flag(CPUFlags::ZERO))), mnemonic: Mnemonic::Static(String::from("jle")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.check_flag(CPUFlags::SIGN) && !this.check_flags_not_equal(CPUFlags::SIGN, CPUFlags::OVERFLOW))), mnemonic: Mnemonic::Static(String::from("jg")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::alu_dispatch_two_args), mnemonic: Mnemonic::Dynamic(Rc::new(alu::alu_dispatch_two_args_mnemonic)), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::alu_dispatch_two_args), mnemonic: Mnemonic::Dynamic(Rc::new(alu::alu_dispatch_two_args_mnemonic)), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(flags::test), mnemonic: Mnemonic::Static(String::from("test")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::xchg), mnemonic: Mnemonic::Static(String::from("xchg")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ ForceWord | Segment }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::lea), mnemonic: Mnemonic::Static(String::from("lea")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ ForceDirection }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("pop")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::nop), mnemonic: Mnemonic::Static(String::from("nop")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Nop }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::xchg), mnemonic: Mnemonic::Static(String::from("xchg")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: Some(Placeholder::Reg16(1)), flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::xchg), mnemonic: Mnemonic::Static(String::from("xchg")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: Some(Placeholder::Reg16(2)), flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::xchg), mnemonic: Mnemonic::Static(String::from("xchg")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: Some(Placeholder::Reg16(3)), flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::xchg), mnemonic: Mnemonic::Static(String::from("xchg")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: Some(Placeholder::Reg16(4)), flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::xchg), mnemonic: Mnemonic::Static(String::from("xchg")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: Some(Placeholder::Reg16(5)), flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::xchg), mnemonic: Mnemonic::Static(String::from("xchg")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: Some(Placeholder::Reg16(6)), flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::xchg), mnemonic: Mnemonic::Static(String::from("xchg")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: Some(Placeholder::Reg16(7)), flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::cbw), mnemonic: Mnemonic::Static(String::from("cbw")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::cwd), mnemonic: Mnemonic::Static(String::from("cwd")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::far_call), mnemonic: Mnemonic::Static(String::from("call")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ ForceDWord | Immediate }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(stack::push), mnemonic: Mnemonic::Static(String::from("pushf")), shorthand1: Some(Placeholder::RegEnum(Regs::FLAGS)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(stack::pop), mnemonic: Mnemonic::Static(String::from("popf")), shorthand1: Some(Placeholder::RegEnum(Regs::FLAGS)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::sahf), mnemonic: Mnemonic::Static(String::from("sahf")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::lahf), mnemonic: Mnemonic::Static(String::from("lahf")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Ptr), flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::movs), mnemonic: Mnemonic::Static(String::from("movsb")), shorthand1: Some(Placeholder::Byte(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::movs), mnemonic: Mnemonic::Static(String::from("movsw")), shorthand1: Some(Placeholder::Word(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::cmps), mnemonic: Mnemonic::Static(String::from("cmpsb")), shorthand1: Some(Placeholder::Byte(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::cmps), mnemonic: Mnemonic::Static(String::from("cmpsw")), shorthand1: Some(Placeholder::Word(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(flags::test), mnemonic: Mnemonic::Static(String::from("test")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::stos), mnemonic: Mnemonic::Static(String::from("stosb")), shorthand1: Some(Placeholder::Byte(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: Some(Regs::ES) }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::stos), mnemonic: Mnemonic::Static(String::from("stosw")), shorthand1: Some(Placeholder::Word(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: Some(Regs::ES) }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::lods), mnemonic: Mnemonic::Static(String::from("lodsb")), shorthand1: Some(Placeholder::Byte(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::lods), mnemonic: Mnemonic::Static(String::from("lodsw")), shorthand1: Some(Placeholder::Word(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::scas), mnemonic: Mnemonic::Static(String::from("scasb")), shorthand1: Some(Placeholder::Reg8(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: Some(Regs::ES) }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::scas), mnemonic: Mnemonic::Static(String::from("scasw")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: Some(Regs::ES) }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg8(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg8(1)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg8(2)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg8(3)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg8(4)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg8(5)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg8(6)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg8(7)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg16(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg16(1)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg16(2)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg16(3)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg16(4)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg16(5)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg16(6)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: Some(Placeholder::Reg16(7)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::rotate_dispatch), mnemonic: Mnemonic::Dynamic(Rc::new(alu::rotate_dispatch_mnemonic)), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceByte | SizeMismatch }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::near_ret), mnemonic: Mnemonic::Static(String::from("ret")), shorthand1: Some(Placeholder::Imm), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceWord }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(stack::near_ret), mnemonic: Mnemonic::Static(String::from("ret")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::les), mnemonic: Mnemonic::Static(String::from("les")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ ForceDWord }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::lds), mnemonic: Mnemonic::Static(String::from("lds")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ ForceDWord }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(mem::mov), mnemonic: Mnemonic::Static(String::from("mov")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(stack::enter), mnemonic: Mnemonic::Static(String::from("enter")), shorthand1: Some(Placeholder::Imm), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(stack::leave), mnemonic: Mnemonic::Static(String::from("leave")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::far_ret), mnemonic: Mnemonic::Static(String::from("ret")), shorthand1: Some(Placeholder::Imm), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceWord }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(stack::far_ret), mnemonic: Mnemonic::Static(String::from("ret")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(int::int_req), mnemonic: Mnemonic::Static(String::from("int")), shorthand1: Some(Placeholder::Byte(3)), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceByte }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(int::int_req), mnemonic: Mnemonic::Static(String::from("int")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceByte }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(int::into), mnemonic: Mnemonic::Static(String::from("into")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(int::iret), mnemonic: Mnemonic::Static(String::from("int")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::rotate_dispatch), mnemonic: Mnemonic::Dynamic(Rc::new(alu::rotate_dispatch_mnemonic)), shorthand1: None, shorthand2: Some(Placeholder::Byte(1)), flags: make_bitflags!(OpcodeFlags::{ SizeMismatch }), segment: None }),
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(alu::rotate_dispatch), mnemonic: Mnemonic::Dynamic(Rc::new(alu::rotate_dispatch_mnemonic)), shorthand1: None, shorthand2: Some(Placeholder::Reg8(2)), flags: make_bitflags!(OpcodeFlags::{ SizeMismatch }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::aam), mnemonic: Mnemonic::Static(String::from("aam")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceByte }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::aad), mnemonic: Mnemonic::Static(String::from("aad")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceByte }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(mem::xlat), mnemonic: Mnemonic::Static(String::from("xlat")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			None,
-			None,
-			None,
-			None,
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::lop(Box::new(|this: &CPU| !this.check_flag(CPUFlags::ZERO))), mnemonic: Mnemonic::Static(String::from("loopne")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::lop(Box::new(|this: &CPU| this.check_flag(CPUFlags::ZERO))), mnemonic: Mnemonic::Static(String::from("loope")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::lop(Box::new(|_: &CPU| true)), mnemonic: Mnemonic::Static(String::from("loop")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::One, action: jmp::cond_jmp(Box::new(|this: &CPU| this.regs.get(&Regs::CX).unwrap().value == 0)), mnemonic: Mnemonic::Static(String::from("jcxz")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | SizeMismatch }), segment: Some(Regs::CS) }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(io::in_action), mnemonic: Mnemonic::Static(String::from("in")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::Imm), flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceByte }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(io::out), mnemonic: Mnemonic::Static(String::from("out")), shorthand1: Some(Placeholder::Imm), shorthand2: Some(Placeholder::Reg(0)), flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceByte }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(stack::near_call), mnemonic: Mnemonic::Static(String::from("call")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ ForceWord | Immediate }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(jmp::jmp), mnemonic: Mnemonic::Static(String::from("jmp")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceWord }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(jmp::jmp_far), mnemonic: Mnemonic::Static(String::from("jmp")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceDWord }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(jmp::jmp), mnemonic: Mnemonic::Static(String::from("jmp")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{ Immediate | ForceByte }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(io::in_action), mnemonic: Mnemonic::Static(String::from("in")), shorthand1: Some(Placeholder::Reg(0)), shorthand2: Some(Placeholder::RegEnum(Regs::DX)), flags: make_bitflags!(OpcodeFlags::{ SizeMismatch | ForceNotDirection }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Two, action: Rc::new(io::out), mnemonic: Mnemonic::Static(String::from("out")), shorthand1: Some(Placeholder::RegEnum(Regs::DX)), shorthand2: Some(Placeholder::Reg(0)), flags: make_bitflags!(OpcodeFlags::{ SizeMismatch | ForceNotDirection }), segment: None }),
-			None,
-			None,
-			None,
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(flags::repne), mnemonic: Mnemonic::Static(String::from("repne")), shorthand1: Some(Placeholder::Opcode), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(flags::rep), mnemonic: Mnemonic::Dynamic(Rc::new(flags::rep_mnemonic)), shorthand1: Some(Placeholder::Opcode), shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::cmc), mnemonic: Mnemonic::Static(String::from("cmc")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::mul_dispatch), mnemonic: Mnemonic::Dynamic(Rc::new(alu::mul_dispatch_mnemonic)), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::clc), mnemonic: Mnemonic::Static(String::from("clc")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::stc), mnemonic: Mnemonic::Static(String::from("stc")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::cli), mnemonic: Mnemonic::Static(String::from("cli")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::sti), mnemonic: Mnemonic::Static(String::from("sti")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::cld), mnemonic: Mnemonic::Static(String::from("cld")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::Zero, action: Rc::new(flags::std), mnemonic: Mnemonic::Static(String::from("std")), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			Some(Opcode{ num_args: NumArgs::One, action: Rc::new(alu::alu_dispatch_one_arg), mnemonic: Mnemonic::Dynamic(Rc::new(alu::alu_dispatch_one_arg_mnemonic)), shorthand1: None, shorthand2: None, flags: make_bitflags!(OpcodeFlags::{  }), segment: None }),
-			None,
-		]
-	}
+// Builds the table from `opcode => entry` lines, where a range shares one entry. The lines have
+// to cover 0x00 to 0xFF in order, each opcode once, or the build fails. That way an opcode is
+// only ever decoded as another one on purpose.
+macro_rules! opcode_table {
+    (@last $first:literal $last:literal) => { $last };
+    (@last $first:literal) => { $first };
+    ($($first:literal $(..= $last:literal)? => $entry:expr),* $(,)?) => {{
+        const _: () = check_coverage(&[$(($first, opcode_table!(@last $first $($last)?))),*]);
+        let mut table: [Option<Opcode>; 256] = std::array::from_fn(|_| None);
+        $(
+            for code in $first..=opcode_table!(@last $first $($last)?) {
+                table[code] = $entry;
+            }
+        )*
+        table
+    }};
+}
+
+const fn check_coverage(ranges: &[(usize, usize)]) {
+    let mut next = 0;
+    let mut i = 0;
+    while i < ranges.len() {
+        if ranges[i].0 != next {
+            panic!("opcode table entries have to be in order, without gaps or overlaps");
+        }
+        if ranges[i].1 < ranges[i].0 {
+            panic!("opcode table range ends before it starts");
+        }
+        next = ranges[i].1 + 1;
+        i += 1;
+    }
+    if next != 0x100 {
+        panic!("opcode table has to end at 0xFF");
+    }
+}
+
+fn op(num_args: NumArgs, action: impl Fn(&mut CPU, Instruction) -> usize + Send + Sync + 'static, mnemonic: &str) -> Opcode {
+    Opcode::new(num_args, Arc::new(action), mnemonic)
+}
+
+fn sub(mnemonic: &str, action: impl Fn(&mut CPU, Instruction) -> usize + Send + Sync + 'static) -> Option<SubOpcode> {
+    Some(SubOpcode { mnemonic: String::from(mnemonic), action: Arc::new(action) })
+}
+
+fn jcc(mnemonic: &str, condition: impl Fn(&CPU) -> bool + Send + Sync + 'static) -> Opcode {
+    Opcode::new(One, jmp::cond_jmp(Box::new(condition)), mnemonic)
+        .with_flags(Immediate | SizeMismatch).with_segment(Regs::CS)
+}
+
+fn lop(mnemonic: &str, condition: impl Fn(&CPU) -> bool + Send + Sync + 'static) -> Opcode {
+    Opcode::new(One, jmp::lop(Box::new(condition)), mnemonic)
+        .with_flags(Immediate | SizeMismatch).with_segment(Regs::CS)
+}
+
+// 0x80 to 0x83
+fn alu_group() -> Group {
+    [sub("add", alu::add), sub("or", alu::or), sub("adc", alu::adc), sub("sbb", alu::sbb),
+        sub("and", alu::and), sub("sub", alu::sub), sub("xor", alu::xor), sub("cmp", flags::cmp)]
+}
+
+// 0xC0, 0xC1 and 0xD0 to 0xD3
+fn shift_group() -> Group {
+    [sub("rol", alu::rol), sub("ror", alu::ror), sub("rcl", alu::rcl), sub("rcr", alu::rcr),
+        sub("sal", alu::sal), sub("shr", alu::shr), None, sub("sar", alu::sar)]
 }
 
+// 0xF6 and 0xF7. TEST also reads an immediate, see InstructionDecoder::get_one_arg.
+fn unary_group() -> Group {
+    [sub("test", flags::test), None, sub("not", alu::not), sub("neg", alu::neg),
+        sub("mul", alu::mul), sub("imul", alu::imul), sub("div", alu::div), sub("idiv", alu::idiv)]
+}
+
+fn inc_dec_group() -> Group {
+    [sub("inc", alu::inc), sub("dec", alu::dec), None, None, None, None, None, None]
+}
+
+fn inc_dec_call_jmp_push_group() -> Group {
+    [sub("inc", alu::inc), sub("dec", alu::dec), sub("call", stack::near_call), sub("call", stack::far_call),
+        sub("jmp", jmp::jmp), sub("jmp", jmp::jmp_far), sub("push", stack::push), None]
+}
+
+// 0xD0 to 0xD3 on the 8086, where /6 is SETMO
+fn shift_group_8086(setmo: &str) -> Group {
+    let mut group = shift_group();
+    group[6] = sub(setmo, alu::setmo);
+    group
+}
+
+// 0xF6 and 0xF7 on the 8086, where /1 is TEST again
+fn unary_group_8086() -> Group {
+    let mut group = unary_group();
+    group[1] = sub("test", flags::test);
+    group
+}
+
+// 0x0F 0x01 on the 286, of which only the real mode interrupt table ones are there
+fn descriptor_table_group() -> Group {
+    [None, sub("sidt", int::sidt), None, sub("lidt", int::lidt), None, None, None, None]
+}
+
+fn only_reg_zero(mnemonic: &str, action: impl Fn(&mut CPU, Instruction) -> usize + Send + Sync + 'static) -> Group {
+    [sub(mnemonic, action), None, None, None, None, None, None, None]
+}
+
+impl Opcode {
+    pub fn get_opcode_data() -> [Option<Opcode>; 256] {
+        opcode_table! {
+            0x00..=0x03 => Some(op(Two, alu::add, "add")),
+            0x04..=0x05 => Some(op(Two, alu::add, "add").with_args(Reg(0), Imm).with_flags(Immediate)),
+            0x06 => Some(op(One, stack::push, "push").with_arg(RegEnum(Regs::ES))),
+            0x07 => Some(op(One, stack::pop, "pop").with_arg(RegEnum(Regs::ES))),
+            0x08..=0x0B => Some(op(Two, alu::or, "or")),
+            0x0C..=0x0D => Some(op(Two, alu::or, "or").with_args(Reg(0), Imm).with_flags(Immediate)),
+            0x0E => Some(op(One, stack::push, "push").with_arg(RegEnum(Regs::CS))),
+            0x0F => None,
+            0x10..=0x13 => Some(op(Two, alu::adc, "adc")),
+            0x14..=0x15 => Some(op(Two, alu::adc, "adc").with_args(Reg(0), Imm).with_flags(Immediate)),
+            0x16 => Some(op(One, stack::push, "push").with_arg(RegEnum(Regs::SS))),
+            0x17 => Some(op(One, stack::pop, "pop").with_arg(RegEnum(Regs::SS))),
+            0x18..=0x1B => Some(op(Two, alu::sbb, "sbb")),
+            0x1C..=0x1D => Some(op(Two, alu::sbb, "sbb").with_args(Reg(0), Imm).with_flags(Immediate)),
+            0x1E => Some(op(One, stack::push, "push").with_arg(RegEnum(Regs::DS))),
+            0x1F => Some(op(One, stack::pop, "pop").with_arg(RegEnum(Regs::DS))),
+            0x20..=0x23 => Some(op(Two, alu::and, "and")),
+            0x24..=0x25 => Some(op(Two, alu::and, "and").with_args(Reg(0), Imm).with_flags(Immediate)),
+            // Segment prefixes are taken by the decoder before it looks at the table
+            0x26 => None,
+            0x27 => Some(op(Zero, alu::daa, "daa")),
+            0x28..=0x2B => Some(op(Two, alu::sub, "sub")),
+            0x2C..=0x2D => Some(op(Two, alu::sub, "sub").with_args(Reg(0), Imm).with_flags(Immediate)),
+            0x2E => None,
+            0x2F => Some(op(Zero, alu::das, "das")),
+            0x30..=0x33 => Some(op(Two, alu::xor, "xor")),
+            0x34..=0x35 => Some(op(Two, alu::xor, "xor").with_args(Reg(0), Imm).with_flags(Immediate)),
+            0x36 => None,
+            0x37 => Some(op(Zero, alu::aaa, "aaa")),
+            0x38..=0x3B => Some(op(Two, flags::cmp, "cmp").with_flags(SizeMismatch)),
+            0x3C..=0x3D => Some(op(Two, flags::cmp, "cmp").with_args(Reg(0), Imm).with_flags(Immediate)),
+            0x3E => None,
+            0x3F => Some(op(Zero, alu::aas, "aas")),
+            0x40 => Some(op(One, alu::inc, "inc").with_arg(Reg16(0))),
+            0x41 => Some(op(One, alu::inc, "inc").with_arg(Reg16(1))),
+            0x42 => Some(op(One, alu::inc, "inc").with_arg(Reg16(2))),
+            0x43 => Some(op(One, alu::inc, "inc").with_arg(Reg16(3))),
+            0x44 => Some(op(One, alu::inc, "inc").with_arg(Reg16(4))),
+            0x45 => Some(op(One, alu::inc, "inc").with_arg(Reg16(5))),
+            0x46 => Some(op(One, alu::inc, "inc").with_arg(Reg16(6))),
+            0x47 => Some(op(One, alu::inc, "inc").with_arg(Reg16(7))),
+            0x48 => Some(op(One, alu::dec, "dec").with_arg(Reg16(0))),
+            0x49 => Some(op(One, alu::dec, "dec").with_arg(Reg16(1))),
+            0x4A => Some(op(One, alu::dec, "dec").with_arg(Reg16(2))),
+            0x4B => Some(op(One, alu::dec, "dec").with_arg(Reg16(3))),
+            0x4C => Some(op(One, alu::dec, "dec").with_arg(Reg16(4))),
+            0x4D => Some(op(One, alu::dec, "dec").with_arg(Reg16(5))),
+            0x4E => Some(op(One, alu::dec, "dec").with_arg(Reg16(6))),
+            0x4F => Some(op(One, alu::dec, "dec").with_arg(Reg16(7))),
+            0x50 => Some(op(One, stack::push, "push").with_arg(Reg16(0))),
+            0x51 => Some(op(One, stack::push, "push").with_arg(Reg16(1))),
+            0x52 => Some(op(One, stack::push, "push").with_arg(Reg16(2))),
+            0x53 => Some(op(One, stack::push, "push").with_arg(Reg16(3))),
+            0x54 => Some(op(One, stack::push, "push").with_arg(Reg16(4))),
+            0x55 => Some(op(One, stack::push, "push").with_arg(Reg16(5))),
+            0x56 => Some(op(One, stack::push, "push").with_arg(Reg16(6))),
+            0x57 => Some(op(One, stack::push, "push").with_arg(Reg16(7))),
+            0x58 => Some(op(One, stack::pop, "pop").with_arg(Reg16(0))),
+            0x59 => Some(op(One, stack::pop, "pop").with_arg(Reg16(1))),
+            0x5A => Some(op(One, stack::pop, "pop").with_arg(Reg16(2))),
+            0x5B => Some(op(One, stack::pop, "pop").with_arg(Reg16(3))),
+            0x5C => Some(op(One, stack::pop, "pop").with_arg(Reg16(4))),
+            0x5D => Some(op(One, stack::pop, "pop").with_arg(Reg16(5))),
+            0x5E => Some(op(One, stack::pop, "pop").with_arg(Reg16(6))),
+            0x5F => Some(op(One, stack::pop, "pop").with_arg(Reg16(7))),
+            0x60 => Some(op(Zero, stack::pusha, "pusha")),
+            0x61 => Some(op(Zero, stack::popa, "popa")),
+            0x62 => Some(op(Two, int::bound, "bound").with_flags(ForceDWord)),
+            0x63..=0x67 => None,
+            0x68 => Some(op(One, stack::push, "push").with_arg(Imm).with_flags(Immediate | ForceWord)),
+            0x69 => Some(op(Two, alu::imul_imm, "imul").with_flags(ForceDirection)),
+            0x6A => Some(op(One, stack::push, "push").with_arg(Imm).with_flags(Immediate | ForceByte)),
+            0x6B => Some(op(Two, alu::imul_imm, "imul").with_flags(ForceDirection | ForceByte)),
+            0x6C => Some(op(Zero, io::ins, "insb").with_args(Byte(0), RegEnum(Regs::DX))
+                .with_flags(SizeMismatch | ForceNotDirection).with_segment(Regs::ES)),
+            0x6D => Some(op(Zero, io::ins, "insw").with_args(Word(0), RegEnum(Regs::DX))
+                .with_flags(SizeMismatch | ForceNotDirection).with_segment(Regs::ES)),
+            0x6E => Some(op(Zero, io::outs, "outsb").with_args(RegEnum(Regs::DX), Byte(0))
+                .with_flags(SizeMismatch | ForceNotDirection).with_segment(Regs::DS)),
+            0x6F => Some(op(Zero, io::outs, "outsw").with_args(RegEnum(Regs::DX), Word(0))
+                .with_flags(SizeMismatch | ForceNotDirection).with_segment(Regs::DS)),
+            0x70 => Some(jcc("jo", |this| this.check_flag(CPUFlags::OVERFLOW))),
+            0x71 => Some(jcc("jno", |this| !this.check_flag(CPUFlags::OVERFLOW))),
+            0x72 => Some(jcc("jc", |this| this.check_flag(CPUFlags::CARRY))),
+            0x73 => Some(jcc("jnc", |this| !this.check_flag(CPUFlags::CARRY))),
+            0x74 => Some(jcc("je", |this| this.check_flag(CPUFlags::ZERO))),
+            0x75 => Some(jcc("jne", |this| !this.check_flag(CPUFlags::ZERO))),
+            0x76 => Some(jcc("jbe", |this| this.check_flag(CPUFlags::CARRY) || this.check_flag(CPUFlags::ZERO))),
+            0x77 => Some(jcc("ja", |this| !this.check_flag(CPUFlags::CARRY) && !this.check_flag(CPUFlags::ZERO))),
+            0x78 => Some(jcc("js", |this| this.check_flag(CPUFlags::SIGN))),
+            0x79 => Some(jcc("jns", |this| !this.check_flag(CPUFlags::SIGN))),
+            0x7A => Some(jcc("jp", |this| this.check_flag(CPUFlags::PARITY))),
+            0x7B => Some(jcc("jnp", |this| !this.check_flag(CPUFlags::PARITY))),
+            0x7C => Some(jcc("jl", |this| this.check_flags_not_equal(CPUFlags::SIGN, CPUFlags::OVERFLOW))),
+            0x7D => Some(jcc("jge", |this| !this.check_flags_not_equal(CPUFlags::SIGN, CPUFlags::OVERFLOW))),
+            0x7E => Some(jcc("jle", |this| this.check_flags_not_equal(CPUFlags::SIGN, CPUFlags::OVERFLOW) || this.check_flag(CPUFlags::ZERO))),
+            0x7F => Some(jcc("jg", |this| this.check_flag(CPUFlags::SIGN) && !this.check_flags_not_equal(CPUFlags::SIGN, CPUFlags::OVERFLOW))),
+            // 0x82 is 0x80 again
+            0x80..=0x82 => Some(Opcode::group(Two, alu_group()).with_flags(Immediate)),
+            0x83 => Some(Opcode::group(Two, alu_group()).with_flags(Immediate | SizeMismatch)),
+            0x84..=0x85 => Some(op(Two, flags::test, "test")),
+            0x86..=0x87 => Some(op(Two, mem::xchg, "xchg")),
+            0x88..=0x8B => Some(op(Two, mem::mov, "mov")),
+            0x8C => Some(op(Two, mem::mov, "mov").with_flags(ForceWord | Segment)),
+            0x8D => Some(op(Two, mem::lea, "lea").with_flags(ForceDirection)),
+            0x8E => Some(op(Two, mem::mov, "mov").with_flags(ForceWord | Segment)),
+            0x8F => Some(Opcode::group(One, only_reg_zero("pop", stack::pop))),
+            0x90 => Some(op(Zero, mem::nop, "nop").with_flags(Nop)),
+            0x91 => Some(op(Two, mem::xchg, "xchg").with_args(Reg16(0), Reg16(1))),
+            0x92 => Some(op(Two, mem::xchg, "xchg").with_args(Reg16(0), Reg16(2))),
+            0x93 => Some(op(Two, mem::xchg, "xchg").with_args(Reg16(0), Reg16(3))),
+            0x94 => Some(op(Two, mem::xchg, "xchg").with_args(Reg16(0), Reg16(4))),
+            0x95 => Some(op(Two, mem::xchg, "xchg").with_args(Reg16(0), Reg16(5))),
+            0x96 => Some(op(Two, mem::xchg, "xchg").with_args(Reg16(0), Reg16(6))),
+            0x97 => Some(op(Two, mem::xchg, "xchg").with_args(Reg16(0), Reg16(7))),
+            0x98 => Some(op(Zero, mem::cbw, "cbw")),
+            0x99 => Some(op(Zero, mem::cwd, "cwd")),
+            0x9A => Some(op(One, stack::far_call, "call").with_flags(ForceDWord | Immediate)),
+            0x9B => None,
+            0x9C => Some(op(Zero, stack::push, "pushf").with_arg(RegEnum(Regs::FLAGS))),
+            0x9D => Some(op(Zero, stack::pop, "popf").with_arg(RegEnum(Regs::FLAGS))),
+            0x9E => Some(op(Zero, flags::sahf, "sahf")),
+            0x9F => Some(op(Zero, flags::lahf, "lahf")),
+            0xA0..=0xA3 => Some(op(Two, mem::mov, "mov").with_args(Reg(0), Ptr)),
+            0xA4 => Some(op(Zero, mem::movs, "movsb").with_arg(Byte(0))),
+            0xA5 => Some(op(Zero, mem::movs, "movsw").with_arg(Word(0))),
+            0xA6 => Some(op(Zero, flags::cmps, "cmpsb").with_arg(Byte(0))),
+            0xA7 => Some(op(Zero, flags::cmps, "cmpsw").with_arg(Word(0))),
+            0xA8..=0xA9 => Some(op(Two, flags::test, "test").with_args(Reg(0), Imm).with_flags(Immediate)),
+            0xAA => Some(op(Zero, mem::stos, "stosb").with_arg(Byte(0)).with_segment(Regs::ES)),
+            0xAB => Some(op(Zero, mem::stos, "stosw").with_arg(Word(0)).with_segment(Regs::ES)),
+            0xAC => Some(op(Zero, mem::lods, "lodsb").with_arg(Byte(0))),
+            0xAD => Some(op(Zero, mem::lods, "lodsw").with_arg(Word(0))),
+            0xAE => Some(op(Zero, flags::scas, "scasb").with_arg(Reg8(0)).with_segment(Regs::ES)),
+            0xAF => Some(op(Zero, flags::scas, "scasw").with_arg(Reg16(0)).with_segment(Regs::ES)),
+            0xB0 => Some(op(Two, mem::mov, "mov").with_args(Reg8(0), Imm).with_flags(Immediate)),
+            0xB1 => Some(op(Two, mem::mov, "mov").with_args(Reg8(1), Imm).with_flags(Immediate)),
+            0xB2 => Some(op(Two, mem::mov, "mov").with_args(Reg8(2), Imm).with_flags(Immediate)),
+            0xB3 => Some(op(Two, mem::mov, "mov").with_args(Reg8(3), Imm).with_flags(Immediate)),
+            0xB4 => Some(op(Two, mem::mov, "mov").with_args(Reg8(4), Imm).with_flags(Immediate)),
+            0xB5 => Some(op(Two, mem::mov, "mov").with_args(Reg8(5), Imm).with_flags(Immediate)),
+            0xB6 => Some(op(Two, mem::mov, "mov").with_args(Reg8(6), Imm).with_flags(Immediate)),
+            0xB7 => Some(op(Two, mem::mov, "mov").with_args(Reg8(7), Imm).with_flags(Immediate)),
+            0xB8 => Some(op(Two, mem::mov, "mov").with_args(Reg16(0), Imm).with_flags(Immediate)),
+            0xB9 => Some(op(Two, mem::mov, "mov").with_args(Reg16(1), Imm).with_flags(Immediate)),
+            0xBA => Some(op(Two, mem::mov, "mov").with_args(Reg16(2), Imm).with_flags(Immediate)),
+            0xBB => Some(op(Two, mem::mov, "mov").with_args(Reg16(3), Imm).with_flags(Immediate)),
+            0xBC => Some(op(Two, mem::mov, "mov").with_args(Reg16(4), Imm).with_flags(Immediate)),
+            0xBD => Some(op(Two, mem::mov, "mov").with_args(Reg16(5), Imm).with_flags(Immediate)),
+            0xBE => Some(op(Two, mem::mov, "mov").with_args(Reg16(6), Imm).with_flags(Immediate)),
+            0xBF => Some(op(Two, mem::mov, "mov").with_args(Reg16(7), Imm).with_flags(Immediate)),
+            0xC0..=0xC1 => Some(Opcode::group(Two, shift_group()).with_flags(Immediate | ForceByte | SizeMismatch)),
+            0xC2 => Some(op(One, stack::near_ret, "ret").with_arg(Imm).with_flags(Immediate | ForceWord)),
+            0xC3 => Some(op(Zero, stack::near_ret, "ret")),
+            0xC4 => Some(op(Two, mem::les, "les").with_flags(ForceDWord)),
+            0xC5 => Some(op(Two, mem::lds, "lds").with_flags(ForceDWord)),
+            0xC6..=0xC7 => Some(Opcode::group(Two, only_reg_zero("mov", mem::mov)).with_flags(Immediate)),
+            0xC8 => Some(op(Two, stack::enter, "enter").with_args(Imm, Imm).with_flags(Immediate | SizeMismatch)),
+            0xC9 => Some(op(Zero, stack::leave, "leave")),
+            0xCA => Some(op(One, stack::far_ret, "retf").with_arg(Imm).with_flags(Immediate | ForceWord)),
+            0xCB => Some(op(Zero, stack::far_ret, "retf")),
+            0xCC => Some(op(Zero, int::int_req, "int3").with_arg(Byte(3))),
+            0xCD => Some(op(One, int::int_req, "int").with_flags(Immediate | ForceByte)),
+            0xCE => Some(op(Zero, int::into, "into")),
+            0xCF => Some(op(Zero, int::iret, "iret")),
+            0xD0..=0xD1 => Some(Opcode::group(Two, shift_group()).with_second_arg(Byte(1)).with_flags(SizeMismatch)),
+            0xD2..=0xD3 => Some(Opcode::group(Two, shift_group()).with_second_arg(Reg8(1)).with_flags(SizeMismatch | ForceNotDirection)),
+            0xD4 => Some(op(One, alu::aam, "aam").with_flags(Immediate | ForceByte)),
+            0xD5 => Some(op(One, alu::aad, "aad").with_flags(Immediate | ForceByte)),
+            0xD6 => None,
+            0xD7 => Some(op(Zero, mem::xlat, "xlat")),
+            // Coprocessor escapes
+            0xD8..=0xDF => None,
+            0xE0 => Some(lop("loopne", |this| !this.check_flag(CPUFlags::ZERO))),
+            0xE1 => Some(lop("loope", |this| this.check_flag(CPUFlags::ZERO))),
+            0xE2 => Some(lop("loop", |_| true)),
+            0xE3 => Some(jcc("jcxz", |this| this.regs.get(&Regs::CX).unwrap().value == 0)),
+            0xE4..=0xE5 => Some(op(Two, io::in_action, "in").with_args(Reg(0), Imm).with_flags(Immediate | ForceByte)),
+            0xE6..=0xE7 => Some(op(Two, io::out, "out").with_args(Imm, Reg(0)).with_flags(Immediate | ForceByte)),
+            0xE8 => Some(op(One, stack::near_call, "call").with_flags(ForceWord | Immediate)),
+            0xE9 => Some(op(One, jmp::jmp, "jmp").with_flags(Immediate | ForceWord)),
+            0xEA => Some(op(One, jmp::jmp_far, "jmp").with_flags(Immediate | ForceDWord)),
+            0xEB => Some(op(One, jmp::jmp, "jmp").with_flags(Immediate | ForceByte)),
+            0xEC..=0xED => Some(op(Two, io::in_action, "in").with_args(Reg(0), RegEnum(Regs::DX))
+                .with_flags(SizeMismatch | ForceNotDirection)),
+            0xEE..=0xEF => Some(op(Two, io::out, "out").with_args(RegEnum(Regs::DX), Reg(0))
+                .with_flags(SizeMismatch | ForceNotDirection)),
+            0xF0..=0xF1 => None,
+            0xF2 => Some(op(One, flags::repne, "repne").with_arg(Placeholder::Opcode)),
+            0xF3 => Some(op(One, flags::rep, "").with_arg(Placeholder::Opcode)
+                .with_mnemonic(Mnemonic::Dynamic(Arc::new(flags::rep_mnemonic)))),
+            0xF4 => None,
+            0xF5 => Some(op(Zero, flags::cmc, "cmc")),
+            0xF6..=0xF7 => Some(Opcode::group(One, unary_group())),
+            0xF8 => Some(op(Zero, flags::clc, "clc")),
+            0xF9 => Some(op(Zero, flags::stc, "stc")),
+            0xFA => Some(op(Zero, flags::cli, "cli")),
+            0xFB => Some(op(Zero, flags::sti, "sti")),
+            0xFC => Some(op(Zero, flags::cld, "cld")),
+            0xFD => Some(op(Zero, flags::std, "std")),
+            0xFE => Some(Opcode::group(One, inc_dec_group())),
+            0xFF => Some(Opcode::group(One, inc_dec_call_jmp_push_group())),
+        }
+    }
+
+    // What the 8086 and 8088 decode differently from the table above, None where they agree. They
+    // don't check for invalid opcodes, so the ones the 186 added run as older instructions that
+    // only differ in a bit the 8086 ignores, and the reserved ones do something of their own.
+    pub fn get_8086_opcode_data() -> [Option<Opcode>; 256] {
+        let documented = Opcode::get_opcode_data();
+        let mut table: [Option<Opcode>; 256] = std::array::from_fn(|_| None);
+        table[0x0F] = Some(op(One, stack::pop, "pop").with_arg(RegEnum(Regs::CS)));
+        table[0x60..=0x6F].clone_from_slice(&documented[0x70..=0x7F]);
+        for code in [0xC0, 0xC1, 0xC8, 0xC9] {
+            table[code] = documented[code + 0x02].clone();
+        }
+        table[0xD0] = Some(Opcode::group(Two, shift_group_8086("setmo")).with_second_arg(Byte(1)).with_flags(SizeMismatch));
+        table[0xD1] = table[0xD0].clone();
+        table[0xD2] = Some(Opcode::group(Two, shift_group_8086("setmoc")).with_second_arg(Reg8(1)).with_flags(SizeMismatch | ForceNotDirection));
+        table[0xD3] = table[0xD2].clone();
+        table[0xD6] = Some(op(Zero, flags::salc, "salc"));
+        table[0xF6] = Some(Opcode::group(One, unary_group_8086()));
+        table[0xF7] = table[0xF6].clone();
+        table
+    }
+
+    // The second byte of the 286's two byte opcodes
+    pub fn get_0f_opcode_data() -> [Option<Opcode>; 256] {
+        let mut table: [Option<Opcode>; 256] = std::array::from_fn(|_| None);
+        table[0x01] = Some(Opcode::group(One, descriptor_table_group()).with_flags(ForceDWord));
+        table
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt::Formatter;
+use crate::cpu::model::Model;
+
+// Why InstructionDecoder gave up. Every variant keeps how many bytes were read, prefixes included,
+// so a listing can skip them and an error message can show them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownOpcode { opcode: u8, consumed: usize },
+    // The ModR/M byte is valid on its own but not for this opcode, like lea with a register operand
+    InvalidModRM { opcode: u8, mod_reg_rm: u8, consumed: usize },
+    // The input ended in the middle of the instruction, after `consumed` bytes
+    Truncated { consumed: usize },
+    Unsupported { opcode: u8, model: Model, consumed: usize },
+}
+
+impl DecodeError {
+    pub fn consumed(&self) -> usize {
+        match self {
+            DecodeError::UnknownOpcode { consumed, .. } => *consumed,
+            DecodeError::InvalidModRM { consumed, .. } => *consumed,
+            DecodeError::Truncated { consumed } => *consumed,
+            DecodeError::Unsupported { consumed, .. } => *consumed
+        }
+    }
+
+    pub fn opcode(&self) -> Option<u8> {
+        match self {
+            DecodeError::UnknownOpcode { opcode, .. } => Some(*opcode),
+            DecodeError::InvalidModRM { opcode, .. } => Some(*opcode),
+            DecodeError::Truncated { .. } => None,
+            DecodeError::Unsupported { opcode, .. } => Some(*opcode)
+        }
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode { opcode, .. } => write!(f, "unknown opcode 0x{:02X}", opcode),
+            DecodeError::InvalidModRM { opcode, mod_reg_rm, .. } => write!(f, "invalid ModR/M 0x{:02X} for opcode 0x{:02X}", mod_reg_rm, opcode),
+            DecodeError::Truncated { consumed } => write!(f, "truncated instruction: {} bytes available", consumed),
+            DecodeError::Unsupported { opcode, model, .. } => write!(f, "opcode 0x{:02X} is not supported on the {}", opcode, model)
+        }
+    }
+}
@@ -2,23 +2,41 @@ use crate::cpu::instruction::opcode::{Opcode, Mnemonic, NumArgs, OpcodeFlags};
 use enumflags2::BitFlags;
 use crate::cpu::{Regs, CPU};
 use crate::cpu::instruction::args::{DstArg, Size};
+use crate::cpu::model::Model;
+use crate::cpu::timing;
 use std::fmt::Formatter;
 
 pub mod opcode;
 pub mod actions;
 pub mod data;
 pub mod args;
+pub mod semantics;
+pub mod error;
+
+pub use crate::cpu::instruction::error::DecodeError;
 
 #[derive(Clone)]
 pub struct Instruction {
     pub flags: BitFlags<OpcodeFlags>,
     pub segment: Regs,
+    // Set when a segment prefix was decoded
+    pub segment_override: Option<Regs>,
+    // The opcode byte, after any segment prefix
+    pub opcode: u8,
+    // Set when a LOCK prefix was decoded
+    pub lock: bool,
+    // String instructions and the like carry their operand size in a placeholder operand that
+    // isn't part of their syntax
+    pub implicit_operands: bool,
     pub action: Option<opcode::OpcodeAction>,
     pub mnemonic: Option<Mnemonic>,
     pub src: Option<args::DstArg>,
     pub dst: Option<args::DstArg>,
+    // The third operand of imul r16, r/m16, imm
+    pub imm: Option<args::DstArg>,
     pub reg_bits: u8,
     pub length: usize,
+    // The clocks it takes on the model it was decoded for, see timing::cycles
     pub next_cycles: usize,
 }
 
@@ -32,21 +50,52 @@ impl Instruction {
         self.flags.contains(flag)
     }
 
+    // Loading SS and STI hold off interrupts until the next instruction is done, so
+    // `mov ss, ax / mov sp, bx` can't be interrupted halfway and `sti / iret` returns first
+    pub fn inhibits_interrupts(&self) -> bool {
+        self.opcode == 0xFB || matches!(self.dst, Some(args::DstArg::Reg(Regs::SS)))
+    }
+
     pub fn new() -> Self {
         Self {
             flags: BitFlags::empty(),
             segment: Regs::DS,
+            segment_override: None,
+            opcode: 0,
+            lock: false,
+            implicit_operands: false,
             action: None,
             mnemonic: None,
             src: None,
             dst: None,
+            imm: None,
             reg_bits: 0,
             length: 0,
             next_cycles: 0,
         }
     }
 
+    // Target offset of a relative jump, call or loop, given the offset of the next instruction
+    pub fn relative_target(&self, next_ip: u16) -> Option<u16> {
+        let mnemonic = self.mnemonic.clone()?.get(self.clone());
+        if !(mnemonic.starts_with('j') || mnemonic.starts_with("loop") || mnemonic == "call") || self.src.is_some() {
+            return None;
+        }
+        match self.dst {
+            Some(DstArg::Imm8(val)) => Some(next_ip.wrapping_add(CPU::sign_extend(val))),
+            Some(DstArg::Imm16(val)) => Some(next_ip.wrapping_add(val)),
+            _ => None
+        }
+    }
+
+    pub fn has_implicit_operands(&self) -> bool {
+        self.implicit_operands
+    }
+
     fn get_num_args(&self) -> NumArgs {
+        if self.has_implicit_operands() {
+            return NumArgs::Zero;
+        }
         let arg1 = if let Some(_) = self.dst { true } else { false };
         let arg2 = if let Some(_) = self.src { true } else { false };
         if arg1 && arg2 {
@@ -59,6 +108,12 @@ impl Instruction {
     }
 }
 
+impl Default for Instruction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl std::fmt::Debug for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Opcode")
@@ -66,6 +121,7 @@ impl std::fmt::Debug for Instruction {
             .field("segment", &self.segment)
             .field("dst", &self.dst)
             .field("src", &self.src)
+            .field("imm", &self.imm)
             .field("reg_bits", &self.reg_bits)
             .field("length", &self.length)
             .finish()
@@ -74,82 +130,105 @@ impl std::fmt::Debug for Instruction {
 
 impl std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.lock {
+            write!(f, "lock ")?;
+        }
         match self.get_num_args() {
-            NumArgs::Zero => write!(f, "{}", self.mnemonic.clone().unwrap().get(self.clone())),
-            NumArgs::One => write!(f, "{} {}", self.mnemonic.clone().unwrap().get(self.clone()), self.dst.clone().unwrap()),
-            NumArgs::Two => write!(f,"{} {}, {}", self.mnemonic.clone().unwrap().get(self.clone()), self.dst.clone().unwrap(), self.src.clone().unwrap())
+            NumArgs::Zero => write!(f, "{}", self.mnemonic.clone().unwrap().get(self.clone()))?,
+            NumArgs::One => write!(f, "{} {}", self.mnemonic.clone().unwrap().get(self.clone()), self.dst.clone().unwrap())?,
+            NumArgs::Two => write!(f,"{} {}, {}", self.mnemonic.clone().unwrap().get(self.clone()), self.dst.clone().unwrap(), self.src.clone().unwrap())?
+        };
+        match self.imm {
+            Some(imm) => write!(f, ", {}", imm),
+            None => Ok(())
         }
     }
 }
 
 pub struct InstructionDecoder<'a> {
-    opcodes: [Option<Opcode>; 256],
+    opcodes: &'a [Option<Opcode>; 256],
     ram: &'a [u8],
+    model: Model,
     ip: usize,
-    next_cycles: usize,
     opcode_data: Option<Opcode>,
     s: u8,
     d: u8,
     code: u8,
+    mod_reg_rm: u8,
+    // Set once a read goes past the end of the slice
+    truncated: bool,
     instruction: Instruction
 }
 
 impl<'a> InstructionDecoder<'a> {
-    pub fn new(opcodes: [Option<Opcode>; 256], ram: &'a[u8]) -> Self {
+    pub fn new(opcodes: &'a [Option<Opcode>; 256], ram: &'a[u8]) -> Self {
+        Self::with_model(opcodes, ram, Model::default())
+    }
+
+    // Opcodes that came after `model` are reported as DecodeError::Unsupported, like 0x0F on the
+    // 186, except on the 8086 and 8088, which run them as the older instructions they alias
+    pub fn with_model(opcodes: &'a [Option<Opcode>; 256], ram: &'a[u8], model: Model) -> Self {
         Self {
             opcodes,
             ram,
+            model,
             ip: 0,
-            next_cycles: 0,
             opcode_data: None,
             s: 0,
             d: 0,
             code: 0,
+            mod_reg_rm: 0,
+            truncated: false,
             instruction: Instruction::new()
         }
     }
 
-    pub fn get(mut self) -> Option<Instruction>{
+    pub fn get(mut self) -> Result<Instruction, DecodeError> {
         let code = self.read_ip();
         self.code = code;
         self.decode(code)
     }
 
-    pub fn decode(&mut self, mut code: u8) -> Option<Instruction> {
-        let opcode_data;
-        let seg;
-        match code {
-            0x26 => {
-                let op = self.read_ip();
-                code = op;
-                opcode_data = self.get_opcode(op)?;
-                seg = Some(Regs::ES);
-            },
-            0x2E => {
-                let op = self.read_ip();
-                code = op;
-                opcode_data = self.get_opcode(op)?;
-                seg = Some(Regs::CS);
-            },
-            0x36 => {
-                let op = self.read_ip();
-                code = op;
-                opcode_data = self.get_opcode(op)?;
-                seg = Some(Regs::SS);
-            },
-            0x3E => {
-                let op = self.read_ip();
-                code = op;
-                opcode_data = self.get_opcode(op)?;
-                seg = Some(Regs::DS);
-            },
-            _ => {
-                opcode_data = self.get_opcode(code)?;
-                seg = None;
+    // Reads past the end of the slice give zeros, so decoding carries on and the instruction is
+    // only thrown away at the end
+    pub fn decode(&mut self, code: u8) -> Result<Instruction, DecodeError> {
+        let decoded = self.decode_opcode(code);
+        if self.truncated {
+            return Err(DecodeError::Truncated { consumed: self.ram.len() });
+        }
+        decoded
+    }
+
+    fn decode_opcode(&mut self, mut code: u8) -> Result<Instruction, DecodeError> {
+        // Prefixes can come in any order. There's only one bus master, so LOCK changes nothing.
+        // The 8086 also takes 0xF1 for it.
+        let mut seg = None;
+        let mut rep = None;
+        loop {
+            match code {
+                0xF0 => self.instruction.lock = true,
+                0xF1 if self.model <= Model::I8086 => self.instruction.lock = true,
+                0xF2 | 0xF3 => rep = Some(code),
+                0x26 => seg = Some(Regs::ES),
+                0x2E => seg = Some(Regs::CS),
+                0x36 => seg = Some(Regs::SS),
+                0x3E => seg = Some(Regs::DS),
+                _ => break
             }
+            code = self.read_ip();
         }
+        // REP only repeats string instructions, which its opcode reads back as its operand. Anything
+        // else runs once, as if there were no prefix.
+        if let Some(prefix) = rep.filter(|_| self.is_string_operation(code)) {
+            self.ip -= 1;
+            code = prefix;
+        }
+        let opcode_data = self.get_opcode(code)?;
 
+        self.instruction.opcode = code;
+        self.instruction.segment_override = seg;
         self.instruction.flags = opcode_data.flags;
+        self.instruction.implicit_operands = matches!(opcode_data.num_args, NumArgs::Zero);
         self.instruction.action = Some(opcode_data.action.clone());
         self.instruction.mnemonic = Some(opcode_data.mnemonic.clone());
 
@@ -172,7 +251,21 @@ impl<'a> InstructionDecoder<'a> {
         let has_dst = if let Some(_) = self.instruction.dst { true } else { false };
 
         if !has_dst || !has_src {
-            self.get_args();
+            self.get_args().ok_or(DecodeError::InvalidModRM { opcode: code, mod_reg_rm: self.mod_reg_rm, consumed: self.ip })?;
+        }
+
+        if self.opcode_data.as_ref().unwrap().group.is_some() {
+            let sub_opcode = self.opcode_data.as_ref().unwrap().sub_opcode(self.instruction.reg_bits).cloned()
+                .ok_or(DecodeError::InvalidModRM { opcode: code, mod_reg_rm: self.mod_reg_rm, consumed: self.ip })?;
+            self.instruction.mnemonic = Some(Mnemonic::Static(sub_opcode.mnemonic));
+            self.instruction.action = Some(sub_opcode.action);
+        }
+
+        // lea and the far indirect call and jump need a memory operand
+        let far_indirect = code == 0xFF && (self.instruction.reg_bits == 0b011 || self.instruction.reg_bits == 0b101);
+        if (code == 0x8D && matches!(self.instruction.src, Some(DstArg::Reg16(_)) | Some(DstArg::Reg8(_))))
+            || (far_indirect && self.mod_reg_rm >> 6 == 0b11) {
+            return Err(DecodeError::InvalidModRM { opcode: code, mod_reg_rm: self.mod_reg_rm, consumed: self.ip });
         }
 
         self.instruction.length = self.ip;
@@ -185,7 +278,9 @@ impl<'a> InstructionDecoder<'a> {
             }
         });
 
-        Some(self.instruction.clone())
+        self.instruction.next_cycles = timing::cycles(self.model, &self.instruction);
+
+        Ok(self.instruction.clone())
     }
 
     fn check_ss(&self) -> bool {
@@ -202,20 +297,26 @@ impl<'a> InstructionDecoder<'a> {
         }
     }
 
-    fn get_opcode(&self, code: u8) -> Option<Opcode> {
-        Self::get_opcode_from_slice(&self.opcodes, code)
+    fn get_opcode(&mut self, code: u8) -> Result<Opcode, DecodeError> {
+        if !self.model.supports(code) && self.undocumented(code).is_none() {
+            return Err(DecodeError::Unsupported { opcode: code, model: self.model, consumed: self.ip });
+        }
+        // A table that doesn't take 0x0F itself gets the 286's two byte opcodes
+        if code == 0x0F && self.model >= Model::I80286 && self.opcodes[0x0F].is_none() {
+            let second = self.read_ip();
+            return Opcode::table_0f()[second as usize].clone()
+                .ok_or(DecodeError::UnknownOpcode { opcode: code, consumed: self.ip });
+        }
+        self.undocumented(code).or(self.opcodes[code as usize].as_ref()).cloned()
+            .ok_or(DecodeError::UnknownOpcode { opcode: code, consumed: self.ip })
     }
 
-    pub fn get_opcode_from_slice(opcodes: &[Option<Opcode>], opcode: u8) -> Option<Opcode> {
-        match opcodes[opcode as usize].clone() {
-            Some(op) => Some(op),
-            None => match opcodes[(opcode & 0xFE) as usize].clone() {
-                Some(op) => Some(op),
-                None => match opcodes[(opcode & 0xFD) as usize].clone() {
-                    Some(op) => Some(op),
-                    None => Some(opcodes[(opcode & 0xFC) as usize].clone()?)
-                }
-            }
+    // What the 8086 and 8088 run for opcodes that later models added, reserved or changed
+    fn undocumented(&self, code: u8) -> Option<&'static Opcode> {
+        if self.model <= Model::I8086 {
+            Opcode::table_8086()[code as usize].as_ref()
+        } else {
+            None
         }
     }
 
@@ -280,20 +381,23 @@ impl<'a> InstructionDecoder<'a> {
         }
     }
 
-    fn get_args(&mut self) {
+    fn get_args(&mut self) -> Option<()> {
         match self.opcode_data.clone().unwrap().num_args {
-            NumArgs::Two => self.get_two_args(),
+            NumArgs::Two => self.get_two_args()?,
             NumArgs::One => if let None = self.instruction.dst { self.get_one_arg() },
             NumArgs::Zero => ()
         }
+        Some(())
     }
 
-    fn get_two_args(&mut self) {
+    fn get_two_args(&mut self) -> Option<()> {
         let immediate = self.opcode_data.clone().unwrap().flags.contains(opcode::OpcodeFlags::Immediate);
         let force_dword = self.opcode_data.clone().unwrap().flags.contains(opcode::OpcodeFlags::ForceDWord);
         let segment = self.opcode_data.as_ref().unwrap().flags.contains(OpcodeFlags::Segment);
+        let not_direction = self.opcode_data.as_ref().unwrap().flags.contains(OpcodeFlags::ForceNotDirection);
 
         let mod_reg_rm = self.read_ip();
+        self.mod_reg_rm = mod_reg_rm;
         let (mod_bits, reg_bits, rm_bits) = Self::get_mod_reg_rm_bits(mod_reg_rm);
         self.instruction.reg_bits = reg_bits;
 
@@ -306,14 +410,18 @@ impl<'a> InstructionDecoder<'a> {
         let arg1 = if immediate {
             self.get_imm()
         } else if force_dword {
-            DstArg::Ptr(self.read_ip_word(), Size::DWord)
+            // les, lds and bound read a pair of words from memory, so a register doesn't encode
+            if mod_bits == 0b11 {
+                return None;
+            }
+            self.translate_mod_rm(mod_bits, rm_bits)
         } else if segment {
-            DstArg::reg_to_seg_arg(reg_bits).unwrap()
+            DstArg::reg_to_seg_arg(reg_bits)?
         } else {
             DstArg::reg_to_arg(reg_bits, self.s)
         };
 
-        if (self.d == 0 || immediate || force_dword) && !self.opcode_data.clone().unwrap().flags.contains(opcode::OpcodeFlags::ForceDirection) {
+        if (self.d == 0 || immediate || force_dword || not_direction) && !self.opcode_data.clone().unwrap().flags.contains(opcode::OpcodeFlags::ForceDirection) {
             if let None = self.instruction.src {
                 self.instruction.src.replace(arg1);
             }
@@ -328,6 +436,13 @@ impl<'a> InstructionDecoder<'a> {
                 self.instruction.dst.replace(arg1);
             }
         }
+
+        // The immediate of imul r16, r/m16, imm comes after any displacement, like the one of TEST
+        if self.instruction.opcode == 0x69 || self.instruction.opcode == 0x6B {
+            let imm = self.get_imm();
+            self.instruction.imm = Some(imm);
+        }
+        Some(())
     }
 
     fn get_one_arg(&mut self) {
@@ -349,17 +464,20 @@ impl<'a> InstructionDecoder<'a> {
                 self.instruction.dst.replace(new_dst);
             } else {
                 let mod_reg_rm = self.read_ip();
+                self.mod_reg_rm = mod_reg_rm;
                 let (mod_bits, reg_bits, rm_bits) = Self::get_mod_reg_rm_bits(mod_reg_rm);
                 self.instruction.reg_bits = reg_bits;
 
-                // Special case for TEST in mul_dispatch, because it needs an immediate while others don't
-                if self.code & 0xFE == 0xF6 && reg_bits == 0x00 {
+                let new_dst = self.translate_mod_rm(mod_bits, rm_bits);
+                self.instruction.dst.replace(new_dst);
+
+                // Special case for TEST in mul_dispatch, because it needs an immediate while others don't.
+                // The immediate comes after any displacement. The 8086 also has it on /1.
+                let test = self.opcode_data.as_ref().unwrap().sub_opcode(reg_bits).is_some_and(|sub| sub.mnemonic == "test");
+                if self.instruction.opcode & 0xFE == 0xF6 && test {
                     let src = self.get_imm();
                     self.instruction.src = Some(src);
                 }
-
-                let new_dst = self.translate_mod_rm(mod_bits, rm_bits);
-                self.instruction.dst.replace(new_dst);
             }
         }
     }
@@ -370,7 +488,7 @@ impl<'a> InstructionDecoder<'a> {
 
     fn translate_mod_rm(&mut self, mod_bits: u8, rm_bits: u8) -> DstArg {
         if mod_bits == 0b00 && rm_bits == 0b110 {
-            if self.s == 1 { DstArg::Ptr(self.read_ip_word(), Size::Word) } else { DstArg::Ptr(self.read_ip_word(), Size::Byte) }
+            DstArg::Ptr(self.read_ip_word(), Size::from_s(self.s))
         } else {
             let (reg1, reg2) = match rm_bits {
                 0b000 => (Regs::BX, Some(Regs::SI)),
@@ -386,7 +504,7 @@ impl<'a> InstructionDecoder<'a> {
 
             let offset = match mod_bits {
                 0b00 => None,
-                0b01 => Some(self.read_ip() as u16),
+                0b01 => Some(CPU::sign_extend(self.read_ip())),
                 0b10 => Some(self.read_ip_word()),
                 0b11 => return DstArg::reg_to_arg(rm_bits, self.s),
                 _ => panic!("Invalid mod_bits value")
@@ -423,16 +541,26 @@ impl<'a> InstructionDecoder<'a> {
             opcode::Placeholder::Reg16(reg) => DstArg::Reg16(reg),
             opcode::Placeholder::Byte(val) => DstArg::Imm8(val),
             opcode::Placeholder::Word(val) => DstArg::Imm16(val),
-            opcode::Placeholder::Ptr => DstArg::Ptr(self.read_ip_word(), Size::Word),
+            opcode::Placeholder::Ptr => DstArg::Ptr(self.read_ip_word(), Size::from_s(self.s)),
             opcode::Placeholder::Opcode => DstArg::Opcode(self.read_ip()),
         }
     }
 
+    // 0x6C to 0x6F alias the conditional jumps before the 186
+    fn is_string_operation(&self, code: u8) -> bool {
+        matches!(code, 0xA4..=0xA7 | 0xAA..=0xAF) || (matches!(code, 0x6C..=0x6F) && self.model.supports(code))
+    }
+
     fn read_ip(&mut self) -> u8 {
         let tmp = self.ip;
         self.ip += 1;
-        self.next_cycles += 1;
-        self.ram[tmp]
+        match self.ram.get(tmp) {
+            Some(byte) => *byte,
+            None => {
+                self.truncated = true;
+                0
+            }
+        }
     }
 
     fn read_ip_word(&mut self) -> u16 {
@@ -1,7 +1,7 @@
 use enumflags2::{BitFlags, bitflags};
 use crate::cpu::{CPU, Regs};
 use std::option::Option::Some;
-use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
 use crate::cpu::instruction::Instruction;
 
 #[bitflags]
@@ -39,7 +39,7 @@ pub enum Placeholder {
     Opcode
 }
 
-pub type MnemonicFunc = Rc<dyn Fn(Instruction) -> String>;
+pub type MnemonicFunc = Arc<dyn Fn(Instruction) -> String + Send + Sync>;
 
 #[derive(Clone)]
 pub enum Mnemonic {
@@ -56,7 +56,16 @@ impl Mnemonic {
     }
 }
 
-pub type OpcodeAction = Rc<dyn Fn(&mut CPU, Instruction) -> usize>;
+pub type OpcodeAction = Arc<dyn Fn(&mut CPU, Instruction) -> usize + Send + Sync>;
+
+// One operation of a group opcode, picked by the reg field of the ModR/M byte
+#[derive(Clone)]
+pub struct SubOpcode {
+    pub mnemonic: String,
+    pub action: OpcodeAction
+}
+
+pub type Group = [Option<SubOpcode>; 8];
 
 #[derive(Clone)]
 pub struct Opcode {
@@ -66,10 +75,96 @@ pub struct Opcode {
     pub flags: BitFlags<OpcodeFlags>,
     pub segment: Option<Regs>,
     pub action: OpcodeAction,
-    pub mnemonic: Mnemonic
+    pub mnemonic: Mnemonic,
+    // Set for opcodes like 0x80, whose mnemonic and action come from the sub-opcode
+    pub group: Option<Arc<Group>>
 }
 
+static OPCODE_TABLE: OnceLock<[Option<Opcode>; 256]> = OnceLock::new();
+static OPCODE_TABLE_8086: OnceLock<[Option<Opcode>; 256]> = OnceLock::new();
+static OPCODE_TABLE_0F: OnceLock<[Option<Opcode>; 256]> = OnceLock::new();
+
 impl Opcode {
+    // Built on first use and shared by every CPU, on any thread
+    pub fn table() -> &'static [Option<Opcode>; 256] {
+        OPCODE_TABLE.get_or_init(Opcode::get_opcode_data)
+    }
+
+    // The undocumented opcodes of the 8086 and 8088, see get_8086_opcode_data
+    pub fn table_8086() -> &'static [Option<Opcode>; 256] {
+        OPCODE_TABLE_8086.get_or_init(Opcode::get_8086_opcode_data)
+    }
+
+    // Looked up by the byte after 0x0F from the 286 on
+    pub fn table_0f() -> &'static [Option<Opcode>; 256] {
+        OPCODE_TABLE_0F.get_or_init(Opcode::get_0f_opcode_data)
+    }
+
+    pub fn new(num_args: NumArgs, action: OpcodeAction, mnemonic: &str) -> Self {
+        Self {
+            num_args,
+            shorthand1: None,
+            shorthand2: None,
+            flags: BitFlags::empty(),
+            segment: None,
+            action,
+            mnemonic: Mnemonic::Static(String::from(mnemonic)),
+            group: None
+        }
+    }
+
+    // The decoder replaces the mnemonic and action with those of the sub-opcode
+    pub fn group(num_args: NumArgs, group: Group) -> Self {
+        Self {
+            group: Some(Arc::new(group)),
+            ..Self::new(num_args, Arc::new(|_, _| unreachable!("group opcodes run their sub-opcode")), "")
+        }
+    }
+
+    pub fn with_arg(mut self, shorthand1: Placeholder) -> Self {
+        self.shorthand1 = Some(shorthand1);
+        self
+    }
+
+    // For opcodes whose first operand comes from the ModR/M byte, like the shift count of 0xD0
+    pub fn with_second_arg(mut self, shorthand2: Placeholder) -> Self {
+        self.shorthand2 = Some(shorthand2);
+        self
+    }
+
+    pub fn with_args(mut self, shorthand1: Placeholder, shorthand2: Placeholder) -> Self {
+        self.shorthand1 = Some(shorthand1);
+        self.shorthand2 = Some(shorthand2);
+        self
+    }
+
+    pub fn with_flags(mut self, flags: impl Into<BitFlags<OpcodeFlags>>) -> Self {
+        self.flags = flags.into();
+        self
+    }
+
+    pub fn with_segment(mut self, segment: Regs) -> Self {
+        self.segment = Some(segment);
+        self
+    }
+
+    pub fn with_mnemonic(mut self, mnemonic: Mnemonic) -> Self {
+        self.mnemonic = mnemonic;
+        self
+    }
+
+    pub fn sub_opcode(&self, reg_bits: u8) -> Option<&SubOpcode> {
+        self.group.as_ref()?[reg_bits as usize & 0x07].as_ref()
+    }
+
+    // What runs for a ModR/M byte with the given reg field, None for a reserved sub-opcode
+    pub fn action_for(&self, reg_bits: u8) -> Option<OpcodeAction> {
+        match self.group {
+            Some(_) => Some(self.sub_opcode(reg_bits)?.action.clone()),
+            None => Some(self.action.clone())
+        }
+    }
+
     pub fn has_shorthand(&self) -> bool {
         if let Some(_) = self.shorthand1 {
             true
@@ -0,0 +1,460 @@
+use enumflags2::BitFlags;
+use crate::cpu::{Regs, Reg8, Reg16, Flag, Flags};
+use crate::cpu::instruction::Instruction;
+use crate::cpu::instruction::args::{DstArg, Size};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlowKind {
+    // Continues with the next instruction
+    Fallthrough,
+    Jump,
+    ConditionalJump,
+    Call,
+    Return,
+    Interrupt,
+    Halt
+}
+
+impl FlowKind {
+    // Whether the next instruction can run right after this one (calls and interrupts return to it)
+    pub fn falls_through(self) -> bool {
+        !matches!(self, FlowKind::Jump | FlowKind::Return | FlowKind::Halt)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite
+}
+
+impl Access {
+    pub fn reads(self) -> bool {
+        self != Access::Write
+    }
+
+    pub fn writes(self) -> bool {
+        self != Access::Read
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub segment: Regs,
+    pub size: Size,
+    pub access: Access,
+}
+
+// What an instruction reads, writes and where it goes next. 8-bit registers are reported as the
+// register that holds them, and IP is left out since `flow` describes it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Semantics {
+    pub regs_read: Vec<Regs>,
+    pub regs_written: Vec<Regs>,
+    pub flags_read: Flags,
+    pub flags_written: Flags,
+    pub memory: Vec<MemoryAccess>,
+    pub flow: FlowKind,
+    // Segment:offset of jumps and calls whose destination is encoded in the instruction
+    pub targets: Vec<(u16, u16)>,
+}
+
+fn arithmetic_flags() -> BitFlags<Flag> {
+    Flag::Overflow | Flag::Sign | Flag::Zero | Flag::AuxCarry | Flag::Parity | Flag::Carry
+}
+
+fn operand_size(arg: DstArg) -> Option<Size> {
+    match arg {
+        DstArg::Reg8(_) => Some(Size::Byte),
+        DstArg::Reg16(_) | DstArg::Reg(_) => Some(Size::Word),
+        DstArg::Ptr(_, size) | DstArg::RegPtr(_, size) | DstArg::RegPtrImm(_, _, size)
+        | DstArg::RegPtrOff(_, _, size) | DstArg::RegPtrOffImm(_, _, _, size) => Some(size),
+        _ => None
+    }
+}
+
+impl Semantics {
+    fn new() -> Self {
+        Self {
+            regs_read: Vec::new(),
+            regs_written: Vec::new(),
+            flags_read: Flags::empty(),
+            flags_written: Flags::empty(),
+            memory: Vec::new(),
+            flow: FlowKind::Fallthrough,
+            targets: Vec::new(),
+        }
+    }
+
+    pub fn reads_reg(&self, reg: Regs) -> bool {
+        self.regs_read.contains(&reg)
+    }
+
+    pub fn writes_reg(&self, reg: Regs) -> bool {
+        self.regs_written.contains(&reg)
+    }
+
+    pub fn reads_memory(&self) -> bool {
+        self.memory.iter().any(|access| access.access.reads())
+    }
+
+    pub fn writes_memory(&self) -> bool {
+        self.memory.iter().any(|access| access.access.writes())
+    }
+
+    fn read(&mut self, regs: &[Regs]) {
+        for reg in regs {
+            if !self.regs_read.contains(reg) {
+                self.regs_read.push(*reg);
+            }
+        }
+    }
+
+    fn write(&mut self, regs: &[Regs]) {
+        for reg in regs {
+            if !self.regs_written.contains(reg) {
+                self.regs_written.push(*reg);
+            }
+        }
+    }
+
+    fn read_flags(&mut self, flags: impl Into<BitFlags<Flag>>) {
+        self.flags_read = Flags::from_bits(self.flags_read.bits() | flags.into().bits());
+    }
+
+    fn write_flags(&mut self, flags: impl Into<BitFlags<Flag>>) {
+        self.flags_written = Flags::from_bits(self.flags_written.bits() | flags.into().bits());
+    }
+
+    fn access(&mut self, segment: Regs, size: Size, access: Access) {
+        self.memory.push(MemoryAccess { segment, size, access });
+    }
+
+    fn operand(&mut self, arg: Option<DstArg>, access: Access, segment: Regs, address_only: bool) {
+        let reg = match arg {
+            Some(DstArg::Reg8(id)) => Reg8::from_index(id).map(|reg| Regs::from(reg.reg16())),
+            Some(DstArg::Reg16(id)) => Reg16::from_index(id).map(Regs::from),
+            Some(DstArg::Reg(reg)) => Some(reg),
+            _ => None
+        };
+        if let Some(reg) = reg {
+            if access.reads() {
+                self.read(&[reg]);
+            }
+            if access.writes() {
+                self.write(&[reg]);
+            }
+            return;
+        }
+
+        let (bases, size) = match arg {
+            Some(DstArg::Ptr(_, size)) => (vec![], size),
+            Some(DstArg::RegPtr(reg, size)) | Some(DstArg::RegPtrImm(reg, _, size)) => (vec![reg], size),
+            Some(DstArg::RegPtrOff(reg1, reg2, size)) | Some(DstArg::RegPtrOffImm(reg1, reg2, _, size)) => (vec![reg1, reg2], size),
+            _ => return
+        };
+        self.read(&bases);
+        if !address_only {
+            self.access(segment, size, access);
+        }
+    }
+
+    fn push(&mut self, size: Size) {
+        self.read(&[Regs::SP]);
+        self.write(&[Regs::SP]);
+        self.access(Regs::SS, size, Access::Write);
+    }
+
+    fn pop(&mut self, size: Size) {
+        self.read(&[Regs::SP]);
+        self.write(&[Regs::SP]);
+        self.access(Regs::SS, size, Access::Read);
+    }
+
+    // MOVS and friends. `segment` is where DS:SI points, which a prefix can change; ES:DI can't be overridden.
+    fn string_op(&mut self, opcode: u8, segment: Regs) {
+        let size = if opcode & 1 == 1 { Size::Word } else { Size::Byte };
+        self.read_flags(Flag::Direction);
+        match opcode {
+            0xA4 | 0xA5 => {
+                self.read(&[Regs::SI, Regs::DI]);
+                self.write(&[Regs::SI, Regs::DI]);
+                self.access(segment, size, Access::Read);
+                self.access(Regs::ES, size, Access::Write);
+            }
+            0xA6 | 0xA7 => {
+                self.read(&[Regs::SI, Regs::DI]);
+                self.write(&[Regs::SI, Regs::DI]);
+                self.write_flags(arithmetic_flags());
+                self.access(segment, size, Access::Read);
+                self.access(Regs::ES, size, Access::Read);
+            }
+            0xAA | 0xAB => {
+                self.read(&[Regs::AX, Regs::DI]);
+                self.write(&[Regs::DI]);
+                self.access(Regs::ES, size, Access::Write);
+            }
+            0xAC | 0xAD => {
+                self.read(&[Regs::SI]);
+                self.write(&[Regs::AX, Regs::SI]);
+                self.access(segment, size, Access::Read);
+            }
+            0xAE | 0xAF => {
+                self.read(&[Regs::AX, Regs::DI]);
+                self.write(&[Regs::DI]);
+                self.write_flags(arithmetic_flags());
+                self.access(Regs::ES, size, Access::Read);
+            }
+            0x6C | 0x6D => {
+                self.read(&[Regs::DX, Regs::DI]);
+                self.write(&[Regs::DI]);
+                self.access(Regs::ES, size, Access::Write);
+            }
+            0x6E | 0x6F => {
+                self.read(&[Regs::DX, Regs::SI]);
+                self.write(&[Regs::SI]);
+                self.access(segment, size, Access::Read);
+            }
+            _ => ()
+        }
+    }
+
+    // Flags tested by the conditional jumps 0x70-0x7F
+    fn condition_flags(opcode: u8) -> BitFlags<Flag> {
+        match (opcode & 0x0F) >> 1 {
+            0 => Flag::Overflow.into(),
+            1 => Flag::Carry.into(),
+            2 => Flag::Zero.into(),
+            3 => Flag::Carry | Flag::Zero,
+            4 => Flag::Sign.into(),
+            5 => Flag::Parity.into(),
+            6 => Flag::Sign | Flag::Overflow,
+            _ => Flag::Sign | Flag::Overflow | Flag::Zero
+        }
+    }
+}
+
+fn is_string_op(opcode: u8) -> bool {
+    matches!(opcode, 0x6C..=0x6F | 0xA4..=0xA7 | 0xAA..=0xAF)
+}
+
+impl Instruction {
+    // `address` is the CS:IP of the instruction, used to resolve static branch targets
+    pub fn semantics(&self, address: (u16, u16)) -> Semantics {
+        let mut semantics = Semantics::new();
+        let mnemonic = self.mnemonic.clone().map_or(String::new(), |mnemonic| mnemonic.get(self.clone()));
+        let all_flags = BitFlags::<Flag>::all();
+
+        // A REP prefix runs the string instruction in its operand until CX runs out
+        if let Some(DstArg::Opcode(op)) = self.dst {
+            semantics.string_op(op, self.segment);
+            semantics.read(&[Regs::CX]);
+            semantics.write(&[Regs::CX]);
+            if matches!(op, 0xA6 | 0xA7 | 0xAE | 0xAF) {
+                semantics.read_flags(Flag::Zero);
+            }
+            return semantics;
+        }
+
+        let (dst, src) = match mnemonic.as_str() {
+            "cmp" | "test" => (Access::Read, Access::Read),
+            "imul" if self.imm.is_some() => (Access::Write, Access::Read),
+            "mov" | "lea" | "les" | "lds" | "pop" | "in" | "sidt" => (Access::Write, Access::Read),
+            "xchg" => (Access::ReadWrite, Access::ReadWrite),
+            "add" | "or" | "adc" | "sbb" | "and" | "sub" | "xor" | "inc" | "dec" | "not" | "neg"
+            | "rol" | "ror" | "rcl" | "rcr" | "sal" | "shl" | "shr" | "sar" => (Access::ReadWrite, Access::Read),
+            _ => (Access::Read, Access::Read)
+        };
+        if is_string_op(self.opcode) {
+            semantics.string_op(self.opcode, self.segment);
+        } else if !self.has_implicit_operands() {
+            semantics.operand(self.dst, dst, self.segment, false);
+            semantics.operand(self.src, src, self.segment, mnemonic == "lea");
+        }
+
+        let size = self.dst.and_then(operand_size).unwrap_or(Size::Word);
+        match mnemonic.as_str() {
+            "add" | "sub" | "cmp" | "neg" | "and" | "or" | "xor" | "test" | "sal" | "shl" | "shr" | "sar" => semantics.write_flags(arithmetic_flags()),
+            "adc" | "sbb" => {
+                semantics.read_flags(Flag::Carry);
+                semantics.write_flags(arithmetic_flags());
+            }
+            "inc" | "dec" => semantics.write_flags(arithmetic_flags() & !Flag::Carry),
+            "rol" | "ror" => semantics.write_flags(Flag::Overflow | Flag::Carry),
+            "rcl" | "rcr" => {
+                semantics.read_flags(Flag::Carry);
+                semantics.write_flags(Flag::Overflow | Flag::Carry);
+            }
+            // imul r16, r/m16, imm leaves AX and DX alone
+            "imul" if self.imm.is_some() => semantics.write_flags(arithmetic_flags()),
+            "mul" | "imul" | "div" | "idiv" => {
+                semantics.read(&[Regs::AX]);
+                semantics.write(&[Regs::AX]);
+                if let Size::Word = size {
+                    if mnemonic.ends_with("div") {
+                        semantics.read(&[Regs::DX]);
+                    }
+                    semantics.write(&[Regs::DX]);
+                }
+                semantics.write_flags(arithmetic_flags());
+            }
+            "daa" | "das" | "aaa" | "aas" => {
+                semantics.read(&[Regs::AX]);
+                semantics.write(&[Regs::AX]);
+                semantics.read_flags(Flag::AuxCarry | Flag::Carry);
+                semantics.write_flags(arithmetic_flags());
+            }
+            "aam" | "aad" => {
+                semantics.read(&[Regs::AX]);
+                semantics.write(&[Regs::AX]);
+                semantics.write_flags(arithmetic_flags());
+            }
+            "cbw" => {
+                semantics.read(&[Regs::AX]);
+                semantics.write(&[Regs::AX]);
+            }
+            "cwd" => {
+                semantics.read(&[Regs::AX]);
+                semantics.write(&[Regs::DX]);
+            }
+            "xlat" => {
+                semantics.read(&[Regs::AX, Regs::BX]);
+                semantics.write(&[Regs::AX]);
+                semantics.access(self.segment, Size::Byte, Access::Read);
+            }
+            "sahf" => {
+                semantics.read(&[Regs::AX]);
+                semantics.write_flags(arithmetic_flags() & !Flag::Overflow);
+            }
+            "lahf" => {
+                semantics.read_flags(arithmetic_flags() & !Flag::Overflow);
+                semantics.write(&[Regs::AX]);
+            }
+            "les" => semantics.write(&[Regs::ES]),
+            "lds" => semantics.write(&[Regs::DS]),
+            "push" => semantics.push(Size::Word),
+            "pop" => semantics.pop(Size::Word),
+            "pushf" => {
+                semantics.read_flags(all_flags);
+                semantics.push(Size::Word);
+            }
+            "popf" => {
+                semantics.write_flags(all_flags);
+                semantics.pop(Size::Word);
+            }
+            // One access per word moved, SP included even though POPA throws it away
+            "pusha" => {
+                semantics.read(&[Regs::AX, Regs::CX, Regs::DX, Regs::BX, Regs::SP, Regs::BP, Regs::SI, Regs::DI]);
+                (0..8).for_each(|_| semantics.push(Size::Word));
+            }
+            "popa" => {
+                semantics.write(&[Regs::AX, Regs::CX, Regs::DX, Regs::BX, Regs::BP, Regs::SI, Regs::DI]);
+                (0..8).for_each(|_| semantics.pop(Size::Word));
+            }
+            "enter" => {
+                semantics.read(&[Regs::BP]);
+                semantics.write(&[Regs::BP]);
+                semantics.push(Size::Word);
+                // Each enclosing frame pointer is read from the old frame and pushed, then the new one
+                let level = match self.src {
+                    Some(DstArg::Imm8(level)) => level & 0x1F,
+                    _ => 0
+                };
+                for _ in 1..level {
+                    semantics.access(Regs::SS, Size::Word, Access::Read);
+                    semantics.push(Size::Word);
+                }
+                if level > 0 {
+                    semantics.push(Size::Word);
+                }
+            }
+            "leave" => {
+                semantics.read(&[Regs::BP]);
+                semantics.write(&[Regs::SP, Regs::BP]);
+                semantics.access(Regs::SS, Size::Word, Access::Read);
+            }
+            "clc" | "stc" => semantics.write_flags(Flag::Carry),
+            "cmc" => {
+                semantics.read_flags(Flag::Carry);
+                semantics.write_flags(Flag::Carry);
+            }
+            "cli" | "sti" => semantics.write_flags(Flag::Interrupt),
+            "cld" | "std" => semantics.write_flags(Flag::Direction),
+            _ => ()
+        }
+
+        let next_ip = address.1.wrapping_add(self.length as u16);
+        if let Some(target) = self.relative_target(next_ip) {
+            semantics.targets.push((address.0, target));
+        }
+        if let (0xEA | 0x9A, Some(DstArg::Imm32(pointer))) = (self.opcode, self.dst) {
+            semantics.targets.push(((pointer >> 16) as u16, pointer as u16));
+        }
+
+        let far = matches!(self.opcode, 0x9A | 0xEA | 0xCA | 0xCB) || (self.opcode == 0xFF && (self.reg_bits == 0b011 || self.reg_bits == 0b101));
+        semantics.flow = match self.opcode {
+            0x70..=0x7F => {
+                semantics.read_flags(Semantics::condition_flags(self.opcode));
+                FlowKind::ConditionalJump
+            }
+            0xE0..=0xE2 => {
+                semantics.read(&[Regs::CX]);
+                semantics.write(&[Regs::CX]);
+                if self.opcode != 0xE2 {
+                    semantics.read_flags(Flag::Zero);
+                }
+                FlowKind::ConditionalJump
+            }
+            0xE3 => {
+                semantics.read(&[Regs::CX]);
+                FlowKind::ConditionalJump
+            }
+            0xE9..=0xEB => FlowKind::Jump,
+            0xE8 | 0x9A => FlowKind::Call,
+            0xFF if self.reg_bits == 0b010 || self.reg_bits == 0b011 => FlowKind::Call,
+            0xFF if self.reg_bits == 0b100 || self.reg_bits == 0b101 => FlowKind::Jump,
+            0xC2 | 0xC3 | 0xCA | 0xCB | 0xCF => FlowKind::Return,
+            0xCC..=0xCE => FlowKind::Interrupt,
+            0xF4 => FlowKind::Halt,
+            _ => FlowKind::Fallthrough
+        };
+
+        match semantics.flow {
+            FlowKind::Call => {
+                if far {
+                    semantics.read(&[Regs::CS]);
+                }
+                semantics.push(if far { Size::DWord } else { Size::Word });
+            }
+            FlowKind::Return => {
+                let iret = self.opcode == 0xCF;
+                if iret {
+                    semantics.write_flags(all_flags);
+                }
+                semantics.pop(if far || iret { Size::DWord } else { Size::Word });
+                // IRET pops FLAGS after CS:IP
+                if iret {
+                    semantics.pop(Size::Word);
+                }
+            }
+            FlowKind::Interrupt => {
+                if self.opcode == 0xCE {
+                    semantics.read_flags(Flag::Overflow);
+                }
+                semantics.read(&[Regs::CS]);
+                semantics.read_flags(all_flags);
+                semantics.write_flags(Flag::Interrupt | Flag::Trap);
+                // FLAGS, then CS:IP
+                semantics.push(Size::Word);
+                semantics.push(Size::DWord);
+            }
+            _ => ()
+        }
+        if far || matches!(semantics.flow, FlowKind::Interrupt) || self.opcode == 0xCF {
+            semantics.write(&[Regs::CS]);
+        }
+
+        semantics
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt::Formatter;
+
+// Ordered by instruction set, so `model >= Model::I80186` means the 186 instructions are there.
+// Everything the opcode table knows is decoded by default.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, PartialOrd, Ord, Debug, Default)]
+pub enum Model {
+    I8088,
+    I8086,
+    I80186,
+    #[default]
+    I80286
+}
+
+impl Model {
+    pub const ALL: [Model; 4] = [Model::I8088, Model::I8086, Model::I80186, Model::I80286];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "8088" => Some(Model::I8088),
+            "8086" => Some(Model::I8086),
+            "186" | "80186" | "188" | "80188" => Some(Model::I80186),
+            "286" | "80286" => Some(Model::I80286),
+            _ => None
+        }
+    }
+
+    // The first model that decodes `opcode`. 0x0F only starts a two byte opcode from the 286 on.
+    pub fn introducing(opcode: u8) -> Model {
+        match opcode {
+            0x60..=0x62 | 0x68..=0x6F | 0xC0 | 0xC1 | 0xC8 | 0xC9 => Model::I80186,
+            0x0F => Model::I80286,
+            _ => Model::I8088
+        }
+    }
+
+    pub fn supports(self, opcode: u8) -> bool {
+        self >= Self::introducing(opcode)
+    }
+}
+
+impl std::fmt::Display for Model {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Model::I8088 => "8088",
+            Model::I8086 => "8086",
+            Model::I80186 => "80186",
+            Model::I80286 => "80286"
+        })
+    }
+}
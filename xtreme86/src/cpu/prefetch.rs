@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use crate::cpu::CPU;
+use crate::cpu::model::Model;
+use crate::cpu::instruction::args::Size;
+use crate::cpu::instruction::semantics::{Access, MemoryAccess};
+
+// The bus interface unit fetches code ahead of the execution unit whenever the queue has room.
+// Instructions are decoded from the queue, so code that changes the bytes right after itself
+// keeps running the old ones until a jump flushes the queue.
+#[derive(Clone, Debug)]
+pub struct PrefetchQueue {
+    bytes: VecDeque<u8>,
+    size: usize,
+    bus_width: usize,
+    // CS:IP of the first queued byte
+    head: (u16, u16),
+    clocks: usize,
+    // Clocks the execution unit still has the bus for
+    held: usize,
+}
+
+impl PrefetchQueue {
+    pub const BUS_CYCLE: usize = 4;
+
+    // The 8088 has an 8 bit bus and a 4 byte queue, the others fetch words into 6 bytes
+    pub fn for_model(model: Model) -> Self {
+        let (size, bus_width) = match model {
+            Model::I8088 => (4, 1),
+            _ => (6, 2)
+        };
+        Self {
+            bytes: VecDeque::with_capacity(size),
+            size,
+            bus_width,
+            head: (0, 0),
+            clocks: 0,
+            held: 0,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.iter().copied().collect()
+    }
+
+    pub fn head(&self) -> (u16, u16) {
+        self.head
+    }
+
+    // Throws the queued bytes away and starts fetching at `address`
+    pub fn flush(&mut self, address: (u16, u16)) {
+        self.bytes.clear();
+        self.head = address;
+        self.clocks = 0;
+        self.held = 0;
+    }
+
+    // The execution unit takes the bus from the queue for a bus cycle per transfer of its memory
+    // accesses, a read and a write for read-modify-write operands
+    pub fn hold(&mut self, accesses: &[MemoryAccess]) {
+        let transfers: usize = accesses.iter().map(|access| {
+            let bytes: usize = match access.size {
+                Size::Byte => 1,
+                Size::Word => 2,
+                Size::DWord => 4
+            };
+            let transfers = bytes.div_ceil(self.bus_width);
+            if access.access == Access::ReadWrite { 2 * transfers } else { transfers }
+        }).sum();
+        self.held += transfers * Self::BUS_CYCLE;
+    }
+
+    // One clock of the bus. A fetch takes a whole bus cycle and a word bus only reads aligned words.
+    pub fn clock(&mut self, ram: &[u8]) {
+        if self.held > 0 {
+            self.held -= 1;
+            return;
+        }
+        let fetch = self.fetch_offset();
+        let count = if self.bus_width == 2 && fetch % 2 == 1 { 1 } else { self.bus_width };
+        if self.size - self.bytes.len() < count {
+            self.clocks = 0;
+            return;
+        }
+        self.clocks += 1;
+        if self.clocks < Self::BUS_CYCLE {
+            return;
+        }
+        self.clocks = 0;
+        for i in 0..count {
+            let address = CPU::physical_address(self.head.0, fetch.wrapping_add(i as u16)) as usize;
+            self.bytes.push_back(ram.get(address).copied().unwrap_or(0xFF));
+        }
+    }
+
+    // The queued bytes followed by what is in memory after them, enough for any instruction
+    pub fn code(&self, ram: &[u8]) -> Vec<u8> {
+        let fetch = self.fetch_offset();
+        let mut code = self.bytes();
+        code.extend((0..16u16)
+            .map(|i| CPU::physical_address(self.head.0, fetch.wrapping_add(i)) as usize)
+            .map_while(|address| ram.get(address).copied()));
+        code
+    }
+
+    // The execution unit took `length` bytes, any it found missing were read from memory
+    pub fn consume(&mut self, length: usize) {
+        let queued = length.min(self.bytes.len());
+        self.bytes.drain(..queued);
+        self.head.1 = self.head.1.wrapping_add(length as u16);
+    }
+
+    fn fetch_offset(&self) -> u16 {
+        self.head.1.wrapping_add(self.bytes.len() as u16)
+    }
+}
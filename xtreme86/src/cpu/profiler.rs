@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use crate::symbols::SymbolTable;
+
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum CallKind {
+    Near,
+    Far,
+    Interrupt(u8)
+}
+
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum ReturnKind {
+    Near,
+    Far,
+    Interrupt
+}
+
+#[derive(Copy, Clone, Debug)]
+enum CallEvent {
+    Call(CallKind, (u16, u16)),
+    Return(usize)
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ProfileEntry {
+    pub cs: u16,
+    pub ip: u16,
+    pub cycles: usize,
+    pub instructions: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RoutineEntry {
+    pub kind: CallKind,
+    pub cs: u16,
+    pub ip: u16,
+    pub calls: usize,
+    pub self_cycles: usize,
+    pub total_cycles: usize,
+    pub instructions: usize,
+}
+
+struct Node {
+    kind: Option<CallKind>,
+    entry: (u16, u16),
+    parent: usize,
+    children: HashMap<(CallKind, (u16, u16)), usize>,
+    calls: usize,
+    cycles: usize,
+    instructions: usize,
+}
+
+impl Node {
+    fn new(kind: Option<CallKind>, entry: (u16, u16), parent: usize) -> Self {
+        Self {
+            kind,
+            entry,
+            parent,
+            children: HashMap::new(),
+            calls: 0,
+            cycles: 0,
+            instructions: 0,
+        }
+    }
+
+    fn name(&self, symbols: &SymbolTable) -> String {
+        let routine = symbols.describe(self.entry.0, self.entry.1)
+            .unwrap_or_else(|| format!("{:04X}:{:04X}", self.entry.0, self.entry.1));
+        match self.kind {
+            Some(CallKind::Interrupt(num)) => format!("int_{:02X}h@{}", num, routine),
+            Some(_) => routine,
+            None => String::from("root")
+        }
+    }
+}
+
+pub struct Profiler {
+    flat: HashMap<(u16, u16), ProfileEntry>,
+    nodes: Vec<Node>,
+    current: usize,
+    pending: Vec<CallEvent>,
+    total_cycles: usize,
+    total_instructions: usize,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            flat: HashMap::new(),
+            nodes: vec![Node::new(None, (0, 0), 0)],
+            current: 0,
+            pending: Vec::new(),
+            total_cycles: 0,
+            total_instructions: 0,
+        }
+    }
+
+    // Call graph changes are applied on the next instruction boundary, so that every cycle
+    // of the CALL/RET itself is charged to the routine that executed it.
+    pub fn record_call(&mut self, kind: CallKind, target: (u16, u16)) {
+        self.pending.push(CallEvent::Call(kind, target));
+    }
+
+    pub fn record_return(&mut self, frames: usize) {
+        self.pending.push(CallEvent::Return(frames));
+    }
+
+    pub fn record_instruction(&mut self, address: (u16, u16)) {
+        for event in std::mem::take(&mut self.pending) {
+            match event {
+                CallEvent::Call(kind, target) => self.enter(kind, target),
+                CallEvent::Return(frames) => for _ in 0..frames {
+                    self.current = self.nodes[self.current].parent;
+                }
+            }
+        }
+
+        self.flat.entry(address).or_insert(ProfileEntry { cs: address.0, ip: address.1, ..Default::default() }).instructions += 1;
+        self.nodes[self.current].instructions += 1;
+        self.total_instructions += 1;
+    }
+
+    pub fn record_cycle(&mut self, address: (u16, u16)) {
+        self.flat.entry(address).or_insert(ProfileEntry { cs: address.0, ip: address.1, ..Default::default() }).cycles += 1;
+        self.nodes[self.current].cycles += 1;
+        self.total_cycles += 1;
+    }
+
+    fn enter(&mut self, kind: CallKind, target: (u16, u16)) {
+        let parent = self.current;
+        let index = match self.nodes[parent].children.get(&(kind, target)) {
+            Some(index) => *index,
+            None => {
+                let index = self.nodes.len();
+                self.nodes.push(Node::new(Some(kind), target, parent));
+                self.nodes[parent].children.insert((kind, target), index);
+                index
+            }
+        };
+        self.nodes[index].calls += 1;
+        self.current = index;
+    }
+
+    pub fn total_cycles(&self) -> usize {
+        self.total_cycles
+    }
+
+    pub fn total_instructions(&self) -> usize {
+        self.total_instructions
+    }
+
+    pub fn flat_profile(&self) -> Vec<ProfileEntry> {
+        let mut entries: Vec<ProfileEntry> = self.flat.values().copied().collect();
+        entries.sort_by(|a, b| b.cycles.cmp(&a.cycles).then((a.cs, a.ip).cmp(&(b.cs, b.ip))));
+        entries
+    }
+
+    pub fn routines(&self) -> Vec<RoutineEntry> {
+        let mut routines: HashMap<(CallKind, (u16, u16)), RoutineEntry> = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate().skip(1) {
+            let kind = node.kind.unwrap();
+            let routine = routines.entry((kind, node.entry)).or_insert(RoutineEntry {
+                kind,
+                cs: node.entry.0,
+                ip: node.entry.1,
+                calls: 0,
+                self_cycles: 0,
+                total_cycles: 0,
+                instructions: 0,
+            });
+            routine.calls += node.calls;
+            routine.self_cycles += node.cycles;
+            routine.instructions += node.instructions;
+            // Recursive routines would be counted more than once otherwise
+            if !self.has_ancestor(index, kind, node.entry) {
+                routine.total_cycles += self.inclusive_cycles(index);
+            }
+        }
+
+        let mut entries: Vec<RoutineEntry> = routines.values().copied().collect();
+        entries.sort_by(|a, b| b.total_cycles.cmp(&a.total_cycles).then((a.cs, a.ip).cmp(&(b.cs, b.ip))));
+        entries
+    }
+
+    fn has_ancestor(&self, index: usize, kind: CallKind, entry: (u16, u16)) -> bool {
+        let mut current = self.nodes[index].parent;
+        while current != 0 {
+            if self.nodes[current].kind == Some(kind) && self.nodes[current].entry == entry {
+                return true;
+            }
+            current = self.nodes[current].parent;
+        }
+        false
+    }
+
+    fn inclusive_cycles(&self, index: usize) -> usize {
+        self.nodes[index].cycles + self.nodes[index].children.values().map(|child| self.inclusive_cycles(*child)).sum::<usize>()
+    }
+
+    pub fn flat_report(&self) -> String {
+        self.flat_report_with_symbols(&SymbolTable::new())
+    }
+
+    pub fn flat_report_with_symbols(&self, symbols: &SymbolTable) -> String {
+        let mut report = String::new();
+        writeln!(report, "{:>6} {:>10} {:>10}  address", "%", "cycles", "count").unwrap();
+        for entry in self.flat_profile() {
+            writeln!(report, "{:>6.2} {:>10} {:>10}  {:04X}:{:04X} {}",
+                     self.percent(entry.cycles), entry.cycles, entry.instructions, entry.cs, entry.ip,
+                     symbols.describe(entry.cs, entry.ip).unwrap_or_default()).unwrap();
+        }
+        report
+    }
+
+    pub fn routine_report(&self) -> String {
+        self.routine_report_with_symbols(&SymbolTable::new())
+    }
+
+    pub fn routine_report_with_symbols(&self, symbols: &SymbolTable) -> String {
+        let mut report = String::new();
+        writeln!(report, "{:>6} {:>10} {:>10} {:>8}  routine", "%", "total", "self", "calls").unwrap();
+        for routine in self.routines() {
+            let address = symbols.describe(routine.cs, routine.ip)
+                .unwrap_or_else(|| format!("{:04X}:{:04X}", routine.cs, routine.ip));
+            let name = match routine.kind {
+                CallKind::Interrupt(num) => format!("int {:02X}h @ {}", num, address),
+                _ => address
+            };
+            writeln!(report, "{:>6.2} {:>10} {:>10} {:>8}  {}",
+                     self.percent(routine.total_cycles), routine.total_cycles, routine.self_cycles, routine.calls, name).unwrap();
+        }
+        report
+    }
+
+    fn percent(&self, cycles: usize) -> f64 {
+        if self.total_cycles == 0 {
+            0.0
+        } else {
+            (cycles as f64) * 100.0 / (self.total_cycles as f64)
+        }
+    }
+
+    // One line per call stack in the "folded" format read by flamegraph.pl and inferno
+    pub fn folded_stacks(&self) -> String {
+        self.folded_stacks_with_symbols(&SymbolTable::new())
+    }
+
+    pub fn folded_stacks_with_symbols(&self, symbols: &SymbolTable) -> String {
+        let mut lines = Vec::new();
+        self.fold(0, symbols, &mut Vec::new(), &mut lines);
+        lines.sort();
+        let mut folded = String::new();
+        for line in lines {
+            writeln!(folded, "{}", line).unwrap();
+        }
+        folded
+    }
+
+    fn fold(&self, index: usize, symbols: &SymbolTable, path: &mut Vec<String>, lines: &mut Vec<String>) {
+        path.push(self.nodes[index].name(symbols));
+        if self.nodes[index].cycles > 0 {
+            lines.push(format!("{} {}", path.join(";"), self.nodes[index].cycles));
+        }
+        for child in self.nodes[index].children.values() {
+            self.fold(*child, symbols, path, lines);
+        }
+        path.pop();
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
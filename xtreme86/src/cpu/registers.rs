@@ -0,0 +1,372 @@
+use std::fmt::Formatter;
+use enumflags2::{bitflags, BitFlags};
+use crate::cpu::{Regs, WordPart};
+
+// Declared in ModR/M encoding order
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum Reg8 {
+    AL,
+    CL,
+    DL,
+    BL,
+    AH,
+    CH,
+    DH,
+    BH
+}
+
+impl Reg8 {
+    pub const ALL: [Reg8; 8] = [Reg8::AL, Reg8::CL, Reg8::DL, Reg8::BL, Reg8::AH, Reg8::CH, Reg8::DH, Reg8::BH];
+
+    pub fn from_index(num: u8) -> Option<Self> {
+        Self::ALL.get(num as usize).copied()
+    }
+
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    pub fn reg16(self) -> Reg16 {
+        Reg16::ALL[(self.index() % 4) as usize]
+    }
+
+    pub fn part(self) -> WordPart {
+        if self.index() < 4 { WordPart::Low } else { WordPart::High }
+    }
+}
+
+impl std::fmt::Display for Reg8 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// Declared in ModR/M encoding order
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum Reg16 {
+    AX,
+    CX,
+    DX,
+    BX,
+    SP,
+    BP,
+    SI,
+    DI
+}
+
+impl Reg16 {
+    pub const ALL: [Reg16; 8] = [Reg16::AX, Reg16::CX, Reg16::DX, Reg16::BX, Reg16::SP, Reg16::BP, Reg16::SI, Reg16::DI];
+
+    pub fn from_index(num: u8) -> Option<Self> {
+        Self::ALL.get(num as usize).copied()
+    }
+
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+}
+
+impl std::fmt::Display for Reg16 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<Reg16> for Regs {
+    fn from(reg: Reg16) -> Self {
+        match reg {
+            Reg16::AX => Regs::AX,
+            Reg16::CX => Regs::CX,
+            Reg16::DX => Regs::DX,
+            Reg16::BX => Regs::BX,
+            Reg16::SP => Regs::SP,
+            Reg16::BP => Regs::BP,
+            Reg16::SI => Regs::SI,
+            Reg16::DI => Regs::DI
+        }
+    }
+}
+
+// Declared in the order of the sreg field of MOV Sreg and the segment override prefixes
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum SegReg {
+    ES,
+    CS,
+    SS,
+    DS
+}
+
+impl SegReg {
+    pub const ALL: [SegReg; 4] = [SegReg::ES, SegReg::CS, SegReg::SS, SegReg::DS];
+
+    pub fn from_index(num: u8) -> Option<Self> {
+        Self::ALL.get(num as usize).copied()
+    }
+
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+}
+
+impl std::fmt::Display for SegReg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<SegReg> for Regs {
+    fn from(reg: SegReg) -> Self {
+        match reg {
+            SegReg::ES => Regs::ES,
+            SegReg::CS => Regs::CS,
+            SegReg::SS => Regs::SS,
+            SegReg::DS => Regs::DS
+        }
+    }
+}
+
+#[bitflags]
+#[repr(u16)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Flag {
+    Carry = 0x0001,
+    Parity = 0x0004,
+    AuxCarry = 0x0010,
+    Zero = 0x0040,
+    Sign = 0x0080,
+    Trap = 0x0100,
+    Interrupt = 0x0200,
+    Direction = 0x0400,
+    Overflow = 0x0800,
+}
+
+impl Flag {
+    // Display order, most significant first
+    pub const ALL: [Flag; 9] = [Flag::Overflow, Flag::Direction, Flag::Interrupt, Flag::Trap, Flag::Sign,
+        Flag::Zero, Flag::AuxCarry, Flag::Parity, Flag::Carry];
+
+    pub fn letter(self) -> char {
+        match self {
+            Flag::Carry => 'C',
+            Flag::Parity => 'P',
+            Flag::AuxCarry => 'A',
+            Flag::Zero => 'Z',
+            Flag::Sign => 'S',
+            Flag::Trap => 'T',
+            Flag::Interrupt => 'I',
+            Flag::Direction => 'D',
+            Flag::Overflow => 'O'
+        }
+    }
+}
+
+// The defined bits of FLAGS. Reserved bits are dropped.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Flags(BitFlags<Flag>);
+
+impl Flags {
+    pub fn empty() -> Self {
+        Self(BitFlags::empty())
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        Self(BitFlags::from_bits_truncate(bits))
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0.bits()
+    }
+
+    pub fn contains(self, flag: Flag) -> bool {
+        self.0.contains(flag)
+    }
+
+    pub fn set(&mut self, flag: Flag, val: bool) {
+        if val {
+            self.0.insert(flag);
+        } else {
+            self.0.remove(flag);
+        }
+    }
+
+    pub fn with(mut self, flag: Flag) -> Self {
+        self.0.insert(flag);
+        self
+    }
+
+    pub fn carry(self) -> bool {
+        self.contains(Flag::Carry)
+    }
+
+    pub fn parity(self) -> bool {
+        self.contains(Flag::Parity)
+    }
+
+    pub fn aux_carry(self) -> bool {
+        self.contains(Flag::AuxCarry)
+    }
+
+    pub fn zero(self) -> bool {
+        self.contains(Flag::Zero)
+    }
+
+    pub fn sign(self) -> bool {
+        self.contains(Flag::Sign)
+    }
+
+    pub fn trap(self) -> bool {
+        self.contains(Flag::Trap)
+    }
+
+    pub fn interrupt(self) -> bool {
+        self.contains(Flag::Interrupt)
+    }
+
+    pub fn direction(self) -> bool {
+        self.contains(Flag::Direction)
+    }
+
+    pub fn overflow(self) -> bool {
+        self.contains(Flag::Overflow)
+    }
+}
+
+impl From<BitFlags<Flag>> for Flags {
+    fn from(flags: BitFlags<Flag>) -> Self {
+        Self(flags)
+    }
+}
+
+impl From<Flag> for Flags {
+    fn from(flag: Flag) -> Self {
+        Self(flag.into())
+    }
+}
+
+// Set flags are shown by their letter and clear ones by a dash, e.g. "- - I - - Z - P C"
+impl std::fmt::Display for Flags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let letters: Vec<String> = Flag::ALL.iter()
+            .map(|flag| if self.contains(*flag) { flag.letter().to_string() } else { String::from("-") })
+            .collect();
+        write!(f, "{}", letters.join(" "))
+    }
+}
+
+impl std::fmt::Debug for Flags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Flags({})", self)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RegisterDiff {
+    pub reg: Regs,
+    pub before: u16,
+    pub after: u16,
+}
+
+impl std::fmt::Display for RegisterDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.reg == Regs::FLAGS {
+            write!(f, "FLAGS: {} -> {}", Flags::from_bits(self.before), Flags::from_bits(self.after))
+        } else {
+            write!(f, "{}: {:04X} -> {:04X}", self.reg.to_text(), self.before, self.after)
+        }
+    }
+}
+
+// A copy of every register, taken with CPU::registers()
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub ax: u16,
+    pub bx: u16,
+    pub cx: u16,
+    pub dx: u16,
+    pub si: u16,
+    pub di: u16,
+    pub sp: u16,
+    pub bp: u16,
+    pub es: u16,
+    pub cs: u16,
+    pub ss: u16,
+    pub ds: u16,
+    pub ip: u16,
+    pub flags: Flags,
+}
+
+impl Registers {
+    pub const ALL: [Regs; 14] = [Regs::AX, Regs::BX, Regs::CX, Regs::DX, Regs::SP, Regs::BP, Regs::SI, Regs::DI,
+        Regs::DS, Regs::ES, Regs::SS, Regs::CS, Regs::IP, Regs::FLAGS];
+
+    pub fn get(&self, reg: Regs) -> u16 {
+        match reg {
+            Regs::AX => self.ax,
+            Regs::BX => self.bx,
+            Regs::CX => self.cx,
+            Regs::DX => self.dx,
+            Regs::SI => self.si,
+            Regs::DI => self.di,
+            Regs::SP => self.sp,
+            Regs::BP => self.bp,
+            Regs::ES => self.es,
+            Regs::CS => self.cs,
+            Regs::SS => self.ss,
+            Regs::DS => self.ds,
+            Regs::IP => self.ip,
+            Regs::FLAGS => self.flags.bits()
+        }
+    }
+
+    pub fn set(&mut self, reg: Regs, val: u16) {
+        match reg {
+            Regs::AX => self.ax = val,
+            Regs::BX => self.bx = val,
+            Regs::CX => self.cx = val,
+            Regs::DX => self.dx = val,
+            Regs::SI => self.si = val,
+            Regs::DI => self.di = val,
+            Regs::SP => self.sp = val,
+            Regs::BP => self.bp = val,
+            Regs::ES => self.es = val,
+            Regs::CS => self.cs = val,
+            Regs::SS => self.ss = val,
+            Regs::DS => self.ds = val,
+            Regs::IP => self.ip = val,
+            Regs::FLAGS => self.flags = Flags::from_bits(val)
+        }
+    }
+
+    pub fn get8(&self, reg: Reg8) -> u8 {
+        let val = self.get(reg.reg16().into());
+        match reg.part() {
+            WordPart::Low => (val & 0xFF) as u8,
+            WordPart::High => (val >> 8) as u8
+        }
+    }
+
+    // Registers that differ in `other`, in display order
+    pub fn diff(&self, other: &Registers) -> Vec<RegisterDiff> {
+        Self::ALL.iter()
+            .filter(|reg| self.get(**reg) != other.get(**reg))
+            .map(|reg| RegisterDiff { reg: *reg, before: self.get(*reg), after: other.get(*reg) })
+            .collect()
+    }
+}
+
+// Laid out like the register dump of DEBUG.COM
+impl std::fmt::Display for Registers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "AX={:04X}  BX={:04X}  CX={:04X}  DX={:04X}  SP={:04X}  BP={:04X}  SI={:04X}  DI={:04X}",
+                 self.ax, self.bx, self.cx, self.dx, self.sp, self.bp, self.si, self.di)?;
+        write!(f, "DS={:04X}  ES={:04X}  SS={:04X}  CS={:04X}  IP={:04X}  {}",
+               self.ds, self.es, self.ss, self.cs, self.ip, self.flags)
+    }
+}
+
+// Multi-line so that assert_eq! failures on snapshots stay readable
+impl std::fmt::Debug for Registers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\n{}\n", self)
+    }
+}
@@ -0,0 +1,260 @@
+use crate::cpu::Regs;
+use crate::cpu::model::Model;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::instruction::args::DstArg;
+
+// Clocks from the Intel manuals for the 8086/8088, the 80186 and the 80286. Where a manual gives a
+// range the lowest value is used, and the 286's m, the size of the instruction jumped to, is left
+// out. The 8088 takes the 8086's clocks plus the word penalty of every word it moves.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Clocks(pub usize, pub usize, pub usize);
+
+impl Clocks {
+    pub fn get(self, model: Model) -> usize {
+        match model {
+            Model::I8088 | Model::I8086 => self.0,
+            Model::I80186 => self.1,
+            Model::I80286 => self.2
+        }
+    }
+}
+
+// The clocks of `instruction` when any branch it has isn't taken and a REP prefix repeats nothing
+pub fn cycles(model: Model, instruction: &Instruction) -> usize {
+    let memory = [instruction.dst, instruction.src].iter().flatten().copied().find(|arg| is_memory(*arg));
+    let (clocks, uses_ea) = base(opcode(model, instruction.opcode), instruction, memory.is_some());
+    let mut total = clocks.get(model);
+    if let (true, Some(arg)) = (uses_ea, memory) {
+        total += effective_address(model, arg);
+    }
+    if instruction.segment_override.is_some() {
+        total += Clocks(2, 2, 0).get(model);
+    }
+    if instruction.lock {
+        total += Clocks(2, 2, 0).get(model);
+    }
+    total
+}
+
+// The extra clocks of a Jcc, LOOP, JCXZ or INTO that is taken
+pub fn taken(model: Model, code: u8) -> usize {
+    match opcode(model, code) {
+        0x70..=0x7F => Clocks(12, 9, 4),
+        0xE0 => Clocks(14, 10, 4),
+        0xE1 | 0xE2 => Clocks(12, 10, 4),
+        0xE3 => Clocks(12, 11, 4),
+        0xCE => Clocks(49, 44, 21),
+        _ => Clocks(0, 0, 0)
+    }.get(model)
+}
+
+// The clocks of every repetition of a string instruction under REP
+pub fn rep_iteration(model: Model, string_opcode: u8) -> usize {
+    match string_opcode {
+        0xA4 | 0xA5 => Clocks(17, 8, 4),
+        0xA6 | 0xA7 => Clocks(22, 22, 9),
+        0xAA | 0xAB => Clocks(10, 9, 3),
+        0xAC | 0xAD => Clocks(13, 11, 4),
+        0xAE | 0xAF => Clocks(15, 15, 8),
+        0x6C..=0x6F => Clocks(8, 8, 4),
+        _ => Clocks(0, 0, 0)
+    }.get(model)
+}
+
+// The extra clocks of a shift or rotate by CL or an immediate, for each bit shifted
+pub fn shift_count(model: Model, instruction: &Instruction, count: u8) -> usize {
+    match opcode(model, instruction.opcode) {
+        0xC0 | 0xC1 | 0xD2 | 0xD3 => Clocks(4, 1, 1).get(model) * count as usize,
+        _ => 0
+    }
+}
+
+// The extra clocks of ENTER for the frame pointers it copies
+pub fn enter_levels(model: Model, level: u8) -> usize {
+    match level {
+        0 => 0,
+        1 => Clocks(10, 10, 4).get(model),
+        _ => Clocks(7, 7, 1).get(model) + Clocks(16, 16, 4).get(model) * (level as usize - 1)
+    }
+}
+
+// The clocks of taking a hardware interrupt, from the INTA cycles to the first handler fetch
+pub fn interrupt_acknowledge(model: Model) -> usize {
+    Clocks(61, 45, 23).get(model)
+}
+
+// What a word transfer at `address` costs on top of the tables. The 8088 moves every word as two
+// bytes, the others only split words at odd addresses.
+pub fn word_penalty(model: Model, address: u32) -> usize {
+    match model {
+        Model::I8088 => 4,
+        Model::I8086 | Model::I80186 if address % 2 == 1 => 4,
+        Model::I80286 if address % 2 == 1 => 2,
+        _ => 0
+    }
+}
+
+// The 8086 and 8088 run the 186 opcodes as the instructions they alias
+fn opcode(model: Model, code: u8) -> u8 {
+    match code {
+        0x60..=0x6F if model <= Model::I8086 => code + 0x10,
+        0xC0 | 0xC1 | 0xC8 | 0xC9 if model <= Model::I8086 => code + 2,
+        _ => code
+    }
+}
+
+fn is_memory(arg: DstArg) -> bool {
+    matches!(arg, DstArg::Ptr(_, _) | DstArg::RegPtr(_, _) | DstArg::RegPtrImm(_, _, _)
+        | DstArg::RegPtrOff(_, _, _) | DstArg::RegPtrOffImm(_, _, _, _))
+}
+
+// Only the 8086 and 8088 compute effective addresses in the execution unit, the 286 is slowed
+// down by base + index + displacement alone
+fn effective_address(model: Model, arg: DstArg) -> usize {
+    let fast_pair = |base: Regs, index: Regs| matches!((base, index), (Regs::BP, Regs::DI) | (Regs::BX, Regs::SI));
+    match model {
+        Model::I8088 | Model::I8086 => match arg {
+            DstArg::Ptr(_, _) => 6,
+            DstArg::RegPtr(_, _) => 5,
+            DstArg::RegPtrImm(_, _, _) => 9,
+            DstArg::RegPtrOff(base, index, _) => if fast_pair(base, index) { 7 } else { 8 },
+            DstArg::RegPtrOffImm(base, index, _, _) => if fast_pair(base, index) { 11 } else { 12 },
+            _ => 0
+        },
+        Model::I80286 => match arg {
+            DstArg::RegPtrOffImm(_, _, _, _) => 1,
+            _ => 0
+        },
+        Model::I80186 => 0
+    }
+}
+
+// The clocks of the register and memory forms, and whether the memory form adds the effective
+// address
+fn base(code: u8, instruction: &Instruction, memory: bool) -> (Clocks, bool) {
+    let rm = |reg: Clocks, mem: Clocks| if memory { (mem, true) } else { (reg, false) };
+    let fixed = |clocks: Clocks| (clocks, false);
+    let word = code & 1 == 1;
+    match code {
+        // add, or, adc, sbb, and, sub, xor and cmp
+        0x00..=0x3D if code & 0x07 < 4 => match (code & 0x02 == 0, code >> 3 == 7) {
+            (true, false) => rm(Clocks(3, 3, 2), Clocks(16, 10, 7)),
+            _ => rm(Clocks(3, 3, 2), Clocks(9, 10, 7))
+        },
+        0x00..=0x3D if code & 0x07 < 6 => fixed(Clocks(4, 3, 3)),
+        0x06 | 0x0E | 0x16 | 0x1E => fixed(Clocks(10, 9, 3)),
+        0x07 | 0x17 | 0x1F => fixed(Clocks(8, 8, 5)),
+        // pop cs on the 8086, lidt and sidt on the 286
+        0x0F => rm(Clocks(8, 8, 12), Clocks(8, 8, 12)),
+        0x27 | 0x2F => fixed(Clocks(4, 4, 3)),
+        0x37 => fixed(Clocks(4, 8, 3)),
+        0x3F => fixed(Clocks(4, 7, 3)),
+        0x40..=0x4F => fixed(Clocks(2, 3, 2)),
+        0x50..=0x57 => fixed(Clocks(11, 10, 3)),
+        0x58..=0x5F => fixed(Clocks(8, 10, 5)),
+        0x60 => fixed(Clocks(36, 36, 17)),
+        0x61 => fixed(Clocks(51, 51, 19)),
+        0x62 => fixed(Clocks(33, 33, 13)),
+        0x68 | 0x6A => fixed(Clocks(10, 10, 3)),
+        0x69 | 0x6B => if memory { fixed(Clocks(29, 29, 24)) } else { fixed(Clocks(22, 22, 21)) },
+        0x6C..=0x6F => fixed(Clocks(14, 14, 5)),
+        0x70..=0x7F => fixed(Clocks(4, 4, 3)),
+        0x80..=0x83 => if instruction.reg_bits == 7 {
+            rm(Clocks(4, 4, 3), Clocks(10, 10, 6))
+        } else {
+            rm(Clocks(4, 4, 3), Clocks(17, 16, 7))
+        },
+        0x84 | 0x85 => rm(Clocks(3, 3, 2), Clocks(9, 10, 6)),
+        0x86 | 0x87 => rm(Clocks(4, 4, 3), Clocks(17, 17, 5)),
+        0x88 | 0x89 => rm(Clocks(2, 2, 2), Clocks(9, 12, 3)),
+        0x8A | 0x8B => rm(Clocks(2, 2, 2), Clocks(8, 9, 5)),
+        0x8C => rm(Clocks(2, 2, 2), Clocks(9, 11, 3)),
+        0x8D => (Clocks(2, 6, 3), true),
+        0x8E => rm(Clocks(2, 2, 2), Clocks(8, 9, 5)),
+        0x8F => rm(Clocks(8, 10, 5), Clocks(17, 20, 5)),
+        0x90..=0x97 => fixed(Clocks(3, 3, 3)),
+        0x98 => fixed(Clocks(2, 2, 2)),
+        0x99 => fixed(Clocks(5, 4, 2)),
+        0x9A => fixed(Clocks(28, 23, 13)),
+        0x9B => fixed(Clocks(3, 6, 3)),
+        0x9C => fixed(Clocks(10, 9, 3)),
+        0x9D => fixed(Clocks(8, 8, 5)),
+        0x9E => fixed(Clocks(4, 3, 2)),
+        0x9F => fixed(Clocks(4, 2, 2)),
+        0xA0 | 0xA1 => fixed(Clocks(10, 8, 5)),
+        0xA2 | 0xA3 => fixed(Clocks(10, 9, 3)),
+        0xA4 | 0xA5 => fixed(Clocks(18, 9, 5)),
+        0xA6 | 0xA7 => fixed(Clocks(22, 22, 8)),
+        0xA8 | 0xA9 => fixed(Clocks(4, 4, 3)),
+        0xAA | 0xAB => fixed(Clocks(11, 10, 3)),
+        0xAC | 0xAD => fixed(Clocks(12, 12, 5)),
+        0xAE | 0xAF => fixed(Clocks(15, 15, 7)),
+        0xB0..=0xBF => fixed(Clocks(4, 3, 2)),
+        0xC0 | 0xC1 => rm(Clocks(5, 5, 5), Clocks(17, 17, 8)),
+        0xC2 => fixed(Clocks(12, 18, 11)),
+        0xC3 => fixed(Clocks(8, 16, 11)),
+        0xC4 | 0xC5 => (Clocks(16, 18, 7), true),
+        0xC6 | 0xC7 => rm(Clocks(4, 3, 2), Clocks(10, 12, 3)),
+        0xC8 => fixed(Clocks(15, 15, 11)),
+        0xC9 => fixed(Clocks(8, 8, 5)),
+        0xCA => fixed(Clocks(17, 25, 15)),
+        0xCB => fixed(Clocks(18, 22, 15)),
+        0xCC => fixed(Clocks(52, 45, 23)),
+        0xCD => fixed(Clocks(51, 47, 23)),
+        0xCE => fixed(Clocks(4, 4, 3)),
+        0xCF => fixed(Clocks(24, 28, 17)),
+        0xD0 | 0xD1 => rm(Clocks(2, 2, 2), Clocks(15, 15, 7)),
+        0xD2 | 0xD3 => rm(Clocks(8, 5, 5), Clocks(20, 17, 8)),
+        0xD4 => fixed(Clocks(83, 19, 16)),
+        0xD5 => fixed(Clocks(60, 15, 14)),
+        0xD6 => fixed(Clocks(3, 3, 3)),
+        0xD7 => fixed(Clocks(11, 11, 5)),
+        0xD8..=0xDF => rm(Clocks(2, 2, 2), Clocks(8, 8, 2)),
+        0xE0 | 0xE2 => fixed(Clocks(5, 6, 4)),
+        0xE1 | 0xE3 => fixed(Clocks(6, 6, 4)),
+        0xE4 | 0xE5 => fixed(Clocks(10, 10, 5)),
+        0xEC | 0xED => fixed(Clocks(8, 8, 5)),
+        0xE6 | 0xE7 => fixed(Clocks(10, 9, 3)),
+        0xEE | 0xEF => fixed(Clocks(8, 7, 3)),
+        0xE8 => fixed(Clocks(19, 15, 7)),
+        0xE9 | 0xEB => fixed(Clocks(15, 14, 7)),
+        0xEA => fixed(Clocks(15, 14, 11)),
+        // rep, repe and repne, set by the string instruction they repeat
+        0xF2 | 0xF3 => fixed(match instruction.dst {
+            Some(DstArg::Opcode(0xA4 | 0xA5)) => Clocks(9, 8, 5),
+            Some(DstArg::Opcode(0xAA | 0xAB)) => Clocks(9, 6, 4),
+            Some(DstArg::Opcode(0xAC | 0xAD)) => Clocks(9, 6, 5),
+            Some(DstArg::Opcode(0x6C..=0x6F)) => Clocks(8, 8, 5),
+            _ => Clocks(9, 5, 5)
+        }),
+        0xF6 | 0xF7 => match instruction.reg_bits {
+            0 | 1 => rm(Clocks(5, 4, 3), Clocks(11, 10, 6)),
+            2 | 3 => rm(Clocks(3, 3, 2), Clocks(16, 10, 7)),
+            4 => match word {
+                false => rm(Clocks(70, 26, 13), Clocks(76, 32, 16)),
+                true => rm(Clocks(118, 35, 21), Clocks(124, 41, 24))
+            },
+            5 => match word {
+                false => rm(Clocks(80, 25, 13), Clocks(86, 31, 16)),
+                true => rm(Clocks(128, 34, 21), Clocks(134, 40, 24))
+            },
+            6 => match word {
+                false => rm(Clocks(80, 29, 14), Clocks(86, 35, 17)),
+                true => rm(Clocks(144, 38, 22), Clocks(150, 44, 25))
+            },
+            _ => match word {
+                false => rm(Clocks(101, 44, 17), Clocks(107, 50, 20)),
+                true => rm(Clocks(165, 53, 25), Clocks(171, 59, 28))
+            }
+        },
+        0xFE | 0xFF => match instruction.reg_bits {
+            0 | 1 => rm(Clocks(3, 3, 2), Clocks(15, 15, 7)),
+            2 => rm(Clocks(16, 13, 7), Clocks(21, 19, 11)),
+            3 => rm(Clocks(37, 38, 16), Clocks(37, 38, 16)),
+            4 => rm(Clocks(11, 11, 7), Clocks(18, 17, 11)),
+            5 => rm(Clocks(24, 26, 15), Clocks(24, 26, 15)),
+            _ => rm(Clocks(11, 10, 3), Clocks(16, 16, 5))
+        },
+        _ => fixed(Clocks(2, 2, 2))
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::VecDeque;
+use std::fmt::Formatter;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub address: (u16, u16),
+    pub symbol: Option<String>,
+    pub text: String,
+}
+
+impl std::fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04X}:{:04X} {:<16} {}", self.address.0, self.address.1, self.symbol.as_deref().unwrap_or(""), self.text)
+    }
+}
+
+// The last `capacity` instructions executed, the oldest are dropped to make room
+#[derive(Clone, Debug)]
+pub struct Trace {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl Trace {
+    pub const DEFAULT_CAPACITY: usize = 0x10000;
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { entries: VecDeque::new(), capacity }
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn into_entries(self) -> Vec<TraceEntry> {
+        self.entries.into()
+    }
+}
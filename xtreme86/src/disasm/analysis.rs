@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use crate::asm;
+use crate::cpu::Model;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::instruction::args::DstArg;
+use crate::cpu::instruction::semantics::FlowKind;
+use crate::disasm::{Disassembler, DisassembledInstruction};
+use crate::disasm::format::FormatOptions;
+use crate::disasm::image::{Image, ImageFormat};
+use crate::symbols::SymbolTable;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SweepMode {
+    // Decode every byte in order
+    Linear,
+    // Only decode what can be reached from the entry point
+    Recursive
+}
+
+#[derive(Clone, Debug)]
+pub enum ItemKind {
+    Code(DisassembledInstruction),
+    Data(Vec<u8>)
+}
+
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub position: usize,
+    pub address: (u16, u16),
+    pub kind: ItemKind,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TargetKind {
+    Jump,
+    Call
+}
+
+// Where control can go after an instruction
+struct Flow {
+    targets: Vec<((u16, u16), TargetKind)>,
+    falls_through: bool,
+}
+
+// int 20h ends the program, so whatever follows it isn't reached through it
+pub fn ends_program(instruction: &Instruction) -> bool {
+    matches!((instruction.opcode, instruction.dst), (0xCD, Some(DstArg::Imm8(0x20))))
+}
+
+fn flow(instruction: &Instruction, address: (u16, u16)) -> Flow {
+    let semantics = instruction.semantics(address);
+    let kind = if semantics.flow == FlowKind::Call { TargetKind::Call } else { TargetKind::Jump };
+
+    Flow {
+        targets: semantics.targets.into_iter().map(|target| (target, kind)).collect(),
+        falls_through: semantics.flow.falls_through() && !ends_program(instruction),
+    }
+}
+
+pub struct Analysis {
+    format: ImageFormat,
+    origin: u16,
+    items: Vec<Item>,
+    labels: SymbolTable,
+}
+
+impl Analysis {
+    pub fn new(image: &Image, mode: SweepMode) -> Self {
+        Self::with_model(image, mode, Model::default())
+    }
+
+    // Decodes the image as `model` would run it
+    pub fn with_model(image: &Image, mode: SweepMode, model: Model) -> Self {
+        let mut code: BTreeMap<usize, DisassembledInstruction> = BTreeMap::new();
+        let mut targets: Vec<((u16, u16), TargetKind)> = Vec::new();
+
+        match mode {
+            SweepMode::Linear => {
+                let mut position = 0;
+                while let Some(decoded) = Self::decode(image, position, image.address_of(position), model) {
+                    position += decoded.length;
+                    if let Some(instruction) = &decoded.instruction {
+                        targets.extend(flow(instruction, decoded.address).targets);
+                        code.insert(position - decoded.length, decoded);
+                    }
+                }
+            }
+            SweepMode::Recursive => {
+                let mut claimed = vec![false; image.bytes.len()];
+                let mut queue = VecDeque::new();
+                queue.push_back(image.entry);
+
+                while let Some(mut address) = queue.pop_front() {
+                    while let Some(position) = image.position_of(address) {
+                        if claimed[position] {
+                            break;
+                        }
+                        let decoded = match Self::decode(image, position, address, model) {
+                            Some(decoded) if !decoded.is_data() => decoded,
+                            _ => break
+                        };
+                        // Don't decode into the middle of something already decoded
+                        if claimed[position..position + decoded.length].iter().any(|byte| *byte) {
+                            break;
+                        }
+                        claimed[position..position + decoded.length].iter_mut().for_each(|byte| *byte = true);
+
+                        let flow = flow(decoded.instruction.as_ref().unwrap(), address);
+                        for (target, kind) in flow.targets {
+                            targets.push((target, kind));
+                            queue.push_back(target);
+                        }
+                        let next = (address.0, decoded.next_ip());
+                        code.insert(position, decoded);
+                        if !flow.falls_through {
+                            break;
+                        }
+                        address = next;
+                    }
+                }
+            }
+        }
+
+        let mut labels = SymbolTable::new();
+        if let Some(position) = image.position_of(image.entry) {
+            if code.contains_key(&position) {
+                labels.insert("start", image.entry.0, image.entry.1);
+            }
+        }
+        // Calls first, so a routine that is also jumped to is still named as one
+        targets.sort_by_key(|(_, kind)| *kind != TargetKind::Call);
+        for (target, kind) in targets {
+            let is_code = image.position_of(target).is_some_and(|position| code.contains_key(&position));
+            if is_code && labels.name_at(target.0, target.1).is_none() {
+                let prefix = if kind == TargetKind::Call { "sub" } else { "loc" };
+                let name = match image.format {
+                    ImageFormat::Com => format!("{}_{:04X}", prefix, target.1),
+                    ImageFormat::Exe => format!("{}_{:04X}_{:04X}", prefix, target.0, target.1)
+                };
+                labels.insert(&name, target.0, target.1);
+            }
+        }
+
+        Self {
+            format: image.format,
+            origin: image.origin,
+            items: Self::fill_gaps(image, code),
+            labels,
+        }
+    }
+
+    fn decode(image: &Image, position: usize, address: (u16, u16), model: Model) -> Option<DisassembledInstruction> {
+        Disassembler::with_model(&image.bytes[position.min(image.bytes.len())..], address.0, address.1, model).decode_at(0)
+    }
+
+    // Fills the gaps between decoded instructions with data
+    fn fill_gaps(image: &Image, code: BTreeMap<usize, DisassembledInstruction>) -> Vec<Item> {
+        let mut items = Vec::new();
+        let mut position = 0;
+        let mut code = code.into_iter().peekable();
+
+        while position < image.bytes.len() {
+            match code.peek() {
+                Some((start, _)) if *start == position => {
+                    let (_, decoded) = code.next().unwrap();
+                    position += decoded.length;
+                    items.push(Item { position: position - decoded.length, address: decoded.address, kind: ItemKind::Code(decoded) });
+                }
+                next => {
+                    let end = next.map_or(image.bytes.len(), |(start, _)| *start);
+                    items.push(Item { position, address: image.address_of(position), kind: ItemKind::Data(image.bytes[position..end].to_vec()) });
+                    position = end;
+                }
+            }
+        }
+        items
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub fn labels(&self) -> &SymbolTable {
+        &self.labels
+    }
+
+    pub fn code_items(&self) -> impl Iterator<Item = &DisassembledInstruction> {
+        self.items.iter().filter_map(|item| match &item.kind {
+            ItemKind::Code(decoded) => Some(decoded),
+            ItemKind::Data(_) => None
+        })
+    }
+
+    fn text(&self, decoded: &DisassembledInstruction, options: &FormatOptions) -> String {
+        options.format_with_symbols(decoded.instruction.as_ref().unwrap(), decoded.address, &self.labels)
+    }
+
+    // Whether the text of the instruction assembles back into the same bytes
+    fn reassembles(decoded: &DisassembledInstruction, options: &FormatOptions) -> bool {
+        let text = options.format(decoded.instruction.as_ref().unwrap(), decoded.address);
+        asm::assemble(&format!("org 0x{:X}\n{}", decoded.address.1, text)).is_ok_and(|bytes| bytes == decoded.bytes)
+    }
+
+    fn data_lines(bytes: &[u8]) -> Vec<String> {
+        bytes.chunks(8).map(|chunk| {
+            let values: Vec<String> = chunk.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+            format!("db {}", values.join(", "))
+        }).collect()
+    }
+
+    // Addresses, raw bytes and instructions side by side
+    pub fn listing(&self, options: &FormatOptions) -> String {
+        let mut listing = String::new();
+        for item in self.items.iter() {
+            if let Some(label) = self.labels.name_at(item.address.0, item.address.1) {
+                writeln!(listing, "{:28}{}:", "", label).unwrap();
+            }
+            match &item.kind {
+                ItemKind::Code(decoded) => {
+                    let bytes: Vec<String> = decoded.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                    writeln!(listing, "{:04X}:{:04X}  {:<16}    {}", item.address.0, item.address.1, bytes.join(""), self.text(decoded, options)).unwrap();
+                }
+                ItemKind::Data(bytes) => {
+                    for (index, (chunk, line)) in bytes.chunks(8).zip(Self::data_lines(bytes)).enumerate() {
+                        let offset = item.address.1.wrapping_add((index * 8) as u16);
+                        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+                        writeln!(listing, "{:04X}:{:04X}  {:<16}    {}", item.address.0, offset, hex.join(""), line).unwrap();
+                    }
+                }
+            }
+        }
+        listing
+    }
+
+    // Source that nasm assembles back into the program. Branches use the generated labels, so
+    // the code can be edited and reassembled.
+    pub fn nasm_source(&self) -> String {
+        let options = FormatOptions::nasm();
+        let mut source = String::new();
+        writeln!(source, "bits 16").unwrap();
+        match self.format {
+            ImageFormat::Com => writeln!(source, "org 0x{:X}", self.origin).unwrap(),
+            ImageFormat::Exe => writeln!(source, "; MZ header and relocations are not reproduced").unwrap()
+        }
+
+        for item in self.items.iter() {
+            if let Some(label) = self.labels.name_at(item.address.0, item.address.1) {
+                writeln!(source, "\n{}:", label).unwrap();
+            }
+            match &item.kind {
+                ItemKind::Code(decoded) if Self::reassembles(decoded, &options) => writeln!(source, "    {}", self.text(decoded, &options)).unwrap(),
+                // nasm would pick a shorter or different encoding, so keep the original bytes
+                ItemKind::Code(decoded) => writeln!(source, "    {} ; {}", Self::data_lines(&decoded.bytes)[0], self.text(decoded, &options)).unwrap(),
+                ItemKind::Data(bytes) => for line in Self::data_lines(bytes) {
+                    writeln!(source, "    {}", line).unwrap();
+                }
+            }
+        }
+        source
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+use crate::cpu::instruction::Instruction;
+use crate::cpu::instruction::args::DstArg;
+use crate::cpu::instruction::semantics::FlowKind;
+use crate::disasm::{Disassembler, DisassembledInstruction};
+use crate::disasm::analysis::ends_program;
+use crate::disasm::format::FormatOptions;
+use crate::disasm::image::Image;
+use crate::symbols::SymbolTable;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    // Straight on to the next instruction, including the return from a call or interrupt
+    Fallthrough,
+    Jump,
+    // The taken side of a conditional jump or loop
+    Branch,
+    Call,
+    // From an int instruction to the instruction after it, through the handler
+    Interrupt(u8)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub from: (u16, u16),
+    pub to: (u16, u16),
+    pub kind: EdgeKind,
+    // Goes back to a block that is still being visited on the way here, which closes a loop
+    pub back: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct BasicBlock {
+    pub start: (u16, u16),
+    pub instructions: Vec<DisassembledInstruction>,
+}
+
+impl BasicBlock {
+    pub fn last(&self) -> &DisassembledInstruction {
+        self.instructions.last().unwrap()
+    }
+
+    // Offset just past the last instruction
+    pub fn end(&self) -> (u16, u16) {
+        (self.start.0, self.last().next_ip())
+    }
+
+    pub fn contains(&self, address: (u16, u16)) -> bool {
+        self.instructions.iter().any(|decoded| decoded.address == address)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loop {
+    pub header: (u16, u16),
+    // The block that jumps back to the header
+    pub latch: (u16, u16),
+    pub blocks: BTreeSet<(u16, u16)>,
+    // Closed by loop, loope or loopne rather than a jump
+    pub counted: bool,
+}
+
+pub struct ControlFlowGraph {
+    entries: Vec<(u16, u16)>,
+    blocks: BTreeMap<(u16, u16), BasicBlock>,
+    edges: Vec<Edge>,
+    loops: Vec<Loop>,
+}
+
+fn is_loop_instruction(instruction: &Instruction) -> bool {
+    matches!(instruction.opcode, 0xE0..=0xE2)
+}
+
+fn interrupt_vector(instruction: &Instruction) -> u8 {
+    match (instruction.opcode, instruction.dst) {
+        (0xCC, _) => 3,
+        (0xCE, _) => 4,
+        (_, Some(DstArg::Imm8(vector))) => vector,
+        _ => 0
+    }
+}
+
+impl ControlFlowGraph {
+    // Splits the code reachable from `entries` into basic blocks. Calls and interrupts end a
+    // block, so the instruction they return to always starts one.
+    pub fn new(image: &Image, entries: &[(u16, u16)]) -> Self {
+        let mut instructions: BTreeMap<(u16, u16), DisassembledInstruction> = BTreeMap::new();
+        let mut leaders: BTreeSet<(u16, u16)> = entries.iter().copied().collect();
+        let mut queue: Vec<(u16, u16)> = entries.to_vec();
+
+        while let Some(mut address) = queue.pop() {
+            while !instructions.contains_key(&address) {
+                let decoded = match image.position_of(address).and_then(|position| Self::decode(image, position, address)) {
+                    Some(decoded) if !decoded.is_data() => decoded,
+                    _ => break
+                };
+                let instruction = decoded.instruction.as_ref().unwrap();
+                let semantics = instruction.semantics(address);
+                let next = (address.0, decoded.next_ip());
+                for target in semantics.targets.iter() {
+                    leaders.insert(*target);
+                    queue.push(*target);
+                }
+                let falls_through = semantics.flow.falls_through() && !ends_program(instruction);
+                if semantics.flow != FlowKind::Fallthrough && falls_through {
+                    leaders.insert(next);
+                }
+                instructions.insert(address, decoded);
+                if !falls_through {
+                    break;
+                }
+                address = next;
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for leader in leaders.iter().filter(|leader| instructions.contains_key(leader)) {
+            let mut block = BasicBlock { start: *leader, instructions: Vec::new() };
+            let mut address = *leader;
+            while let Some(decoded) = instructions.get(&address) {
+                block.instructions.push(decoded.clone());
+                let flow = decoded.instruction.as_ref().unwrap().semantics(address).flow;
+                address = (address.0, decoded.next_ip());
+                if flow != FlowKind::Fallthrough || leaders.contains(&address) {
+                    break;
+                }
+            }
+            blocks.insert(*leader, block);
+        }
+
+        let mut edges = Vec::new();
+        for block in blocks.values() {
+            let last = block.last();
+            let instruction = last.instruction.as_ref().unwrap();
+            let semantics = instruction.semantics(last.address);
+            let mut add = |to: (u16, u16), kind: EdgeKind| {
+                if blocks.contains_key(&to) {
+                    edges.push(Edge { from: block.start, to, kind, back: false });
+                }
+            };
+            let next = block.end();
+
+            match semantics.flow {
+                FlowKind::Fallthrough => add(next, EdgeKind::Fallthrough),
+                FlowKind::Jump => semantics.targets.iter().for_each(|target| add(*target, EdgeKind::Jump)),
+                FlowKind::ConditionalJump => {
+                    semantics.targets.iter().for_each(|target| add(*target, EdgeKind::Branch));
+                    add(next, EdgeKind::Fallthrough);
+                }
+                FlowKind::Call => {
+                    semantics.targets.iter().for_each(|target| add(*target, EdgeKind::Call));
+                    add(next, EdgeKind::Fallthrough);
+                }
+                FlowKind::Interrupt => if !ends_program(instruction) {
+                    add(next, EdgeKind::Interrupt(interrupt_vector(instruction)));
+                },
+                FlowKind::Return | FlowKind::Halt => ()
+            }
+        }
+
+        let mut graph = Self { entries: entries.to_vec(), blocks, edges, loops: Vec::new() };
+        graph.find_loops();
+        graph
+    }
+
+    fn decode(image: &Image, position: usize, address: (u16, u16)) -> Option<DisassembledInstruction> {
+        Disassembler::new(&image.bytes[position..], address.0, address.1).decode_at(0)
+    }
+
+    // Marks back edges with a depth first search and collects the blocks of each loop. Calls
+    // aren't followed, so a routine called from a loop isn't part of it, but called routines are
+    // searched on their own.
+    fn find_loops(&mut self) {
+        let mut roots = self.entries.clone();
+        roots.extend(self.edges.iter().filter(|edge| edge.kind == EdgeKind::Call).map(|edge| edge.to));
+
+        let mut visited = HashSet::new();
+        let mut on_stack = HashSet::new();
+        let mut back = Vec::new();
+        for root in roots.into_iter().filter(|root| self.blocks.contains_key(root)) {
+            if !visited.insert(root) {
+                continue;
+            }
+            on_stack.insert(root);
+            // Each frame is a block and the index of the next of its edges to look at
+            let mut stack = vec![(root, 0)];
+            while let Some((block, index)) = stack.last_mut() {
+                let block = *block;
+                let edge = self.edges.iter().enumerate()
+                    .filter(|(_, edge)| edge.from == block && edge.kind != EdgeKind::Call)
+                    .nth(*index);
+                *index += 1;
+                match edge {
+                    Some((number, edge)) => {
+                        if on_stack.contains(&edge.to) {
+                            back.push(number);
+                        } else if visited.insert(edge.to) {
+                            on_stack.insert(edge.to);
+                            stack.push((edge.to, 0));
+                        }
+                    }
+                    None => {
+                        on_stack.remove(&block);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+
+        for number in back {
+            self.edges[number].back = true;
+            let Edge { from: latch, to: header, .. } = self.edges[number];
+
+            // Everything that reaches the latch without going through the header, which is
+            // already in the set so its predecessors aren't followed
+            let mut blocks = BTreeSet::new();
+            blocks.insert(header);
+            let mut work = vec![latch];
+            while let Some(block) = work.pop() {
+                if blocks.insert(block) {
+                    work.extend(self.predecessors(block));
+                }
+            }
+
+            let counted = self.blocks[&latch].last().instruction.as_ref().is_some_and(is_loop_instruction);
+            self.loops.push(Loop { header, latch, blocks, counted });
+        }
+        self.loops.sort_by_key(|found| (found.header, found.latch));
+    }
+
+    pub fn entries(&self) -> &[(u16, u16)] {
+        &self.entries
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: (u16, u16)) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    pub fn block_containing(&self, address: (u16, u16)) -> Option<&BasicBlock> {
+        self.blocks.range(..=address).next_back().map(|(_, block)| block).filter(|block| block.contains(address))
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    // Blocks that control can go to from `block`, not counting called routines
+    pub fn successors(&self, block: (u16, u16)) -> Vec<(u16, u16)> {
+        self.edges.iter().filter(|edge| edge.from == block && edge.kind != EdgeKind::Call).map(|edge| edge.to).collect()
+    }
+
+    pub fn predecessors(&self, block: (u16, u16)) -> Vec<(u16, u16)> {
+        self.edges.iter().filter(|edge| edge.to == block && edge.kind != EdgeKind::Call).map(|edge| edge.from).collect()
+    }
+
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with_symbols(&SymbolTable::new())
+    }
+
+    // Graphviz source with a box per block. Blocks and targets that have a symbol are shown by name.
+    pub fn to_dot_with_symbols(&self, symbols: &SymbolTable) -> String {
+        let options = FormatOptions::nasm();
+        let node = |address: (u16, u16)| format!("\"{:04X}:{:04X}\"", address.0, address.1);
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let mut label = match symbols.name_at(block.start.0, block.start.1) {
+                Some(name) => format!("{}:\\l", escape(name)),
+                None => format!("{:04X}:{:04X}:\\l", block.start.0, block.start.1)
+            };
+            for decoded in block.instructions.iter() {
+                let text = options.format_with_symbols(decoded.instruction.as_ref().unwrap(), decoded.address, symbols);
+                label += &format!("    {}\\l", escape(&text));
+            }
+            let style = if self.entries.contains(&block.start) { ", style=bold" } else { "" };
+            writeln!(dot, "    {} [label=\"{}\"{}];", node(block.start), label, style).unwrap();
+        }
+        for edge in self.edges.iter() {
+            let mut attributes = match edge.kind {
+                EdgeKind::Fallthrough => Vec::new(),
+                EdgeKind::Jump => vec![String::from("label=\"jump\"")],
+                EdgeKind::Branch => vec![String::from("label=\"taken\""), String::from("color=darkgreen")],
+                EdgeKind::Call => vec![String::from("label=\"call\""), String::from("color=blue")],
+                EdgeKind::Interrupt(vector) => vec![format!("label=\"int 0x{:02x}\"", vector), String::from("color=purple")]
+            };
+            if edge.back {
+                attributes.push(String::from("style=dashed"));
+            }
+            let attributes = if attributes.is_empty() { String::new() } else { format!(" [{}]", attributes.join(", ")) };
+            writeln!(dot, "    {} -> {}{};", node(edge.from), node(edge.to), attributes).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
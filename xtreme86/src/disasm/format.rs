@@ -0,0 +1,215 @@
+use crate::cpu::{Reg8, Reg16};
+use crate::cpu::instruction::Instruction;
+use crate::cpu::instruction::args::{DstArg, Size, get_opcode_mnemonic};
+use crate::symbols::SymbolTable;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    // What Instruction's Display prints, e.g. "mov word [BP + 65534], AX"
+    Native,
+    // Accepted back by nasm, e.g. "mov [bp-0x2], ax"
+    Nasm
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LetterCase {
+    // Lowercase mnemonics and uppercase registers
+    AsDecoded,
+    Lower,
+    Upper
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub syntax: Syntax,
+    pub case: LetterCase,
+    pub hex: bool,
+    // Shows [bp - 2] instead of [bp + 65534]
+    pub signed_displacements: bool,
+    // Shows segment prefixes, e.g. [es:di]
+    pub segment_overrides: bool,
+    // Adds short/near/far to jumps and calls
+    pub branch_qualifiers: bool,
+    // Shows where relative jumps and calls land instead of their displacement
+    pub absolute_targets: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            syntax: Syntax::Native,
+            case: LetterCase::AsDecoded,
+            hex: false,
+            signed_displacements: false,
+            segment_overrides: false,
+            branch_qualifiers: false,
+            absolute_targets: false,
+        }
+    }
+}
+
+impl FormatOptions {
+    pub fn nasm() -> Self {
+        Self {
+            syntax: Syntax::Nasm,
+            case: LetterCase::Lower,
+            hex: true,
+            signed_displacements: true,
+            segment_overrides: true,
+            branch_qualifiers: true,
+            absolute_targets: true,
+        }
+    }
+
+    // `address` is the CS:IP of the instruction, used to resolve relative targets
+    pub fn format(&self, instruction: &Instruction, address: (u16, u16)) -> String {
+        self.format_with_symbols(instruction, address, &SymbolTable::new())
+    }
+
+    // Like format, but absolute targets that have a symbol are shown by name
+    pub fn format_with_symbols(&self, instruction: &Instruction, address: (u16, u16), symbols: &SymbolTable) -> String {
+        let text = self.format_operation(instruction, address, symbols);
+        if instruction.lock {
+            self.mnemonic("lock ") + &text
+        } else {
+            text
+        }
+    }
+
+    fn format_operation(&self, instruction: &Instruction, address: (u16, u16), symbols: &SymbolTable) -> String {
+        let mnemonic = self.mnemonic(&instruction.mnemonic.clone().map_or(String::new(), |mnemonic| mnemonic.get(instruction.clone())));
+        let next_ip = address.1.wrapping_add(instruction.length as u16);
+
+        if let Some(DstArg::Opcode(op)) = instruction.dst {
+            // A REP prefix and the string instruction it repeats
+            let name = get_opcode_mnemonic(op).map_or_else(|| self.number(op as u32), |name| self.mnemonic(&name));
+            return format!("{} {}", self.override_prefix(instruction).unwrap_or_default() + &mnemonic, name);
+        }
+
+        if let Some(target) = instruction.relative_target(next_ip) {
+            let displacement = target.wrapping_sub(address.1) as i16;
+            let qualifier = match (self.branch_qualifiers, instruction.dst) {
+                (true, Some(DstArg::Imm8(_))) => "short ",
+                (true, Some(DstArg::Imm16(_))) => "near ",
+                _ => ""
+            };
+            let operand = if self.absolute_targets {
+                symbols.name_at(address.0, target).map_or_else(|| self.number(target as u32), String::from)
+            } else if self.syntax == Syntax::Nasm {
+                format!("${}{}", if displacement < 0 { "-" } else { "+" }, self.number(displacement.unsigned_abs() as u32))
+            } else {
+                instruction.dst.unwrap().to_string()
+            };
+            return format!("{}{} {}{}", self.override_prefix(instruction).unwrap_or_default(), mnemonic, self.mnemonic(qualifier), operand);
+        }
+
+        let args = if instruction.has_implicit_operands() { [None, None, None] } else { [instruction.dst, instruction.src, instruction.imm] };
+        let operands: Vec<String> = args.iter().flatten()
+            .map(|arg| self.operand(instruction, *arg))
+            .collect();
+
+        let has_memory = args.iter().flatten().any(|arg| Self::memory_size(*arg).is_some());
+        let prefix = if has_memory { None } else { self.override_prefix(instruction) };
+        let mnemonic = prefix.unwrap_or_default() + &mnemonic;
+
+        if operands.is_empty() {
+            mnemonic
+        } else {
+            format!("{} {}", mnemonic, operands.join(", "))
+        }
+    }
+
+    fn operand(&self, instruction: &Instruction, arg: DstArg) -> String {
+        match arg {
+            DstArg::Reg8(id) => self.register(&Reg8::from_index(id).map_or(String::new(), |reg| reg.to_string())),
+            DstArg::Reg16(id) => self.register(&Reg16::from_index(id).map_or(String::new(), |reg| reg.to_string())),
+            DstArg::Reg(reg) => self.register(&format!("{:?}", reg)),
+            DstArg::Imm8(val) => if Self::sign_extends_imm8(instruction) {
+                self.number((val as i8) as i16 as u16 as u32)
+            } else {
+                self.number(val as u32)
+            },
+            DstArg::Imm16(val) => self.number(val as u32),
+            DstArg::Imm32(val) => match self.syntax {
+                Syntax::Native if !self.hex => val.to_string(),
+                _ => format!("{}:{}", self.number(val >> 16), self.number(val & 0xFFFF))
+            },
+            DstArg::Opcode(op) => self.number(op as u32),
+            _ => self.memory(instruction, arg)
+        }
+    }
+
+    fn memory(&self, instruction: &Instruction, arg: DstArg) -> String {
+        let (bases, displacement, size) = match arg {
+            DstArg::Ptr(ptr, size) => (vec![], Some(ptr), size),
+            DstArg::RegPtr(reg, size) => (vec![reg], None, size),
+            DstArg::RegPtrImm(reg, imm, size) => (vec![reg], Some(imm), size),
+            DstArg::RegPtrOff(reg1, reg2, size) => (vec![reg1, reg2], None, size),
+            DstArg::RegPtrOffImm(reg1, reg2, imm, size) => (vec![reg1, reg2], Some(imm), size),
+            _ => unreachable!()
+        };
+        let separator = if self.syntax == Syntax::Nasm { "" } else { " " };
+
+        let mut inner: String = bases.iter().map(|reg| self.register(&format!("{:?}", reg)))
+            .collect::<Vec<String>>().join(&format!("{}+{}", separator, separator));
+        if let Some(displacement) = displacement {
+            if bases.is_empty() {
+                inner = self.number(displacement as u32);
+            } else if self.signed_displacements && (displacement as i16) < 0 {
+                inner += &format!("{}-{}{}", separator, separator, self.number((displacement as i16).unsigned_abs() as u32));
+            } else {
+                inner += &format!("{}+{}{}", separator, separator, self.number(displacement as u32));
+            }
+        }
+        if let (true, Some(seg)) = (self.segment_overrides, instruction.segment_override) {
+            inner = format!("{}:{}", self.register(&format!("{:?}", seg)), inner);
+        }
+
+        // Indirect far jumps and calls read a segment:offset pair
+        if instruction.opcode == 0xFF && (instruction.reg_bits == 0b011 || instruction.reg_bits == 0b101)
+            && (self.syntax == Syntax::Nasm || self.branch_qualifiers) {
+            return format!("{} [{}]", self.mnemonic("far"), inner);
+        }
+        format!("{} [{}]", self.mnemonic(&size.to_string()), inner)
+    }
+
+    fn override_prefix(&self, instruction: &Instruction) -> Option<String> {
+        if !self.segment_overrides {
+            return None;
+        }
+        instruction.segment_override.map(|seg| self.mnemonic(&format!("{:?}", seg).to_lowercase()) + " ")
+    }
+
+    // The group 1 immediates of opcode 0x83 are sign extended to the size of the destination, and
+    // push imm8 and imul r16, r/m16, imm8 use a sign extended word
+    fn sign_extends_imm8(instruction: &Instruction) -> bool {
+        (instruction.opcode == 0x83 && matches!(instruction.src, Some(DstArg::Imm8(_)))) || instruction.opcode == 0x6A
+            || instruction.opcode == 0x6B
+    }
+
+    fn memory_size(arg: DstArg) -> Option<Size> {
+        match arg {
+            DstArg::Ptr(_, size) | DstArg::RegPtr(_, size) | DstArg::RegPtrImm(_, _, size)
+            | DstArg::RegPtrOff(_, _, size) | DstArg::RegPtrOffImm(_, _, _, size) => Some(size),
+            _ => None
+        }
+    }
+
+    fn number(&self, val: u32) -> String {
+        if self.hex { format!("0x{:X}", val) } else { val.to_string() }
+    }
+
+    fn mnemonic(&self, text: &str) -> String {
+        match self.case {
+            LetterCase::Upper => text.to_uppercase(),
+            _ => text.to_lowercase()
+        }
+    }
+
+    fn register(&self, text: &str) -> String {
+        match self.case {
+            LetterCase::Lower => text.to_lowercase(),
+            _ => text.to_uppercase()
+        }
+    }
+}
@@ -0,0 +1,98 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Com,
+    Exe
+}
+
+// A DOS program as it would be laid out in memory. Segments are relative to the load segment,
+// so an EXE image starts at 0000:0000 and a COM image at 0000:0100.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub format: ImageFormat,
+    pub bytes: Vec<u8>,
+    // Offset of the first byte from the start of segment 0
+    pub origin: u16,
+    pub entry: (u16, u16),
+    pub stack: (u16, u16),
+    // Segment:offset of every word the loader adds the load segment to
+    pub relocations: Vec<(u16, u16)>,
+}
+
+fn read_word(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    match bytes.get(offset..offset + 2) {
+        Some(word) => Ok(u16::from_le_bytes([word[0], word[1]])),
+        None => Err(String::from("Truncated MZ header"))
+    }
+}
+
+impl Image {
+    // Picks the format from the "MZ" signature, like DOS does
+    pub fn load(bytes: Vec<u8>) -> Result<Self, String> {
+        if bytes.starts_with(b"MZ") || bytes.starts_with(b"ZM") {
+            Self::from_exe(&bytes)
+        } else {
+            Self::from_com(bytes)
+        }
+    }
+
+    pub fn from_com(bytes: Vec<u8>) -> Result<Self, String> {
+        if bytes.len() > 0xFF00 {
+            return Err(String::from("COM files can't be larger than 65280 bytes"));
+        }
+        Ok(Self {
+            format: ImageFormat::Com,
+            bytes,
+            origin: 0x100,
+            entry: (0, 0x100),
+            stack: (0, 0xFFFE),
+            relocations: Vec::new(),
+        })
+    }
+
+    pub fn from_exe(file: &[u8]) -> Result<Self, String> {
+        let last_page_bytes = read_word(file, 0x02)? as usize;
+        let pages = read_word(file, 0x04)? as usize;
+        let relocation_count = read_word(file, 0x06)? as usize;
+        let header_size = read_word(file, 0x08)? as usize * 16;
+        let relocation_table = read_word(file, 0x18)? as usize;
+
+        let mut file_size = pages * 512;
+        if last_page_bytes != 0 {
+            file_size = file_size.saturating_sub(512 - last_page_bytes);
+        }
+        let end = file_size.min(file.len());
+        if header_size > end {
+            return Err(String::from("MZ header is larger than the file"));
+        }
+
+        let relocations = (0..relocation_count)
+            .map(|index| Ok((read_word(file, relocation_table + index * 4 + 2)?, read_word(file, relocation_table + index * 4)?)))
+            .collect::<Result<Vec<(u16, u16)>, String>>()?;
+
+        Ok(Self {
+            format: ImageFormat::Exe,
+            bytes: file[header_size..end].to_vec(),
+            origin: 0,
+            entry: (read_word(file, 0x16)?, read_word(file, 0x14)?),
+            stack: (read_word(file, 0x0E)?, read_word(file, 0x10)?),
+            relocations,
+        })
+    }
+
+    pub fn position_of(&self, address: (u16, u16)) -> Option<usize> {
+        let linear = (address.0 as usize) * 16 + address.1 as usize;
+        let position = linear.checked_sub(self.origin as usize)?;
+        if position < self.bytes.len() { Some(position) } else { None }
+    }
+
+    // The address of a byte, relative to the entry segment when it can be reached from it
+    pub fn address_of(&self, position: usize) -> (u16, u16) {
+        let linear = position + self.origin as usize;
+        let base = self.entry.0 as usize * 16;
+        if linear >= base && linear - base <= 0xFFFF {
+            (self.entry.0, (linear - base) as u16)
+        } else {
+            ((linear >> 4) as u16, (linear & 0xF) as u16)
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt::Formatter;
+use crate::cpu::Model;
+use crate::cpu::instruction::{Instruction, InstructionDecoder, DecodeError};
+use crate::cpu::instruction::args::DstArg;
+use crate::cpu::instruction::opcode::{Opcode, Mnemonic};
+use crate::disasm::format::FormatOptions;
+
+pub mod format;
+pub mod image;
+pub mod analysis;
+pub mod cfg;
+
+#[derive(Clone, Debug)]
+pub struct DisassembledInstruction {
+    pub address: (u16, u16),
+    pub bytes: Vec<u8>,
+    pub length: usize,
+    // None for bytes that couldn't be decoded, which are shown as `db`
+    pub instruction: Option<Instruction>,
+    // Why the decoder rejected them, if it did. Bytes it decodes but can't be formatted have none.
+    pub error: Option<DecodeError>,
+    pub text: String,
+}
+
+impl DisassembledInstruction {
+    pub fn is_data(&self) -> bool {
+        self.instruction.is_none()
+    }
+
+    // Offset of the instruction that follows this one
+    pub fn next_ip(&self) -> u16 {
+        self.address.1.wrapping_add(self.length as u16)
+    }
+}
+
+impl std::fmt::Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        write!(f, "{:04X}:{:04X}  {:<16}{}", self.address.0, self.address.1, bytes.join(""), self.text)
+    }
+}
+
+// Decodes a slice of machine code on its own, without a CPU. The first byte is at `cs:ip`.
+pub struct Disassembler<'a> {
+    bytes: &'a [u8],
+    cs: u16,
+    ip: u16,
+    position: usize,
+    opcodes: &'static [Option<Opcode>; 256],
+    model: Model,
+    options: FormatOptions,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(bytes: &'a [u8], cs: u16, ip: u16) -> Self {
+        Self {
+            bytes,
+            cs,
+            ip,
+            position: 0,
+            opcodes: Opcode::table(),
+            model: Model::default(),
+            options: FormatOptions::default(),
+        }
+    }
+
+    // Decodes what `model` would run, e.g. 0xC0 as ret on the 8086
+    pub fn with_model(bytes: &'a [u8], cs: u16, ip: u16, model: Model) -> Self {
+        Self { model, ..Self::new(bytes, cs, ip) }
+    }
+
+    pub fn with_options(bytes: &'a [u8], cs: u16, ip: u16, options: FormatOptions) -> Self {
+        Self { options, ..Self::new(bytes, cs, ip) }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn options(&self) -> &FormatOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: FormatOptions) {
+        self.options = options;
+    }
+
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    // Moves the iterator to an offset within the slice
+    pub fn seek(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    pub fn address_of(&self, position: usize) -> (u16, u16) {
+        (self.cs, self.ip.wrapping_add(position as u16))
+    }
+
+    // Offset within the slice of a CS-relative offset, if the slice covers it
+    pub fn position_of(&self, offset: u16) -> Option<usize> {
+        let position = offset.wrapping_sub(self.ip) as usize;
+        if position < self.bytes.len() { Some(position) } else { None }
+    }
+
+    pub fn decode_at(&self, position: usize) -> Option<DisassembledInstruction> {
+        let remaining = self.bytes.get(position..)?;
+        if remaining.is_empty() {
+            return None;
+        }
+
+        let decoded = InstructionDecoder::with_model(self.opcodes, remaining, self.model).get();
+        let error = decoded.as_ref().err().copied();
+
+        Some(match decoded.ok().filter(|instruction| self.can_format(instruction)) {
+            Some(instruction) => DisassembledInstruction {
+                address: self.address_of(position),
+                bytes: remaining[..instruction.length].to_vec(),
+                length: instruction.length,
+                text: self.options.format(&instruction, self.address_of(position)),
+                instruction: Some(instruction),
+                error: None,
+            },
+            None => DisassembledInstruction {
+                address: self.address_of(position),
+                bytes: vec![remaining[0]],
+                length: 1,
+                instruction: None,
+                error,
+                text: format!("db 0x{:02X}", remaining[0]),
+            }
+        })
+    }
+
+    // Reserved ModR/M encodings have no mnemonic, and a REP prefix can only be shown on the string
+    // instruction it repeats
+    fn can_format(&self, instruction: &Instruction) -> bool {
+        let mnemonic = instruction.mnemonic.clone().map_or(String::new(), |mnemonic| mnemonic.get(instruction.clone()));
+        !mnemonic.is_empty() && [instruction.dst, instruction.src].iter().all(|arg| match arg {
+            Some(DstArg::Opcode(op)) => matches!(op, 0x6C..=0x6F | 0xA4..=0xA7 | 0xAA..=0xAF)
+                && matches!(self.opcodes[*op as usize], Some(Opcode { mnemonic: Mnemonic::Static(_), .. })),
+            _ => true
+        })
+    }
+}
+
+impl<'a> Iterator for Disassembler<'a> {
+    type Item = DisassembledInstruction;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let decoded = self.decode_at(self.position)?;
+        self.position += decoded.length;
+        Some(decoded)
+    }
+}
@@ -3,3 +3,6 @@ extern crate dyn_clone;
 
 pub mod cpu;
 pub mod peripheral;
+pub mod symbols;
+pub mod disasm;
+pub mod asm;
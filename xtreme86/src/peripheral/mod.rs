@@ -1,13 +1,22 @@
 use crate::cpu::CPU;
 use dyn_clone::{DynClone};
 
-pub trait Peripheral : DynClone {
+pub trait Peripheral : DynClone + Send {
     fn init(&self, comp: &mut CPU, index: usize);
+    // Stands in for the handler of a vector hooked with CPU::hook_interrupt, and returns the clocks
+    // it took. FLAGS, CS and IP are on the stack as the interrupt pushed them. Once this returns
+    // they're popped as by RETF 2, so SP ends up where it was before the interrupt, CS:IP set
+    // here are overwritten by the return address and flags set here stay, apart from IF and TF.
     fn handle_interrupt(&mut self, comp: &mut CPU, int_num: u8) -> usize;
     fn handle_mem_read_byte(&mut self, address: u16) -> u8;
     fn handle_mem_read_word(&mut self, address: u16) -> u16;
     fn handle_mem_write_byte(&mut self, address: u16, val: u8);
     fn handle_mem_write_word(&mut self, address: u16, val: u16);
+    // Called after every instruction the CPU finishes, comp.cycles() says when
+    fn tick(&mut self, _comp: &mut CPU) {}
+    // Called by CPU::reset after the registers are reset. A cleared RAM loses the interrupts
+    // hooked in init, so hook them again here.
+    fn reset(&mut self, _comp: &mut CPU, _index: usize) {}
 }
 
 dyn_clone::clone_trait_object!(Peripheral);
@@ -0,0 +1,237 @@
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub segment: u16,
+    pub offset: u16,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    by_name: HashMap<String, (u16, u16)>,
+    by_address: BTreeMap<(u16, u16), String>,
+}
+
+const DIRECTIVES: [&str; 15] = ["cpu", "org", "bits", "section", "segment", "global", "extern", "align", "alignb",
+    "use16", "absolute", "common", "struc", "endstruc", "default"];
+
+const DATA_DIRECTIVES: [&str; 12] = ["db", "dw", "dd", "dq", "dt", "resb", "resw", "resd", "resq", "rest", "times", "incbin"];
+
+fn parse_number(text: &str) -> Option<u32> {
+    let text = text.trim().to_lowercase();
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = text.strip_suffix('h') {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+// Number of bytes a listing data field stands for, e.g. "B90A00", "<res 10>" or "00<rep 4>"
+fn data_length(data: &str) -> u32 {
+    let data = data.trim().trim_end_matches('-');
+    match data.find('<') {
+        Some(start) => {
+            // NASM prints these counts in hex without a suffix
+            let inner = data[start + 1..].trim_end_matches('>');
+            let count = inner.split_whitespace().nth(1).and_then(|count| u32::from_str_radix(count, 16).ok()).unwrap_or(0);
+            if inner.starts_with("res") {
+                count
+            } else {
+                (data[..start].len() as u32 / 2) * count
+            }
+        }
+        None => data.chars().filter(|c| c.is_ascii_hexdigit()).count() as u32 / 2
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            by_name: HashMap::new(),
+            by_address: BTreeMap::new(),
+        }
+    }
+
+    // Parses the output of `nasm -l`. Listing offsets are relative to the start of the
+    // output, so the ORG of the source is added to get offsets within `segment`.
+    pub fn from_nasm_listing(listing: &str, segment: u16) -> Self {
+        let mut table = Self::new();
+        let mut origin = 0u32;
+        let mut location = 0u32;
+        let mut global = String::new();
+
+        for line in listing.lines() {
+            let rest = line.trim_start();
+            let number_len = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+            if number_len == 0 {
+                continue;
+            }
+            let rest = rest[number_len..].strip_prefix(' ').unwrap_or("");
+
+            let is_hex = |text: &str| text.len() == 8 && text.chars().all(|c| c.is_ascii_hexdigit());
+            // Comments and strings can hold any UTF-8, so nothing is sliced off the middle of a character
+            let source = if rest.len() > 9 && rest.get(..8).is_some_and(is_hex) && rest.as_bytes()[8] == b' ' {
+                let offset = u32::from_str_radix(&rest[..8], 16).unwrap();
+                let fields = &rest[9..];
+                let mut data_end = fields.len().min(18);
+                while !fields.is_char_boundary(data_end) {
+                    data_end -= 1;
+                }
+                location = offset + data_length(&fields[..data_end]);
+                if data_end < fields.len() { &fields[data_end..] } else { "" }
+            } else {
+                rest
+            };
+
+            let source = source.trim_start();
+            let source = match source.strip_prefix('<') {
+                Some(macro_line) => macro_line.split_once('>').map_or("", |(_, text)| text.trim_start()),
+                None => source
+            };
+            let source = source.split(';').next().unwrap_or("");
+
+            let mut tokens = source.split_whitespace();
+            let first = match tokens.next() {
+                Some(first) => first,
+                None => continue
+            };
+            let second = tokens.next().map(|token| token.to_lowercase());
+
+            let directive = first.trim_start_matches('[').to_lowercase();
+            if directive == "org" {
+                let value = source.trim_start().trim_start_matches('[').split_whitespace().nth(1).unwrap_or("");
+                origin = parse_number(value.trim_end_matches(']')).unwrap_or(origin);
+                continue;
+            }
+            if first.starts_with('%') || first.starts_with('[') || DIRECTIVES.contains(&directive.as_str()) {
+                continue;
+            }
+
+            let name = if let Some(name) = first.strip_suffix(':') {
+                name
+            } else if second.as_deref().is_some_and(|second| DATA_DIRECTIVES.contains(&second)) {
+                first
+            } else {
+                continue
+            };
+
+            // The address of a label is the location counter before its own line is emitted
+            let address = if source.trim_start() != first && rest.len() > 9 && is_hex(&rest[..8]) {
+                u32::from_str_radix(&rest[..8], 16).unwrap()
+            } else {
+                location
+            };
+
+            let full_name = if name.starts_with('.') {
+                format!("{}{}", global, name)
+            } else {
+                global = name.to_string();
+                name.to_string()
+            };
+            table.insert(&full_name, segment, (origin + address) as u16);
+        }
+
+        table
+    }
+
+    // Parses a map file written by NASM's `[map all]` / `[map symbols]` for the bin format
+    pub fn from_nasm_map(map: &str, segment: u16) -> Self {
+        let mut table = Self::new();
+        let mut in_symbols = false;
+        let mut in_section = false;
+
+        for line in map.lines() {
+            if line.starts_with("----") {
+                in_section = in_symbols && line.starts_with("---- Section");
+                continue;
+            } else if line.starts_with("--") {
+                in_symbols = line.starts_with("-- Symbols");
+                in_section = false;
+                continue;
+            }
+            if !in_section {
+                continue;
+            }
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if let [_, virtual_address, name] = tokens.as_slice() {
+                if let Ok(address) = u32::from_str_radix(virtual_address, 16) {
+                    table.insert(name, segment, address as u16);
+                }
+            }
+        }
+
+        table
+    }
+
+    pub fn insert(&mut self, name: &str, segment: u16, offset: u16) {
+        if let Some(old) = self.by_name.insert(name.to_string(), (segment, offset)) {
+            if self.by_address.get(&old).is_some_and(|old_name| old_name == name) {
+                self.by_address.remove(&old);
+            }
+        }
+        self.by_address.entry((segment, offset)).or_insert_with(|| name.to_string());
+    }
+
+    pub fn extend(&mut self, other: SymbolTable) {
+        for symbol in other.symbols() {
+            self.insert(&symbol.name, symbol.segment, symbol.offset);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.by_name.iter()
+            .map(|(name, (segment, offset))| Symbol { name: name.clone(), segment: *segment, offset: *offset })
+            .collect();
+        symbols.sort_by(|a, b| (a.segment, a.offset, &a.name).cmp(&(b.segment, b.offset, &b.name)));
+        symbols
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<(u16, u16)> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn name_at(&self, segment: u16, offset: u16) -> Option<&str> {
+        self.by_address.get(&(segment, offset)).map(|name| name.as_str())
+    }
+
+    // The closest label at or below the address in the same segment, with the distance to it
+    pub fn nearest(&self, segment: u16, offset: u16) -> Option<(&str, u16)> {
+        self.by_address.range((segment, 0)..=(segment, offset)).next_back()
+            .map(|((_, label_offset), name)| (name.as_str(), offset - label_offset))
+    }
+
+    pub fn describe(&self, segment: u16, offset: u16) -> Option<String> {
+        self.nearest(segment, offset).map(|(name, distance)| if distance == 0 {
+            name.to_string()
+        } else {
+            format!("{}+{:X}h", name, distance)
+        })
+    }
+
+    // Accepts "label", "label+10h", "label+0x10" and "1234:0010"
+    pub fn resolve(&self, location: &str) -> Option<(u16, u16)> {
+        let location = location.trim();
+        if let Some((segment, offset)) = location.split_once(':') {
+            return Some((u16::from_str_radix(segment.trim(), 16).ok()?, u16::from_str_radix(offset.trim(), 16).ok()?));
+        }
+        match location.split_once('+') {
+            Some((name, displacement)) => {
+                let (segment, offset) = self.lookup(name.trim())?;
+                Some((segment, offset.wrapping_add(parse_number(displacement)? as u16)))
+            }
+            None => self.lookup(location)
+        }
+    }
+}
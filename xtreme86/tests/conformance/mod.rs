@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Formatter;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use serde_json::Value;
+use xtreme86::cpu::{CPU, Regs, Flags, Model};
+
+// Room for everything segment:offset can address, past 1 MiB included
+const RAM_SIZE: usize = 0x110000;
+
+const REGISTERS: [(&str, Regs); 14] = [
+    ("ax", Regs::AX), ("bx", Regs::BX), ("cx", Regs::CX), ("dx", Regs::DX),
+    ("sp", Regs::SP), ("bp", Regs::BP), ("si", Regs::SI), ("di", Regs::DI),
+    ("cs", Regs::CS), ("ds", Regs::DS), ("es", Regs::ES), ("ss", Regs::SS),
+    ("ip", Regs::IP), ("flags", Regs::FLAGS)
+];
+
+const PREFIXES: [u8; 7] = [0x26, 0x2E, 0x36, 0x3E, 0xF0, 0xF2, 0xF3];
+
+// Opcodes whose ModR/M reg field picks the operation, so each one is reported on its own
+const GROUPS: [u8; 14] = [0x80, 0x81, 0x82, 0x83, 0xC0, 0xC1, 0xD0, 0xD1, 0xD2, 0xD3, 0xF6, 0xF7, 0xFE, 0xFF];
+
+fn register_name(reg: Regs) -> &'static str {
+    REGISTERS.iter().find(|(_, register)| *register == reg).map(|(name, _)| *name).unwrap()
+}
+
+// Registers and memory as test vectors give them. Addresses are physical.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct State {
+    pub regs: Vec<(Regs, u16)>,
+    pub ram: Vec<(u32, u8)>,
+}
+
+impl State {
+    fn from_json(json: &Value) -> Result<Self, String> {
+        let mut state = State::default();
+        for (name, value) in json.get("regs").and_then(Value::as_object).ok_or("missing regs")? {
+            let reg = REGISTERS.iter().find(|(register, _)| register == name).ok_or_else(|| format!("unknown register {}", name))?.1;
+            let value = value.as_u64().filter(|value| *value <= 0xFFFF).ok_or_else(|| format!("invalid value for {}", name))?;
+            state.regs.push((reg, value as u16));
+        }
+        for entry in json.get("ram").and_then(Value::as_array).ok_or("missing ram")? {
+            match entry.as_array().map(|pair| pair.iter().map(Value::as_u64).collect::<Vec<_>>()).as_deref() {
+                Some([Some(address), Some(value)]) if *address < RAM_SIZE as u64 && *value <= 0xFF => state.ram.push((*address as u32, *value as u8)),
+                _ => return Err(format!("invalid ram entry {:?}", entry))
+            }
+        }
+        Ok(state)
+    }
+
+    // Test vectors only list what an instruction changed, so the rest is taken from `initial`
+    fn over(&self, initial: &State) -> State {
+        let mut regs: Vec<(Regs, u16)> = initial.regs.clone();
+        for (reg, value) in self.regs.iter() {
+            match regs.iter_mut().find(|(register, _)| register == reg) {
+                Some(entry) => entry.1 = *value,
+                None => regs.push((*reg, *value))
+            }
+        }
+        let mut ram: BTreeMap<u32, u8> = initial.ram.iter().copied().collect();
+        ram.extend(self.ram.iter().copied());
+        State { regs, ram: ram.into_iter().collect() }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TestCase {
+    pub name: String,
+    pub bytes: Vec<u8>,
+    pub initial: State,
+    pub expected: State,
+}
+
+impl TestCase {
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let name = json.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
+        let bytes = json.get("bytes").and_then(Value::as_array).ok_or("missing bytes")?.iter()
+            .map(|byte| byte.as_u64().filter(|byte| *byte <= 0xFF).map(|byte| byte as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or("invalid bytes")?;
+        let initial = State::from_json(json.get("initial").ok_or("missing initial state")?)?;
+        let expected = State::from_json(json.get("final").ok_or("missing final state")?)?.over(&initial);
+        Ok(Self { name, bytes, initial, expected })
+    }
+
+    // The opcode after any prefixes, like "00", with the reg field for groups, like "80.7"
+    pub fn opcode(&self) -> String {
+        let mut bytes = self.bytes.iter().skip_while(|byte| PREFIXES.contains(byte));
+        match (bytes.next(), bytes.next()) {
+            (Some(op), Some(modrm)) if GROUPS.contains(op) => format!("{:02X}.{}", op, (modrm >> 3) & 7),
+            (Some(op), _) => format!("{:02X}", op),
+            (None, _) => String::from("??")
+        }
+    }
+}
+
+// A file of test vectors is an array of cases
+pub fn parse_cases(text: &str) -> Result<Vec<TestCase>, String> {
+    let json: Value = serde_json::from_str(text).map_err(|error| error.to_string())?;
+    let cases = json.as_array().ok_or("expected an array of test cases")?;
+    cases.iter().enumerate()
+        .map(|(index, case)| TestCase::from_json(case).map_err(|error| format!("case {}: {}", index, error)))
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Register { reg: Regs, expected: u16, actual: u16 },
+    Memory { address: u32, expected: u8, actual: u8 },
+    Panic(String)
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Register { reg: Regs::FLAGS, expected, actual } => write!(f, "flags: expected {:04X} ({}), got {:04X} ({})",
+                expected, Flags::from_bits(*expected), actual, Flags::from_bits(*actual)),
+            Mismatch::Register { reg, expected, actual } => write!(f, "{}: expected {:04X}, got {:04X}", register_name(*reg), expected, actual),
+            Mismatch::Memory { address, expected, actual } => write!(f, "[{:05X}]: expected {:02X}, got {:02X}", address, expected, actual),
+            Mismatch::Panic(message) => write!(f, "panicked: {}", message)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub name: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OpcodeReport {
+    pub passed: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl OpcodeReport {
+    pub fn failed(&self) -> usize {
+        self.failures.len()
+    }
+
+    pub fn total(&self) -> usize {
+        self.passed + self.failed()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub opcodes: BTreeMap<String, OpcodeReport>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.opcodes.values().map(|report| report.passed).sum()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.opcodes.values().map(OpcodeReport::failed).sum()
+    }
+}
+
+// A line per opcode, with the first failure of each opcode that has any
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (opcode, report) in self.opcodes.iter() {
+            writeln!(f, "{:<5} {}/{} passed", opcode, report.passed, report.total())?;
+            if let Some(failure) = report.failures.first() {
+                writeln!(f, "      {}", failure.name)?;
+                for mismatch in failure.mismatches.iter() {
+                    writeln!(f, "        {}", mismatch)?;
+                }
+            }
+        }
+        write!(f, "{}/{} passed", self.passed(), self.passed() + self.failed())
+    }
+}
+
+// Runs single instruction test vectors, like the 8088 SingleStepTests suite, through
+// CPU::execute_next. Flags outside the mask aren't compared, since test vectors have values for
+// flags the instruction leaves undefined.
+pub struct Runner {
+    flags_mask: u16,
+    opcode_masks: HashMap<String, u16>,
+    model: Model,
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Runner {
+    // Compares the defined flags and ignores the reserved bits
+    pub fn new() -> Self {
+        Self { flags_mask: Flags::from_bits(0xFFFF).bits(), opcode_masks: HashMap::new(), model: Model::default() }
+    }
+
+    // The processor the vectors were recorded on, load_metadata takes it from the "cpu" field
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    // `opcode` is named like TestCase::opcode
+    pub fn set_flags_mask(&mut self, opcode: &str, mask: u16) {
+        self.opcode_masks.insert(opcode.to_uppercase(), mask);
+    }
+
+    pub fn flags_mask(&self, opcode: &str) -> u16 {
+        self.flags_mask & self.opcode_masks.get(opcode).copied().unwrap_or(0xFFFF)
+    }
+
+    // Reads the "flags-mask" of every opcode from a suite's metadata file, which looks like
+    // {"opcodes": {"D0": {"reg": {"4": {"flags-mask": 63487}}}, "27": {"flags-mask": 63487}}}
+    pub fn load_metadata(&mut self, text: &str) -> Result<(), String> {
+        let json: Value = serde_json::from_str(text).map_err(|error| error.to_string())?;
+        if let Some(name) = json.get("cpu").and_then(Value::as_str) {
+            self.model = Model::from_name(name).ok_or_else(|| format!("unknown cpu {}", name))?;
+        }
+        let opcodes = json.get("opcodes").and_then(Value::as_object).ok_or("missing opcodes")?;
+        for (opcode, info) in opcodes {
+            let mut entries = vec![(opcode.clone(), info)];
+            if let Some(regs) = info.get("reg").and_then(Value::as_object) {
+                entries.extend(regs.iter().map(|(reg, info)| (format!("{}.{}", opcode, reg), info)));
+            }
+            for (name, info) in entries {
+                if let Some(mask) = info.get("flags-mask") {
+                    let mask = mask.as_u64().filter(|mask| *mask <= 0xFFFF).ok_or_else(|| format!("invalid flags-mask for {}", name))?;
+                    self.set_flags_mask(&name, mask as u16);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn run_case(&self, case: &TestCase) -> Vec<Mismatch> {
+        let mut cpu = CPU::with_model(RAM_SIZE, self.model);
+        for (reg, value) in case.initial.regs.iter() {
+            cpu.set_reg(*reg, *value);
+        }
+        for (address, value) in case.initial.ram.iter() {
+            cpu.load(vec![*value], *address as usize);
+        }
+
+        // A bad instruction can trip an assertion in the emulator, which is a failure like any other
+        // Vectors end in the handler of an interrupt the instruction raised
+        let run = || {
+            cpu.execute_next();
+            if cpu.raised_interrupt().is_some() {
+                cpu.execute_next();
+            }
+        };
+        if let Err(panic) = catch_unwind(AssertUnwindSafe(run)) {
+            let message = panic.downcast_ref::<&str>().map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            return vec![Mismatch::Panic(message)];
+        }
+
+        let flags_mask = self.flags_mask(&case.opcode());
+        let mut mismatches = Vec::new();
+        for (reg, expected) in case.expected.regs.iter() {
+            let actual = cpu.read_reg(*reg).unwrap();
+            let mask = if *reg == Regs::FLAGS { flags_mask } else { 0xFFFF };
+            if (actual ^ expected) & mask != 0 {
+                mismatches.push(Mismatch::Register { reg: *reg, expected: *expected, actual });
+            }
+        }
+        for (address, expected) in case.expected.ram.iter() {
+            let actual = cpu.probe_mem(*address as usize);
+            if actual != *expected {
+                mismatches.push(Mismatch::Memory { address: *address, expected: *expected, actual });
+            }
+        }
+        mismatches
+    }
+
+    pub fn run(&self, cases: &[TestCase]) -> Report {
+        let mut report = Report::default();
+        for case in cases {
+            let mismatches = self.run_case(case);
+            let entry = report.opcodes.entry(case.opcode()).or_default();
+            if mismatches.is_empty() {
+                entry.passed += 1;
+            } else {
+                entry.failures.push(Failure { name: case.name.clone(), mismatches });
+            }
+        }
+        report
+    }
+
+    pub fn run_json(&self, text: &str) -> Result<Report, String> {
+        Ok(self.run(&parse_cases(text)?))
+    }
+
+    // Test vectors are usually distributed gzipped, so they need to be unpacked first
+    pub fn run_file<P: AsRef<Path>>(&self, path: P) -> Result<Report, String> {
+        let text = std::fs::read_to_string(path.as_ref()).map_err(|error| format!("{}: {}", path.as_ref().display(), error))?;
+        self.run_json(&text).map_err(|error| format!("{}: {}", path.as_ref().display(), error))
+    }
+}
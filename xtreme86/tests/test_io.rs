@@ -1,6 +1,7 @@
 use xtreme86::peripheral::Peripheral;
 use xtreme86::cpu::{CPU, Regs, WordPart};
 use xtreme86::cpu;
+use xtreme86::asm;
 use std::fs::File;
 use std::fs;
 use std::io::Read;
@@ -114,7 +115,7 @@ fn load_binary(filename: &str) -> Vec<u8> {
     buffer
 }
 
-fn new_cpu_from_file(filename: &str) -> cpu::CPU {
+fn new_cpu_from_code(code: Vec<u8>) -> cpu::CPU {
     let mut computer = cpu::CPU::new(0x7FFFFF);
     computer.set_reg(cpu::Regs::SS, 0x003F);
     computer.set_reg(cpu::Regs::CS, 0x103F);
@@ -122,12 +123,16 @@ fn new_cpu_from_file(filename: &str) -> cpu::CPU {
     computer.set_reg(cpu::Regs::SP, 0xFFFF);
     computer.set_reg(cpu::Regs::IP, 0x0000);
 
-    let buffer = load_binary(filename);
-    computer.load(buffer, 0x103F0);
+    computer.load(code, 0x103F0);
 
     computer
 }
 
+fn new_cpu_from_source(filename: &str) -> cpu::CPU {
+    let source = String::from_utf8(load_binary(filename)).expect("Source isn't UTF-8");
+    new_cpu_from_code(asm::assemble(&source).unwrap())
+}
+
 impl TestDevice {
     fn new() -> Self {
         TestDevice {
@@ -148,7 +153,7 @@ impl StringDevice {
 
 #[test]
 fn test_in_out() {
-    let mut comp = new_cpu_from_file("obj/io.out");
+    let mut comp = new_cpu_from_source("src/io.asm");
     comp.hook_peripheral(Box::new(TestDevice::new()));
     comp.hook_peripheral(Box::new(StringDevice::new()));
 
@@ -169,3 +174,18 @@ fn test_in_out() {
         assert_eq!(comp.probe_mem_es(i), string[i as usize]);
     }
 }
+
+#[test]
+fn test_rep_insw() {
+    // mov dx, 0xF2 / mov cx, 2 / rep insw
+    let mut comp = cpu::CPU::new(0xFFFF);
+    comp.load(vec![0xBA, 0xF2, 0x00, 0xB9, 0x02, 0x00, 0xF3, 0x6D], 0x100);
+    comp.hook_peripheral(Box::new(TestDevice { val: 0xBEEF, part: false }));
+    comp.execute_next_from(0x100);
+    comp.execute_next();
+    comp.execute_next();
+
+    assert_eq!(comp.read_reg(Regs::CX).unwrap(), 0);
+    assert_eq!(comp.read_reg(Regs::DI).unwrap(), 4);
+    assert_eq!((comp.probe_mem_word(0), comp.probe_mem_word(2)), (0xBEEF, 0xBEEF));
+}
@@ -1,9 +1,12 @@
 use xtreme86::cpu;
+use xtreme86::asm;
 use std::fs::File;
 use std::fs;
 use std::io::Read;
 use std::path::PathBuf;
 
+mod conformance;
+
 fn load_binary(filename: &str) -> Vec<u8> {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("tests");
@@ -17,7 +20,7 @@ fn load_binary(filename: &str) -> Vec<u8> {
     buffer
 }
 
-fn new_cpu_from_file(filename: &str) -> cpu::CPU {
+fn new_cpu_from_code(code: Vec<u8>) -> cpu::CPU {
     let mut computer = cpu::CPU::new(0x7FFFFF);
     computer.set_reg(cpu::Regs::SS, 0x003F);
     computer.set_reg(cpu::Regs::CS, 0x103F);
@@ -25,12 +28,16 @@ fn new_cpu_from_file(filename: &str) -> cpu::CPU {
     computer.set_reg(cpu::Regs::SP, 0xFFFF);
     computer.set_reg(cpu::Regs::IP, 0x0000);
 
-    let buffer = load_binary(filename);
-    computer.load(buffer, 0x103F0);
+    computer.load(code, 0x103F0);
 
     computer
 }
 
+fn new_cpu_from_source(filename: &str) -> cpu::CPU {
+    let source = String::from_utf8(load_binary(filename)).expect("Source isn't UTF-8");
+    new_cpu_from_code(asm::assemble(&source).unwrap())
+}
+
 fn new_cpu_vec(code: Vec<u8>) -> cpu::CPU {
     let mut computer = cpu::CPU::new(code.len());
     computer.load(code, 0);
@@ -39,7 +46,7 @@ fn new_cpu_vec(code: Vec<u8>) -> cpu::CPU {
 
 mod mov_test {
     use super::cpu;
-    use crate::new_cpu_from_file;
+    use crate::{new_cpu_from_source};
     use crate::cpu::Regs;
     use xtreme86::cpu::CPU;
 
@@ -72,7 +79,7 @@ mod mov_test {
 
     #[test]
     fn test_seg_override() {
-        let mut comp = new_cpu_from_file("obj/seg.out");
+        let mut comp = new_cpu_from_source("src/seg.asm");
 
         comp.run_to_nop(0);
         assert_eq!(comp.probe_mem_es(0), 0x05);
@@ -83,7 +90,7 @@ mod mov_test {
 
     #[test]
     fn test_lea_convert() {
-        let mut comp = new_cpu_from_file("obj/lea.out");
+        let mut comp = new_cpu_from_source("src/lea.asm");
         comp.run_to_nop(0);
         assert_eq!(comp.read_reg(Regs::AX).unwrap(), 9);
         comp.run_to_nop_from_ip();
@@ -94,7 +101,7 @@ mod mov_test {
 
     #[test]
     fn test_load() {
-        let mut comp = new_cpu_from_file("obj/load.out");
+        let mut comp = new_cpu_from_source("src/load.asm");
         comp.write_bytes_ds(0, vec![0x00, 0x00, 0xFF, 0xFF]).unwrap();
         comp.run_to_nop(0);
         assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x0000);
@@ -106,7 +113,7 @@ mod mov_test {
 
     #[test]
     fn test_ex() {
-        let mut comp = new_cpu_from_file("obj/ex.out");
+        let mut comp = new_cpu_from_source("src/ex.asm");
         comp.write_bytes_ds(0x0A, vec![0xFF]).unwrap();
         comp.run_to_nop(0);
         assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x20);
@@ -116,10 +123,63 @@ mod mov_test {
     }
 }
 
+mod addressing_test {
+    use crate::cpu::{Regs, CPU};
+
+    // Code at 0x100, with DS at 0 so data sits below it
+    fn run(code: Vec<u8>, data: &[(usize, u8)], count: usize) -> CPU {
+        let mut computer = CPU::new(0xFFFF);
+        computer.load(code, 0x100);
+        for (address, val) in data {
+            computer.load(vec![*val], *address);
+        }
+        computer.execute_next_from(0x100);
+        for _ in 1..count {
+            computer.execute_next();
+        }
+        computer
+    }
+
+    #[test]
+    fn test_mov_ds() {
+        // mov ax, 0x1234 / mov ds, ax / mov bx, ds
+        let computer = run(vec![0xB8, 0x34, 0x12, 0x8E, 0xD8, 0x8C, 0xDB], &[], 3);
+        assert_eq!(computer.read_reg(Regs::DS).unwrap(), 0x1234);
+        assert_eq!(computer.read_reg(Regs::CS).unwrap(), 0);
+        assert_eq!(computer.read_reg(Regs::BX).unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn test_negative_displacement() {
+        // mov bx, 0x10 / mov al, [bx-2]
+        let computer = run(vec![0xBB, 0x10, 0x00, 0x8A, 0x47, 0xFE], &[(0x0E, 0x5A), (0x10E, 0xA5)], 2);
+        assert_eq!(computer.read_reg(Regs::AX).unwrap() & 0xFF, 0x5A);
+    }
+
+    #[test]
+    fn test_effective_address_wraps() {
+        // mov bx, 0xFFFF / mov al, [bx+2] / mov si, 0x8000 / mov ah, [bx+si+0x8004]
+        let code = vec![0xBB, 0xFF, 0xFF, 0x8A, 0x47, 0x02, 0xBE, 0x00, 0x80, 0x8A, 0xA0, 0x04, 0x80];
+        let computer = run(code, &[(0x01, 0x77), (0x03, 0x66)], 4);
+        assert_eq!(computer.read_reg(Regs::AX).unwrap(), 0x6677);
+    }
+
+    #[test]
+    fn test_trace_group_mnemonic() {
+        // cmp byte [bx], 1
+        let mut computer = CPU::new(0xFFFF);
+        computer.load(vec![0x80, 0x3F, 0x01], 0x100);
+        computer.enable_trace();
+        computer.execute_next_from(0x100);
+        assert!(computer.take_trace()[0].text.starts_with("cmp"));
+        assert_eq!(computer.read_reg(Regs::IP).unwrap(), 0x103);
+    }
+}
+
 mod test_alu {
     use super::cpu;
-    use crate::cpu::Regs;
-    use crate::{new_cpu_vec, new_cpu_from_file};
+    use crate::cpu::{Regs, exceptions};
+    use crate::{asm, new_cpu_vec, new_cpu_from_code, new_cpu_from_source};
 
     #[test]
     fn test_add() {
@@ -197,18 +257,29 @@ mod test_alu {
 
     #[test]
     fn test_div() {
-        let mut computer = new_cpu_vec(vec![0xba, 0xaa, 0x00, 0xb8, 0x55, 0x55, 0xbb, 0xff, 0x00, 0xf7, 0xfb]);
+        let mut computer = new_cpu_vec(vec![0xba, 0xaa, 0x00, 0xb8, 0x55, 0x55, 0xbb, 0xff, 0x00, 0xf7, 0xf3]);
         computer.execute_next();
         computer.execute_next();
         computer.execute_next();
         computer.execute_next();
         assert_eq!(computer.read_reg(Regs::AX).unwrap(), 0xab00);
         assert_eq!(computer.read_reg(Regs::DX).unwrap(), 0x0055);
+
+        // idiv bx, 0x00AA5555 / 0x00FF is 43780, too big for a signed word
+        let mut computer = new_cpu_vec(vec![0xba, 0xaa, 0x00, 0xb8, 0x55, 0x55, 0xbb, 0xff, 0x00, 0xf7, 0xfb]);
+        computer.execute_next();
+        computer.execute_next();
+        computer.execute_next();
+        computer.execute_next();
+        assert_eq!(computer.raised_interrupt(), Some(exceptions::DIVIDE_BY_ZERO));
+        assert_eq!(computer.read_reg(Regs::AX).unwrap(), 0x5555);
+        assert_eq!(computer.read_reg(Regs::DX).unwrap(), 0x00aa);
+        assert_eq!(computer.read_reg(Regs::IP).unwrap(), 9);
     }
 
     #[test]
     fn test_misc() {
-        let mut computer = new_cpu_from_file("obj/alu.out");
+        let mut computer = new_cpu_from_source("src/alu.asm");
         computer.run_to_nop(0);
         assert_eq!(computer.read_reg(Regs::AX).unwrap(), 0x0000);
         computer.run_to_nop_from_ip();
@@ -251,7 +322,7 @@ mod test_alu {
 
     #[test]
     fn test_shift() {
-        let mut comp = new_cpu_from_file("obj/shift.out");
+        let mut comp = new_cpu_from_source("src/shift.asm");
 
         comp.run_to_nop(0);
         assert_eq!(comp.read_reg(Regs::CX).unwrap(), 0x7FFF);
@@ -272,27 +343,38 @@ mod test_alu {
         assert_eq!(comp.read_reg(Regs::BX).unwrap(), 0x000F);
 
         comp.run_to_nop_from_ip();
-        assert_eq!(comp.read_reg(Regs::SI).unwrap(), 0x8003);
+        assert_eq!(comp.read_reg(Regs::SI).unwrap(), 0xE003);
+    }
+
+    #[test]
+    fn test_shift_by_cl() {
+        let code = asm::assemble("mov cl, 3\nmov dl, 0xFF\nmov al, 1\nshl al, cl\nmov bx, 0x8000\nshr bx, cl\nnop").unwrap();
+        let mut comp = new_cpu_from_code(code);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x0008);
+        assert_eq!(comp.read_reg(Regs::BX).unwrap(), 0x1000);
     }
 }
 
 mod stack_test {
-    use crate::cpu::Regs;
-    use crate::{new_cpu_from_file};
+    use crate::cpu::{Regs, CPU};
+    use crate::{new_cpu_from_code, new_cpu_from_source};
+    use xtreme86::asm;
+    use xtreme86::cpu::Model;
 
     #[test]
     fn test_push() {
-        let mut computer = new_cpu_from_file("obj/push.out");
+        let mut computer = new_cpu_from_code(asm::assemble("mov ax, 5\npush ax").unwrap());
         computer.execute_next();
         assert_eq!(computer.read_reg(Regs::AX).unwrap(), 0x05);
         computer.execute_next();
         assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0xFFFD);
-        assert_eq!(computer.get_mem_seg(Regs::SS, computer.read_reg(Regs::SP).unwrap() + 1), 0x05);
+        assert_eq!(computer.get_mem_seg(Regs::SS, computer.read_reg(Regs::SP).unwrap()), 0x05);
     }
 
     #[test]
     fn test_pop() {
-        let mut computer = new_cpu_from_file("obj/pop.out");
+        let mut computer = new_cpu_from_code(asm::assemble("mov ax, 5\npush ax\npop bx").unwrap());
         computer.execute_next();
         computer.execute_next();
         computer.execute_next();
@@ -301,7 +383,7 @@ mod stack_test {
 
     #[test]
     fn test_proc() {
-        let mut computer = new_cpu_from_file("obj/proc.out");
+        let mut computer = new_cpu_from_source("src/proc.asm");
         computer.run_to_nop(0);
         assert_eq!(computer.read_reg(Regs::AX).unwrap(), 0x16);
         assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0xFFFF);
@@ -309,16 +391,427 @@ mod stack_test {
         computer.run_to_nop_from_ip();
         assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0xFFFF);
     }
+
+    fn new_cpu_from_asm(model: Model, source: &str) -> CPU {
+        let mut computer = CPU::with_model(0x7FFFF, model);
+        computer.load(asm::assemble(&format!("{}\nnop", source)).unwrap(), 0x10000);
+        computer.set_reg(Regs::CS, 0x1000);
+        computer.set_reg(Regs::SS, 0x2000);
+        computer
+    }
+
+    #[test]
+    fn test_push_wraps() {
+        // .COM programs start with SP at 0, so the first push lands at the top of SS
+        let mut computer = new_cpu_from_asm(Model::I8086, "mov ax, 0x1234\npush ax\npop bx\npush ax");
+        computer.set_reg(Regs::SP, 0);
+        computer.run_to_nop(0);
+        assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0xFFFE);
+        assert_eq!(computer.read_reg(Regs::BX).unwrap(), 0x1234);
+        assert_eq!(computer.probe_mem_word(0x2FFFE), 0x1234);
+
+        // A word at SS:FFFF has its high byte at SS:0000
+        let mut computer = new_cpu_from_asm(Model::I8086, "mov ax, 0x1234\npush ax\npop bx");
+        computer.set_reg(Regs::SP, 1);
+        computer.run_to_nop(0);
+        assert_eq!((computer.probe_mem(0x2FFFF), computer.probe_mem(0x20000)), (0x34, 0x12));
+        assert_eq!(computer.read_reg(Regs::BX).unwrap(), 0x1234);
+        assert_eq!(computer.read_reg(Regs::SP).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_call_ret_wrap() {
+        let mut computer = new_cpu_from_asm(Model::I8086, "mov bx, 0x5555\npush bx\ncall f\nmov ax, 1\nnop\nf: ret 2");
+        computer.set_reg(Regs::SP, 0);
+        computer.run_to_nop(0);
+        assert_eq!(computer.read_reg(Regs::AX).unwrap(), 1);
+        // ret 2 released the pushed word
+        assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0);
+        assert_eq!(computer.probe_mem_word(0x2FFFE), 0x5555);
+    }
+
+    #[test]
+    fn test_push_sp() {
+        for (model, pushed) in [(Model::I8086, 0x0FE), (Model::I80186, 0x0FE), (Model::I80286, 0x100)] {
+            let mut computer = new_cpu_from_asm(model, "push sp\npop ax");
+            computer.set_reg(Regs::SP, 0x100);
+            computer.run_to_nop(0);
+            assert_eq!(computer.read_reg(Regs::AX).unwrap(), pushed, "{}", model);
+        }
+
+        // POP SP keeps the popped value instead of adding 2 to it
+        let mut computer = new_cpu_from_asm(Model::I80286, "mov ax, 0x4000\npush ax\npop sp");
+        computer.set_reg(Regs::SP, 0x100);
+        computer.run_to_nop(0);
+        assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0x4000);
+    }
+}
+
+mod i186_test {
+    use crate::cpu::{Regs, CPU, CPUFlags};
+    use crate::new_cpu_from_code;
+    use xtreme86::asm;
+    use xtreme86::cpu::Model;
+    use xtreme86::cpu::instruction::{InstructionDecoder, DecodeError};
+    use xtreme86::cpu::instruction::opcode::Opcode;
+    use xtreme86::disasm::Disassembler;
+    use xtreme86::disasm::format::FormatOptions;
+    use xtreme86::peripheral::Peripheral;
+    use std::sync::{Arc, Mutex};
+
+    fn new_cpu_from_asm(source: &str) -> CPU {
+        new_cpu_from_code(asm::assemble(&format!("CPU 286\n{}", source)).unwrap())
+    }
+
+    fn carry(comp: &CPU) -> bool {
+        comp.read_reg(Regs::FLAGS).unwrap() & CPUFlags::CARRY != 0
+    }
+
+    #[test]
+    fn test_imul_imm() {
+        let mut comp = new_cpu_from_asm("mov bx, -300\nmov dx, 0x55\nimul ax, bx, 100\nnop\n\
+            imul cx, bx, 200\nnop\nmov word [0x10], 7\nimul dx, [0x10], -3\nnop");
+
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), (-30000i16) as u16);
+        assert_eq!(comp.read_reg(Regs::DX).unwrap(), 0x55);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0xFFFF);
+        assert!(!carry(&comp));
+
+        // -60000 doesn't fit in a word
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::CX).unwrap(), 0x15A0);
+        assert!(carry(&comp));
+        assert!(comp.read_reg(Regs::FLAGS).unwrap() & CPUFlags::OVERFLOW != 0);
+
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::DX).unwrap(), (-21i16) as u16);
+        assert!(!carry(&comp));
+
+        let text = |code: &[u8]| Disassembler::with_options(code, 0, 0x100, FormatOptions::nasm()).decode_at(0).unwrap().text;
+        assert_eq!(text(&[0x6B, 0xC3, 0x64]), "imul ax, bx, 0x64");
+        assert_eq!(text(&[0x69, 0x4F, 0x02, 0x34, 0x12]), "imul cx, word [bx+0x2], 0x1234");
+        assert_eq!(text(&[0x6B, 0x16, 0x10, 0x00, 0xFD]), "imul dx, word [0x10], 0xFFFD");
+    }
+
+    #[test]
+    fn test_push_imm() {
+        let mut comp = new_cpu_from_asm("push 0x1234\npush -2\npop bx\npop ax\nnop");
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x1234);
+        assert_eq!(comp.read_reg(Regs::BX).unwrap(), 0xFFFE);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0xFFFF);
+    }
+
+    #[test]
+    fn test_pusha_popa() {
+        let mut comp = new_cpu_from_asm("mov sp, 0x100\nmov ax, 1\nmov cx, 2\nmov dx, 3\nmov bx, 4\nmov bp, 6\nmov si, 7\nmov di, 8\n\
+            pusha\nnop\npop ax\npop bx\npop cx\npop dx\nnop\n\
+            sub sp, 8\nxor ax, ax\nxor bx, bx\nxor cx, cx\nxor dx, dx\nxor bp, bp\nxor si, si\nxor di, di\npopa\nnop");
+
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0xF0);
+
+        // DI is pushed last and SP holds its value from before the first push
+        comp.run_to_nop_from_ip();
+        let popped: Vec<u16> = [Regs::AX, Regs::BX, Regs::CX, Regs::DX].iter().map(|reg| comp.read_reg(*reg).unwrap()).collect();
+        assert_eq!(popped, vec![8, 7, 6, 0x100]);
+
+        comp.run_to_nop_from_ip();
+        let restored: Vec<u16> = [Regs::AX, Regs::CX, Regs::DX, Regs::BX, Regs::SP, Regs::BP, Regs::SI, Regs::DI].iter()
+            .map(|reg| comp.read_reg(*reg).unwrap()).collect();
+        assert_eq!(restored, vec![1, 2, 3, 4, 0x100, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_shift_imm() {
+        let mut comp = new_cpu_from_asm("mov ax, 0x1234\nrol ax, 4\nnop\nmov bl, 0x81\nsar bl, 9\nnop\n\
+            mov cx, 0x8001\nshr cx, 33\nnop\nmov dl, 0x80\nstc\nrcl dl, 2\nnop");
+
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x2341);
+        assert!(carry(&comp));
+
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::BX).unwrap() & 0xFF, 0xFF);
+        assert!(carry(&comp));
+
+        // The 186 only uses the low five bits of the count, so this shifts once
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::CX).unwrap(), 0x4000);
+        assert!(carry(&comp));
+
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::DX).unwrap() & 0xFF, 0x03);
+        assert!(!carry(&comp));
+    }
+
+    #[test]
+    fn test_rotate_overflow() {
+        let mut comp = new_cpu_from_asm("mov al, 0x40\nrol al, 1\nnop\nmov al, 0xC0\nrol al, 1\nnop\n\
+            mov ax, 1\nror ax, 1\nnop\nmov bl, 2\nror bl, 1\nnop");
+        let overflow = |comp: &CPU| comp.read_reg(Regs::FLAGS).unwrap() & CPUFlags::OVERFLOW != 0;
+
+        // OF is the top bit of the result xor CF after a rotate left
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap() & 0xFF, 0x80);
+        assert!(overflow(&comp) && !carry(&comp));
+
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::AX).unwrap() & 0xFF, 0x81);
+        assert!(!overflow(&comp) && carry(&comp));
+
+        // and the two top bits of the result xored after a rotate right
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x8000);
+        assert!(overflow(&comp) && carry(&comp));
+
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::BX).unwrap() & 0xFF, 0x01);
+        assert!(!overflow(&comp) && !carry(&comp));
+    }
+
+    #[test]
+    fn test_shift_count_per_model() {
+        let code = asm::assemble("mov ax, 0xFFFF\nmov cl, 33\nshr ax, cl\nnop").unwrap();
+        for (model, result) in [(Model::I8086, 0x0000), (Model::I80186, 0x7FFF)] {
+            let mut comp = CPU::with_model(0xFFFF, model);
+            comp.load(code.clone(), 0);
+            comp.run_to_nop(0);
+            assert_eq!(comp.read_reg(Regs::AX).unwrap(), result, "{}", model);
+        }
+    }
+
+    #[derive(Clone)]
+    struct Port {
+        next: u16,
+        written: Arc<Mutex<Vec<u16>>>
+    }
+
+    impl Peripheral for Port {
+        fn init(&self, comp: &mut CPU, index: usize) {
+            comp.hook_io_memory(index, 0x60);
+        }
+
+        fn handle_interrupt(&mut self, _: &mut CPU, _: u8) -> usize { 0 }
+
+        fn handle_mem_read_byte(&mut self, _: u16) -> u8 { 0 }
+
+        fn handle_mem_read_word(&mut self, _: u16) -> u16 {
+            self.next = self.next.wrapping_add(0x1111);
+            self.next
+        }
+
+        fn handle_mem_write_byte(&mut self, _: u16, _: u8) {}
+
+        fn handle_mem_write_word(&mut self, _: u16, val: u16) {
+            self.written.lock().unwrap().push(val);
+        }
+    }
+
+    #[test]
+    fn test_ins_outs() {
+        let mut comp = new_cpu_from_asm("mov dx, 0x60\nmov di, 0x10\ncld\ninsw\ninsw\nnop\nmov si, 0x12\nstd\noutsw\noutsw\nnop");
+        let written = Arc::new(Mutex::new(Vec::new()));
+        comp.hook_peripheral(Box::new(Port { next: 0, written: written.clone() }));
+        comp.set_reg(Regs::ES, comp.read_reg(Regs::DS).unwrap());
+
+        comp.run_to_nop(0);
+        assert_eq!(comp.probe_mem_es_word(0x10), 0x1111);
+        assert_eq!(comp.probe_mem_es_word(0x12), 0x2222);
+        assert_eq!(comp.read_reg(Regs::DI).unwrap(), 0x14);
+
+        comp.run_to_nop_from_ip();
+        assert_eq!(*written.lock().unwrap(), vec![0x2222, 0x1111]);
+        assert_eq!(comp.read_reg(Regs::SI).unwrap(), 0x0E);
+    }
+
+    #[test]
+    fn test_enter() {
+        let mut comp = new_cpu_from_asm("mov sp, 0x100\nmov bp, 0xAAAA\nenter 2, 1\nenter 0, 2\nenter 0, 3\nnop\n\
+            pop ax\npop bx\npop cx\npop dx\nnop");
+
+        // Each level copies one more frame pointer from the enclosing frame, then pushes its own
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0xEC);
+        assert_eq!(comp.read_reg(Regs::BP).unwrap(), 0xF2);
+
+        comp.run_to_nop_from_ip();
+        let popped: Vec<u16> = [Regs::AX, Regs::BX, Regs::CX, Regs::DX].iter().map(|reg| comp.read_reg(*reg).unwrap()).collect();
+        assert_eq!(popped, vec![0xF2, 0xF8, 0xFE, 0xF8]);
+    }
+
+    #[test]
+    fn test_enter_level_mask() {
+        // Only the low five bits of the level count, so 0x21 is level 1
+        let mut comp = new_cpu_from_asm("mov sp, 0x100\nmov bp, 0xAAAA\nenter 4, 0x21\nnop\nleave\nnop");
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::BP).unwrap(), 0xFE);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0xF8);
+
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::BP).unwrap(), 0xAAAA);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0x100);
+    }
+
+    #[test]
+    fn test_bound() {
+        // The handler for int 5 at 0x40 pops the return address into DX
+        let mut code = asm::assemble("CPU 286\nmov ax, -5\nbound ax, [bx+0x200]\nmov ax, 11\nbound ax, [bx+0x200]").unwrap();
+        code.resize(0x40, 0x90);
+        code.extend([0x5A, 0x90]);
+        let mut comp = new_cpu_from_code(code);
+        comp.load(vec![0x40, 0x00, 0x3F, 0x10], 0x14);
+        // The bounds are signed, -10 to 10
+        comp.write_bytes_ds(0x200, vec![0xF6, 0xFF, 0x0A, 0x00]).unwrap();
+
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 0x42);
+        assert_eq!(comp.read_reg(Regs::DX).unwrap(), 0x0A);
+
+        let error = InstructionDecoder::new(Opcode::table(), &[0x62, 0xC0]).get().unwrap_err();
+        assert_eq!(error, DecodeError::InvalidModRM { opcode: 0x62, mod_reg_rm: 0xC0, consumed: 2 });
+    }
+}
+
+mod i8086_test {
+    use crate::cpu::{Regs, CPU, CPUFlags};
+    use xtreme86::cpu::Model;
+    use xtreme86::cpu::instruction::{InstructionDecoder, DecodeError};
+    use xtreme86::cpu::instruction::opcode::Opcode;
+    use xtreme86::disasm::format::FormatOptions;
+
+    fn new_8086(code: Vec<u8>) -> CPU {
+        let mut comp = CPU::with_model(0xFFFF, Model::I8086);
+        comp.set_reg(Regs::SP, 0x8000);
+        comp.load(code, 0);
+        comp
+    }
+
+    fn text(code: &[u8], model: Model) -> String {
+        let instruction = InstructionDecoder::with_model(Opcode::table(), code, model).get().unwrap();
+        FormatOptions::nasm().format(&instruction, (0, 0x100))
+    }
+
+    fn flag(comp: &CPU, flag: u16) -> bool {
+        comp.read_reg(Regs::FLAGS).unwrap() & flag != 0
+    }
+
+    #[test]
+    fn test_jcc_aliases() {
+        // jno over mov bx, 1
+        let mut comp = new_8086(vec![0x61, 0x03, 0xBB, 0x01, 0x00, 0x90]);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::BX).unwrap(), 0);
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 6);
+
+        assert_eq!(text(&[0x64, 0x02], Model::I8088), "je short 0x104");
+        assert_eq!(text(&[0x6F, 0xFE], Model::I8086), "jg short 0x100");
+        assert_eq!(text(&[0x61], Model::I80186), "popa");
+    }
+
+    #[test]
+    fn test_pop_cs() {
+        // mov ax, 0x2000; push ax; pop cs
+        let mut comp = new_8086(vec![0xB8, 0x00, 0x20, 0x50, 0x0F]);
+        for _ in 0..3 {
+            comp.execute_next();
+        }
+        assert_eq!(comp.read_reg(Regs::CS).unwrap(), 0x2000);
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 5);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0x8000);
+    }
+
+    #[test]
+    fn test_salc() {
+        // mov ax, 0x1234; stc; salc; nop; clc; salc; nop
+        let mut comp = new_8086(vec![0xB8, 0x34, 0x12, 0xF9, 0xD6, 0x90, 0xF8, 0xD6, 0x90]);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x12FF);
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x1200);
+    }
+
+    #[test]
+    fn test_ret_aliases() {
+        // call 5; nop; at 5: mov ax, 5; ret as 0xC1
+        let mut comp = new_8086(vec![0xE8, 0x02, 0x00, 0x90, 0x90, 0xB8, 0x05, 0x00, 0xC1]);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 5);
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 4);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0x8000);
+
+        // call 0:0x10; nop; at 0x10: mov ax, 7; retf as 0xC9
+        let mut code = vec![0x9A, 0x10, 0x00, 0x00, 0x00, 0x90];
+        code.resize(0x10, 0x00);
+        code.extend([0xB8, 0x07, 0x00, 0xC9]);
+        let mut comp = new_8086(code);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 7);
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 6);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0x8000);
+
+        assert_eq!(text(&[0xC0, 0x02, 0x00], Model::I8086), "ret 0x2");
+        assert_eq!(text(&[0xC8, 0x04, 0x00], Model::I8086), text(&[0xCA, 0x04, 0x00], Model::I8086));
+    }
+
+    #[test]
+    fn test_setmo() {
+        // mov ax, 0x1200; setmo al; nop; mov bx, 0x1234; mov cl, 0; setmoc bx, cl; nop; mov cl, 1; setmoc bx, cl; nop
+        let mut comp = new_8086(vec![0xB8, 0x00, 0x12, 0xD0, 0xF0, 0x90, 0xBB, 0x34, 0x12, 0xB1, 0x00, 0xD3, 0xF3, 0x90,
+                                     0xB1, 0x01, 0xD3, 0xF3, 0x90]);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x12FF);
+        assert!(flag(&comp, CPUFlags::SIGN) && flag(&comp, CPUFlags::PARITY));
+        assert!(!flag(&comp, CPUFlags::CARRY) && !flag(&comp, CPUFlags::ZERO));
+
+        // Nothing happens with a count of zero
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::BX).unwrap(), 0x1234);
+
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::BX).unwrap(), 0xFFFF);
+
+        assert_eq!(text(&[0xD3, 0xF3], Model::I8086), "setmoc bx, cl");
+        let error = InstructionDecoder::new(Opcode::table(), &[0xD0, 0xF0]).get().unwrap_err();
+        assert_eq!(error, DecodeError::InvalidModRM { opcode: 0xD0, mod_reg_rm: 0xF0, consumed: 2 });
+    }
+
+    #[test]
+    fn test_test_alias() {
+        // mov al, 2; test al, 1 as 0xF6 /1; nop
+        let mut comp = new_8086(vec![0xB0, 0x02, 0xF6, 0xC8, 0x01, 0x90]);
+        comp.run_to_nop(0);
+        assert!(flag(&comp, CPUFlags::ZERO));
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_lock() {
+        // lock inc ax, with 0xF1 as LOCK; nop
+        let mut comp = new_8086(vec![0xF1, 0x40, 0x90]);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 1);
+
+        assert_eq!(text(&[0xF1, 0x40], Model::I8088), "lock inc ax");
+        assert_eq!(text(&[0xF0, 0x26, 0x01, 0x07], Model::I80286), "lock add word [es:bx], ax");
+        // Prefixes come in any order
+        assert_eq!(text(&[0x26, 0xF0, 0x01, 0x07], Model::I80286), "lock add word [es:bx], ax");
+        assert_eq!(text(&[0x2E, 0xF1, 0x36, 0xFF, 0x07], Model::I8086), "lock inc word [ss:bx]");
+        let error = InstructionDecoder::new(Opcode::table(), &[0xF1, 0x40]).get().unwrap_err();
+        assert_eq!(error, DecodeError::UnknownOpcode { opcode: 0xF1, consumed: 1 });
+    }
 }
 
 mod jmp_test {
     use crate::cpu::Regs;
-    use crate::{new_cpu_from_file, load_binary};
+    use crate::{new_cpu_from_source, new_cpu_from_code, load_binary};
     use xtreme86::cpu::CPU;
+    use xtreme86::asm;
 
     #[test]
     fn test_jmp() {
-        let mut computer = new_cpu_from_file("obj/jmp.out");
+        let mut computer = new_cpu_from_source("src/jmp.asm");
         computer.execute_next();
         computer.execute_next();
         assert_eq!(computer.read_reg(Regs::AX).unwrap(), 0x06);
@@ -326,7 +819,7 @@ mod jmp_test {
 
     #[test]
     fn test_cond_jmp() {
-        let mut computer = new_cpu_from_file("obj/jmp_cond.out");
+        let mut computer = new_cpu_from_source("src/jmp_cond.asm");
         computer.run_to_nop(0);
         assert_eq!(computer.read_reg(Regs::AX).unwrap(), 0x16);
     }
@@ -344,7 +837,7 @@ mod jmp_test {
 
     #[test]
     fn test_loop() {
-        let mut comp = new_cpu_from_file("obj/fib.out");
+        let mut comp = new_cpu_from_source("src/fib.asm");
         comp.run_to_nop(0);
         for i in 0..10 as usize {
             if i > 1 {
@@ -360,8 +853,13 @@ mod jmp_test {
 
     #[test]
     fn test_far() {
-        let mut comp = new_cpu_from_file("obj/far_code.out");
-        let code = load_binary("obj/far.out");
+        let mut comp = new_cpu_from_code(asm::assemble("
+            call 0x8000:0x0000
+            nop
+            jmp 0x8000:0x0004
+        ").unwrap());
+        let source = String::from_utf8(load_binary("src/far.asm")).unwrap();
+        let code = asm::assemble(&source).unwrap();
 
         comp.load(code, CPU::physical_address(0x8000, 0) as usize);
 
@@ -375,12 +873,13 @@ mod jmp_test {
 }
 
 mod int_test {
-    use crate::new_cpu_from_file;
-    use crate::cpu::Regs;
+    use crate::{new_cpu_from_source, new_cpu_from_code};
+    use crate::cpu::{Regs, CPU};
+    use xtreme86::cpu::instruction::DecodeError;
 
     #[test]
     fn test_soft_int() {
-        let mut computer = new_cpu_from_file("obj/int.out");
+        let mut computer = new_cpu_from_source("src/int.asm");
         computer.load(vec![0x14, 0x00, 0x3F, 0x10], 0);
         computer.load(vec![0x14, 0x00, 0x3F, 0x10], 20);
         computer.load(vec![0x14, 0x00, 0x3F, 0x10], 16);
@@ -395,15 +894,31 @@ mod int_test {
         assert_eq!(computer.read_reg(Regs::AX).unwrap(), 5);
         assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0xFFFF);
     }
+
+    #[test]
+    fn test_invalid_opcode() {
+        // The handler at 0x10 is a nop
+        let mut code = vec![0xF1, 0x90];
+        code.resize(0x10, 0x00);
+        code.push(0x90);
+        let mut computer = new_cpu_from_code(code);
+        computer.load(vec![0x10, 0x00, 0x3F, 0x10], 0x18);
+        computer.run_to_nop(0);
+
+        assert_eq!(computer.read_reg(Regs::IP).unwrap(), 0x11);
+        assert_eq!(computer.decode_error(), Some(DecodeError::UnknownOpcode { opcode: 0xF1, consumed: 1 }));
+        let stack = CPU::physical_address(computer.read_reg(Regs::SS).unwrap(), computer.read_reg(Regs::SP).unwrap()) as usize;
+        assert_eq!(computer.probe_mem_word(stack), 0x0000);
+    }
 }
 
 mod test_flags {
-    use crate::new_cpu_from_file;
+    use crate::{new_cpu_from_source, new_cpu_from_code};
     use crate::cpu::Regs;
 
     #[test]
     fn test_cmp() {
-        let mut comp = new_cpu_from_file("obj/cmp.out");
+        let mut comp = new_cpu_from_source("src/cmp.asm");
         comp.run_to_nop(0);
         assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x30);
         comp.run_to_nop_from_ip();
@@ -412,19 +927,50 @@ mod test_flags {
 
     #[test]
     fn test_flag() {
-        let mut comp = new_cpu_from_file("obj/flag.out");
+        let mut comp = new_cpu_from_source("src/flag.asm");
         comp.run_to_nop(0);
         assert_ne!(comp.read_reg(Regs::FLAGS).unwrap() & 0x80, 0);
     }
+
+    #[test]
+    fn test_parity() {
+        // Each instruction with the PF it leaves, only the low byte of a word result counts
+        let steps: Vec<(&[u8], bool)> = vec![
+            (&[0xB0, 0x00], false),         // mov al, 0 leaves PF alone
+            (&[0x04, 0x03], true),          // add al, 3 -> 0x03
+            (&[0x04, 0x01], false),         // add al, 1 -> 0x04
+            (&[0x3C, 0x05], true),          // cmp al, 5 -> 0xFF
+            (&[0x2C, 0x02], false),         // sub al, 2 -> 0x02
+            (&[0xBB, 0x00, 0x01], false),   // mov bx, 0x100
+            (&[0x09, 0xDB], true),          // or bx, bx -> 0x0100
+            (&[0x43], false),               // inc bx -> 0x0101
+            (&[0x80, 0xE3, 0x02], true),    // and bl, 2 -> 0x00
+            (&[0x31, 0xC0], true),          // xor ax, ax -> 0
+        ];
+        let code: Vec<u8> = steps.iter().flat_map(|(bytes, _)| bytes.iter().copied()).collect();
+        let mut comp = new_cpu_from_code(code);
+        comp.execute_next_from(0);
+        for (i, (_, parity)) in steps.iter().enumerate() {
+            if i > 0 {
+                comp.execute_next();
+            }
+            assert_eq!(comp.read_reg(Regs::FLAGS).unwrap() & 0x0004 != 0, *parity, "step {}", i);
+        }
+
+        // jp is taken after test al, al with al = 3, so mov dl, 1 is skipped
+        let mut comp = new_cpu_from_code(vec![0xB0, 0x03, 0x84, 0xC0, 0x7A, 0x02, 0xB2, 0x01, 0x90]);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::DX).unwrap(), 0);
+    }
 }
 
 mod test_string {
-    use crate::new_cpu_from_file;
+    use crate::{new_cpu_from_code, new_cpu_from_source};
     use xtreme86::cpu::{Regs, CPU};
 
     #[test]
     fn test_string() {
-        let mut comp = new_cpu_from_file("obj/str.out");
+        let mut comp = new_cpu_from_source("src/str.asm");
         let str1 = "Hello, world!".to_string().into_bytes();
         let str2 = "Hello, aayal!".to_string().into_bytes();
 
@@ -458,4 +1004,1819 @@ mod test_string {
         assert_eq!(comp.read_reg(Regs::SI).unwrap(), 10);
         assert_eq!(comp.read_reg(Regs::DI).unwrap(), 10);
     }
+
+    #[test]
+    fn test_rep_segment_override() {
+        // mov cx, 3; xor si, si; mov di, 0x10; rep es movsb in both prefix orders; nop
+        for prefixes in [[0xF3, 0x26], [0x26, 0xF3]] {
+            let mut code = vec![0xB9, 0x03, 0x00, 0x31, 0xF6, 0xBF, 0x10, 0x00];
+            code.extend(prefixes);
+            code.extend([0xA4, 0x90]);
+            let mut comp = new_cpu_from_code(code);
+            comp.write_bytes_ds(0, b"xyz".to_vec()).unwrap();
+            comp.write_bytes_es(0, b"abc".to_vec()).unwrap();
+            comp.run_to_nop(0);
+
+            let address = CPU::physical_address(comp.read_reg(Regs::ES).unwrap(), 0x10) as usize;
+            assert_eq!([comp.probe_mem(address), comp.probe_mem(address + 1), comp.probe_mem(address + 2)], *b"abc", "{:02X?}", prefixes);
+            assert_eq!(comp.read_reg(Regs::CX).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn test_rep_lods() {
+        // mov cx, 2; xor si, si; rep lodsw; nop
+        let mut comp = new_cpu_from_code(vec![0xB9, 0x02, 0x00, 0x31, 0xF6, 0xF3, 0xAD, 0x90]);
+        comp.write_bytes_ds(0, vec![0x34, 0x12, 0x78, 0x56]).unwrap();
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x5678);
+        assert_eq!(comp.read_reg(Regs::SI).unwrap(), 4);
+        assert_eq!(comp.read_reg(Regs::CX).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_rep_other_instructions() {
+        // mov cx, 5; xor ax, ax; rep inc ax; rep nop, which is still a nop
+        let mut comp = new_cpu_from_code(vec![0xB9, 0x05, 0x00, 0x31, 0xC0, 0xF3, 0x40, 0xF3, 0x90]);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 1);
+        assert_eq!(comp.read_reg(Regs::CX).unwrap(), 5);
+    }
+}
+
+mod profiler_test {
+    use xtreme86::cpu::{Regs, CPU};
+    use xtreme86::cpu::profiler::CallKind;
+
+    fn new_profiled_cpu() -> CPU {
+        let code = vec![
+            0xB9, 0x03, 0x00,   // mov cx, 3
+            0xE8, 0x04, 0x00,   // call routine
+            0xE2, 0xFB,         // loop 3
+            0x90,               // nop
+            0x90,               // nop
+            0x40,               // routine: inc ax
+            0xC3,               // ret
+        ];
+        let mut comp = CPU::new(0x1000);
+        comp.load(code, 0);
+        comp.set_reg(Regs::SP, 0x0FFF);
+        comp.enable_profiler();
+        comp
+    }
+
+    #[test]
+    fn test_routine_profile() {
+        let mut comp = new_profiled_cpu();
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 3);
+
+        let profiler = comp.profiler().unwrap();
+        let routines = profiler.routines();
+        assert_eq!(routines.len(), 1);
+        assert_eq!(routines[0].kind, CallKind::Near);
+        assert_eq!((routines[0].cs, routines[0].ip), (0x0000, 0x000A));
+        assert_eq!(routines[0].calls, 3);
+        assert_eq!(routines[0].instructions, 6);
+        assert!(routines[0].self_cycles > 0);
+        assert_eq!(routines[0].self_cycles, routines[0].total_cycles);
+
+        let flat = profiler.flat_profile();
+        let inc = flat.iter().find(|entry| entry.ip == 0x000A).unwrap();
+        assert_eq!(inc.instructions, 3);
+        assert_eq!(flat.iter().map(|entry| entry.cycles).sum::<usize>(), profiler.total_cycles());
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        let mut comp = new_profiled_cpu();
+        comp.run_to_nop(0);
+
+        let profiler = comp.take_profiler().unwrap();
+        let folded = profiler.folded_stacks();
+        let lines: Vec<&str> = folded.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("root "));
+        assert!(lines[1].starts_with("root;0000:000A "));
+
+        let total: usize = lines.iter().map(|line| line.rsplit(' ').next().unwrap().parse::<usize>().unwrap()).sum();
+        assert_eq!(total, profiler.total_cycles());
+        assert!(comp.profiler().is_none());
+    }
+}
+
+mod backtrace_test {
+    use xtreme86::cpu::{Regs, CPU};
+    use xtreme86::cpu::profiler::CallKind;
+
+    fn new_cpu_at(code: Vec<u8>, cs: u16) -> CPU {
+        let mut comp = CPU::new(0x1FFFF);
+        comp.load(code, CPU::physical_address(cs, 0) as usize);
+        comp.set_reg(Regs::CS, cs);
+        comp.set_reg(Regs::SP, 0xFFFF);
+        comp
+    }
+
+    #[test]
+    fn test_backtrace_on_divide_error() {
+        let mut code = vec![
+            0xE8, 0x03, 0x00,   // call outer
+            0x90, 0x90, 0x90,
+            0xE8, 0x03, 0x00,   // outer: call inner
+            0xC3,               // ret
+            0x90, 0x90,
+            0xB3, 0x00,         // inner: mov bl, 0
+            0xF6, 0xF3,         // div bl
+            0xC3,               // ret
+        ];
+        code.resize(0x40, 0x00);
+        code.extend(vec![0x90, 0xCF]); // handler: nop, iret
+
+        let mut comp = new_cpu_at(code, 0x0050);
+        comp.load(vec![0x40, 0x00, 0x50, 0x00], 0);
+        comp.run_to_nop(0);
+
+        let frames = comp.backtrace();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].kind, CallKind::Interrupt(0));
+        assert_eq!(frames[0].target, (0x0050, 0x0040));
+        assert_eq!(frames[0].return_address, (0x0050, 0x000E));
+        assert_eq!(frames[1].kind, CallKind::Near);
+        assert_eq!(frames[1].call_site, (0x0050, 0x0006));
+        assert_eq!(frames[1].target, (0x0050, 0x000C));
+        assert_eq!(frames[1].return_address, (0x0050, 0x0009));
+        assert_eq!(frames[2].call_site, (0x0050, 0x0000));
+        assert_eq!(frames[2].target, (0x0050, 0x0006));
+        assert_eq!(frames[2].return_address, (0x0050, 0x0003));
+        assert_eq!(frames[1].to_string(), "0050:000C (call from 0050:0006, returns to 0050:0009)");
+    }
+
+    #[test]
+    fn test_backtrace_after_stack_manipulation() {
+        let code = vec![
+            0xE8, 0x07, 0x00,   // call discard
+            0xE8, 0x08, 0x00,   // call routine
+            0x90,               // nop
+            0x90, 0x90, 0x90,
+            0x5B,               // discard: pop bx
+            0xE9, 0xF5, 0xFF,   // jmp 3
+            0x90,               // routine: nop
+            0xC3,               // ret
+        ];
+        let mut comp = new_cpu_at(code, 0x0100);
+        comp.run_to_nop(0);
+
+        let frames = comp.backtrace();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].target, (0x0100, 0x000E));
+        assert_eq!(frames[0].return_address, (0x0100, 0x0006));
+
+        comp.run_to_nop_from_ip();
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 0x0007);
+        assert!(comp.backtrace().is_empty());
+    }
+}
+
+mod symbols_test {
+    use xtreme86::cpu::{Regs, CPU};
+    use xtreme86::symbols::SymbolTable;
+
+    const LISTING: &str = "     1                                  org 0x100
+     2                                  
+     3                                  start:
+     4 00000000 B90A00                      mov cx, 10
+     5                                  load_loop:
+     6 00000003 E80300                      call fib
+     7 00000006 E2FB                        loop load_loop
+     8 00000008 90                          nop
+     9                                  fib:
+    10 00000009 31C0                        xor ax, ax
+    11                                  .done:
+    12 0000000B C3                          ret
+    13 0000000C 0000                    result dw 0
+    14 0000000E <res 10>                buffer resb 16
+    15                                  end:
+";
+
+    const MAP: &str = "
+- NASM Map file ---------------------------------------------------------------
+
+Source file:  fib.asm
+Output file:  fib.com
+
+-- Symbols --------------------------------------------------------------------
+
+---- Section .text ------------------------------------------------------------
+
+Real              Virtual           Name
+               0               100  start
+               3               103  load_loop
+               9               109  fib
+
+";
+
+    fn new_fib_cpu() -> CPU {
+        let code = vec![0xB9, 0x0A, 0x00, 0xE8, 0x03, 0x00, 0xE2, 0xFB, 0x90, 0x31, 0xC0, 0xC3];
+        let mut comp = CPU::new(0x1FFFF);
+        comp.load(code, CPU::physical_address(0x0050, 0x0100) as usize);
+        comp.set_reg(Regs::CS, 0x0050);
+        comp.set_reg(Regs::IP, 0x0100);
+        comp.set_reg(Regs::SP, 0xFFFF);
+        comp.load_symbols(SymbolTable::from_nasm_listing(LISTING, 0x0050));
+        comp
+    }
+
+    #[test]
+    fn test_listing_symbols() {
+        let symbols = SymbolTable::from_nasm_listing(LISTING, 0x0050);
+        assert_eq!(symbols.lookup("start"), Some((0x0050, 0x0100)));
+        assert_eq!(symbols.lookup("load_loop"), Some((0x0050, 0x0103)));
+        assert_eq!(symbols.lookup("fib"), Some((0x0050, 0x0109)));
+        assert_eq!(symbols.lookup("fib.done"), Some((0x0050, 0x010B)));
+        assert_eq!(symbols.lookup("result"), Some((0x0050, 0x010C)));
+        assert_eq!(symbols.lookup("buffer"), Some((0x0050, 0x010E)));
+        assert_eq!(symbols.lookup("end"), Some((0x0050, 0x011E)));
+
+        assert_eq!(symbols.nearest(0x0050, 0x0107), Some(("load_loop", 4)));
+        assert_eq!(symbols.describe(0x0050, 0x0107), Some(String::from("load_loop+4h")));
+        assert_eq!(symbols.describe(0x0050, 0x00FF), None);
+        assert_eq!(symbols.resolve("load_loop+0x3"), Some((0x0050, 0x0106)));
+        assert_eq!(symbols.resolve("0050:0108"), Some((0x0050, 0x0108)));
+    }
+
+    #[test]
+    fn test_listing_with_utf8() {
+        // Multi-byte characters where the offset and data columns are
+        let listing = "     1 aéééééé\n     2 00000000 C3A9 ééééééééé\n     3                                  café: ; ünïcode\n";
+        let symbols = SymbolTable::from_nasm_listing(listing, 0x0050);
+        assert_eq!(symbols.lookup("café"), Some((0x0050, 0x0002)));
+    }
+
+    #[test]
+    fn test_map_symbols() {
+        let symbols = SymbolTable::from_nasm_map(MAP, 0x0050);
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols.lookup("start"), Some((0x0050, 0x0100)));
+        assert_eq!(symbols.lookup("fib"), Some((0x0050, 0x0109)));
+        assert_eq!(symbols.name_at(0x0050, 0x0103), Some("load_loop"));
+    }
+
+    #[test]
+    fn test_symbolic_breakpoint() {
+        let mut comp = new_fib_cpu();
+        assert_eq!(comp.add_breakpoint_at("fib"), Ok((0x0050, 0x0109)));
+        assert!(comp.add_breakpoint_at("nowhere").is_err());
+
+        assert_eq!(comp.run_to_breakpoint(100), Some((0x0050, 0x0109)));
+        assert_eq!(comp.read_reg(Regs::CX).unwrap(), 10);
+        assert_eq!(comp.backtrace()[0].routine.as_deref(), Some("fib"));
+        assert_eq!(comp.run_to_breakpoint(100), Some((0x0050, 0x0109)));
+        assert_eq!(comp.read_reg(Regs::CX).unwrap(), 9);
+
+        assert!(comp.remove_breakpoint(0x0050, 0x0109));
+        assert_eq!(comp.run_to_breakpoint(5), None);
+    }
+
+    #[test]
+    fn test_symbolic_disassembly_and_trace() {
+        let mut comp = new_fib_cpu();
+        let call = comp.get_instruction_text_at(0x0050, 0x0103).unwrap();
+        assert!(call.starts_with("load_loop: "));
+        assert!(call.ends_with("; fib"));
+        assert!(comp.get_instruction_text_at(0x0050, 0x0106).unwrap().ends_with("; load_loop"));
+
+        comp.enable_trace();
+        comp.add_breakpoint(0x0050, 0x0106);
+        comp.run_to_breakpoint(100);
+        let trace = comp.take_trace();
+        let symbols: Vec<Option<&str>> = trace.iter().map(|entry| entry.symbol.as_deref()).collect();
+        assert_eq!(symbols, vec![Some("start"), Some("load_loop"), Some("fib"), Some("fib.done")]);
+        assert_eq!(trace[2].address, (0x0050, 0x0109));
+    }
+
+    #[test]
+    fn test_trace_capacity() {
+        let mut comp = new_fib_cpu();
+        comp.enable_trace_with_capacity(2);
+        comp.add_breakpoint(0x0050, 0x0106);
+        comp.run_to_breakpoint(100);
+        let trace = comp.take_trace();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].address, (0x0050, 0x0109));
+        assert_eq!(trace[1].address, (0x0050, 0x010B));
+    }
+}
+
+mod thread_test {
+    use xtreme86::cpu::{Regs, CPU};
+    use std::thread;
+
+    #[test]
+    fn test_cpus_on_worker_threads() {
+        let workers: Vec<thread::JoinHandle<u16>> = (1..=4u16).map(|n| {
+            let mut comp = CPU::new(0x1FFFF);
+            // mov ax, n; add ax, ax; nop
+            comp.load(vec![0xB8, n as u8, 0x00, 0x01, 0xC0, 0x90], 0);
+            comp.set_reg(Regs::SP, 0xFFFF);
+            thread::spawn(move || {
+                comp.run_to_nop(0);
+                comp.read_reg(Regs::AX).unwrap()
+            })
+        }).collect();
+
+        let results: Vec<u16> = workers.into_iter().map(|worker| worker.join().unwrap()).collect();
+        assert_eq!(results, vec![2, 4, 6, 8]);
+    }
+}
+
+mod registers_test {
+    use xtreme86::cpu::{Regs, CPU, Reg8, Reg16, SegReg, Flag, Flags, RegisterDiff};
+
+    #[test]
+    fn test_typed_registers() {
+        let mut comp = CPU::new(0x1FFFF);
+        comp.set_reg16(Reg16::BX, 0x1234);
+        comp.set_reg8(Reg8::AH, 0xAB);
+        comp.set_seg(SegReg::ES, 0xB800);
+
+        assert_eq!(comp.read_reg8(Reg8::BL), 0x34);
+        assert_eq!(comp.read_reg8(Reg8::BH), 0x12);
+        assert_eq!(comp.read_reg(Regs::AX), Some(0xAB00));
+        assert_eq!(comp.read_seg(SegReg::ES), 0xB800);
+        assert_eq!(Reg8::from_index(5), Some(Reg8::CH));
+        assert_eq!(Reg8::CH.reg16(), Reg16::CX);
+        assert_eq!(Reg16::from_index(4).map(Regs::from), Some(Regs::SP));
+    }
+
+    #[test]
+    fn test_flags() {
+        let flags = Flags::from(Flag::Zero).with(Flag::Carry).with(Flag::Interrupt);
+        assert!(flags.zero() && flags.carry() && flags.interrupt());
+        assert!(!flags.sign() && !flags.parity());
+        assert_eq!(flags.bits(), 0x0241);
+        assert_eq!(flags.to_string(), "- - I - - Z - - C");
+        assert_eq!(Flags::from_bits(0xFFFF).to_string(), "O D I T S Z A P C");
+
+        let mut comp = CPU::new(0x1FFFF);
+        comp.set_flags(flags);
+        assert_eq!(comp.flags(), flags);
+        // stc, nop
+        comp.load(vec![0xF9, 0x90], 0);
+        comp.set_flags(Flags::empty());
+        comp.run_to_nop(0);
+        assert!(comp.flags().carry());
+    }
+
+    #[test]
+    fn test_register_snapshot() {
+        let mut comp = CPU::new(0x1FFFF);
+        // mov al, 0x12; mov bh, 0x34; stc; nop
+        comp.load(vec![0xB0, 0x12, 0xB7, 0x34, 0xF9, 0x90], 0);
+        let before = comp.registers();
+        comp.run_to_nop(0);
+        let after = comp.registers();
+
+        assert_eq!(after.get8(Reg8::AL), 0x12);
+        assert_eq!(after.bx, 0x3400);
+        assert_eq!(before.diff(&after), vec![
+            RegisterDiff { reg: Regs::AX, before: 0x0000, after: 0x0012 },
+            RegisterDiff { reg: Regs::BX, before: 0x0000, after: 0x3400 },
+            RegisterDiff { reg: Regs::IP, before: 0x0000, after: 0x0006 },
+            RegisterDiff { reg: Regs::FLAGS, before: 0x0000, after: 0x0001 },
+        ]);
+        assert_eq!(before.diff(&after)[3].to_string(), "FLAGS: - - - - - - - - - -> - - - - - - - - C");
+        assert_eq!(after.to_string(), "AX=0012  BX=3400  CX=0000  DX=0000  SP=0000  BP=0000  SI=0000  DI=0000\n\
+                                       DS=0000  ES=0000  SS=0000  CS=0000  IP=0006  - - - - - - - - C");
+
+        let mut copy = CPU::new(0x1FFFF);
+        copy.set_registers(&after);
+        assert_eq!(copy.registers(), after);
+    }
+}
+
+mod disasm_test {
+    use xtreme86::cpu::Model;
+    use xtreme86::cpu::instruction::DecodeError;
+    use xtreme86::disasm::Disassembler;
+
+    #[test]
+    fn test_disassemble_slice() {
+        // mov ax, 6; int 0x21; add [bx + si], al; ret
+        let code = [0xB8, 0x06, 0x00, 0xCD, 0x21, 0x00, 0x00, 0xC3];
+        let listing: Vec<_> = Disassembler::new(&code, 0x1000, 0x0100).collect();
+
+        assert_eq!(listing.len(), 4);
+        let addresses: Vec<(u16, u16)> = listing.iter().map(|entry| entry.address).collect();
+        assert_eq!(addresses, vec![(0x1000, 0x0100), (0x1000, 0x0103), (0x1000, 0x0105), (0x1000, 0x0107)]);
+        assert_eq!(listing[0].bytes, vec![0xB8, 0x06, 0x00]);
+        assert_eq!(listing[0].length, 3);
+        assert_eq!(listing[0].text, "mov AX, 6");
+        assert_eq!(listing[1].text, "int 33");
+        assert_eq!(listing[3].next_ip(), 0x0108);
+        assert!(listing.iter().all(|entry| entry.instruction.is_some()));
+        assert_eq!(listing[0].to_string(), "1000:0100  B80600          mov AX, 6");
+    }
+
+    #[test]
+    fn test_undecodable_bytes() {
+        // mov ax, <missing byte>; inc word <missing ModR/M>
+        let truncated = [0x90, 0xB8, 0xFF];
+        let listing: Vec<_> = Disassembler::new(&truncated, 0, 0).collect();
+        let texts: Vec<&str> = listing.iter().map(|entry| entry.text.as_str()).collect();
+        assert_eq!(texts, vec!["nop", "db 0xB8", "db 0xFF"]);
+        assert!(listing[1].is_data());
+
+        // mov ax, <segment register 4>
+        let invalid = [0x8C, 0xE0, 0x90];
+        let listing: Vec<_> = Disassembler::new(&invalid, 0, 0).collect();
+        assert_eq!(listing[0].text, "db 0x8C");
+        // Decoding resumes at the next byte
+        assert_eq!(listing[1].address, (0, 1));
+        assert_eq!(listing[1].length, 2);
+    }
+
+    #[test]
+    fn test_model() {
+        // ret 2 on the 8086, a shift by an immediate from the 186 on
+        let code = [0xC0, 0x02, 0x00];
+        assert_eq!(Disassembler::with_model(&code, 0, 0, Model::I8086).next().unwrap().text, "ret 2");
+        assert_ne!(Disassembler::new(&code, 0, 0).next().unwrap().text, "ret 2");
+        assert_eq!(Disassembler::new(&code, 0, 0).model(), Model::I80286);
+
+        let listing: Vec<_> = Disassembler::with_model(&[0x0F, 0x01, 0x16], 0, 0, Model::I80186).collect();
+        assert!(listing[0].is_data());
+        assert!(matches!(listing[0].error, Some(DecodeError::Unsupported { .. })));
+    }
+
+    #[test]
+    fn test_decode_at() {
+        let code = [0xEB, 0x01, 0x90, 0x40];
+        let disassembler = Disassembler::new(&code, 0x0050, 0xFFFE);
+        assert_eq!(disassembler.decode_at(3).unwrap().address, (0x0050, 0x0001));
+        assert_eq!(disassembler.position_of(0x0001), Some(3));
+        assert_eq!(disassembler.position_of(0x0002), None);
+        assert!(disassembler.decode_at(4).is_none());
+    }
+}
+
+mod format_test {
+    use xtreme86::disasm::Disassembler;
+    use xtreme86::disasm::format::{FormatOptions, LetterCase, Syntax};
+
+    fn format(code: &[u8], options: FormatOptions) -> String {
+        Disassembler::with_options(code, 0x1000, 0x0100, options).next().unwrap().text
+    }
+
+    #[test]
+    fn test_native_syntax() {
+        assert_eq!(format(&[0x8B, 0x46, 0xFE], FormatOptions::default()), "mov AX, word [BP + 65534]");
+        let signed = FormatOptions { signed_displacements: true, hex: true, ..FormatOptions::default() };
+        assert_eq!(format(&[0x8B, 0x46, 0xFE], signed), "mov AX, word [BP - 0x2]");
+        assert_eq!(format(&[0xEB, 0x02], FormatOptions::default()), "jmp 2");
+        assert_eq!(format(&[0xF3, 0xA4], FormatOptions::default()), "rep movsb");
+    }
+
+    #[test]
+    fn test_nasm_syntax() {
+        let cases: Vec<(&[u8], &str)> = vec![
+            (&[0x8B, 0x46, 0xFE], "mov ax, word [bp-0x2]"),
+            (&[0x89, 0x40, 0x10], "mov word [bx+si+0x10], ax"),
+            (&[0x26, 0x89, 0x05], "mov word [es:di], ax"),
+            (&[0x2E, 0xA4], "cs movsb"),
+            (&[0x83, 0xC0, 0xFF], "add ax, 0xFFFF"),
+            (&[0x80, 0xF8, 0x05], "cmp al, 0x5"),
+            (&[0x8E, 0xD8], "mov ds, ax"),
+            (&[0xEB, 0xFE], "jmp short 0x100"),
+            (&[0x74, 0x10], "je short 0x112"),
+            (&[0xE8, 0x03, 0x00], "call near 0x106"),
+            (&[0xEA, 0x10, 0x00, 0x34, 0x12], "jmp 0x1234:0x10"),
+            (&[0xFF, 0x1F], "call far [bx]"),
+            (&[0xF3, 0xA4], "rep movsb"),
+        ];
+        for (code, text) in cases {
+            assert_eq!(format(code, FormatOptions::nasm()), text);
+        }
+    }
+
+    #[test]
+    fn test_far_return_and_breakpoint() {
+        // nasm assembles ret and int 0x3 to C3 and CD 03
+        for options in [FormatOptions::default(), FormatOptions::nasm()].iter() {
+            assert_eq!(format(&[0xCB], *options), "retf");
+            assert_eq!(format(&[0xCC], *options), "int3");
+            assert_eq!(format(&[0xC3], *options), "ret");
+        }
+        assert_eq!(format(&[0xCA, 0x04, 0x00], FormatOptions::nasm()), "retf 0x4");
+        assert_eq!(format(&[0xCD, 0x03], FormatOptions::nasm()), "int 0x3");
+    }
+
+    #[test]
+    fn test_options() {
+        let upper = FormatOptions { case: LetterCase::Upper, ..FormatOptions::nasm() };
+        assert_eq!(format(&[0x8B, 0x46, 0xFE], upper), "MOV AX, WORD [BP-0x2]");
+
+        let relative = FormatOptions { absolute_targets: false, branch_qualifiers: false, ..FormatOptions::nasm() };
+        assert_eq!(format(&[0xEB, 0xFE], relative), "jmp $+0x0");
+        assert_eq!(format(&[0xE8, 0x03, 0x00], relative), "call $+0x6");
+
+        let decimal = FormatOptions { hex: false, syntax: Syntax::Nasm, ..FormatOptions::nasm() };
+        assert_eq!(format(&[0xEA, 0x10, 0x00, 0x34, 0x12], decimal), "jmp 4660:16");
+    }
+
+    #[test]
+    fn test_arbitrary_bytes() {
+        for options in [FormatOptions::default(), FormatOptions::nasm()].iter() {
+            for first in 0..=255u8 {
+                for second in 0..=255u8 {
+                    let code = [first, second, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+                    let decoded = Disassembler::with_options(&code, 0, 0, *options).next().unwrap();
+                    assert!(!decoded.text.is_empty());
+                }
+            }
+        }
+    }
+}
+
+mod analysis_test {
+    use xtreme86::asm;
+    use xtreme86::disasm::analysis::{Analysis, SweepMode, ItemKind};
+    use xtreme86::disasm::format::FormatOptions;
+    use xtreme86::disasm::image::{Image, ImageFormat};
+
+    fn com_program() -> Vec<u8> {
+        vec![
+            0xBA, 0x0D, 0x01,   // mov dx, msg
+            0xE8, 0x0A, 0x00,   // call routine
+            0xEB, 0x01,         // jmp print
+            0x90,               // (never reached)
+            0xB4, 0x09,         // print: mov ah, 9
+            0xCD, 0x20,         // int 0x20
+            0x48, 0x69, 0x24,   // msg: db "Hi$"
+            0xC3,               // routine: ret
+        ]
+    }
+
+    fn data_items(analysis: &Analysis) -> Vec<(usize, Vec<u8>)> {
+        analysis.items().iter().filter_map(|item| match &item.kind {
+            ItemKind::Data(bytes) => Some((item.position, bytes.clone())),
+            ItemKind::Code(_) => None
+        }).collect()
+    }
+
+    #[test]
+    fn test_recursive_com() {
+        let image = Image::load(com_program()).unwrap();
+        assert_eq!(image.format, ImageFormat::Com);
+        let analysis = Analysis::new(&image, SweepMode::Recursive);
+
+        assert_eq!(analysis.labels().lookup("start"), Some((0, 0x100)));
+        assert_eq!(analysis.labels().lookup("sub_0110"), Some((0, 0x110)));
+        assert_eq!(analysis.labels().lookup("loc_0109"), Some((0, 0x109)));
+        assert_eq!(data_items(&analysis), vec![(8, vec![0x90]), (13, vec![0x48, 0x69, 0x24])]);
+
+        let source = analysis.nasm_source();
+        assert!(source.starts_with("bits 16\norg 0x100\n"));
+        assert!(source.contains("\nstart:\n    mov dx, 0x10D\n    call near sub_0110\n    jmp short loc_0109\n    db 0x90\n"));
+        assert!(source.contains("\n    db 0x48, 0x69, 0x24\n\nsub_0110:\n    ret\n"));
+
+        let listing = analysis.listing(&FormatOptions::nasm());
+        assert!(listing.contains("0000:0106  EB01                jmp short loc_0109\n"));
+    }
+
+    #[test]
+    fn test_nasm_source_keeps_encodings() {
+        let code = vec![
+            0x81, 0x07, 0xFF, 0xFF, // add word [bx], 0xFFFF with a word immediate
+            0x8B, 0x06, 0x34, 0x12, // mov ax, [0x1234] without the accumulator form
+            0x81, 0xC0, 0x05, 0x00, // add ax, 5 with a word immediate
+            0x8B, 0x87, 0x02, 0x00, // mov ax, [bx+2] with a word displacement
+            0x82, 0xC0, 0x01,       // add al, 1 through the alias of opcode 0x80
+            0x01, 0xD8,             // add ax, bx
+            0xCB, 0xCC, 0xCD, 0x20,
+        ];
+        let image = Image::load(code.clone()).unwrap();
+        let source = Analysis::new(&image, SweepMode::Linear).nasm_source();
+        assert!(source.contains("\n    db 0x81, 0x07, 0xFF, 0xFF ; add word [bx], 0xFFFF\n"));
+        assert!(source.contains("\n    add ax, bx\n    retf\n    int3\n    int 0x20\n"));
+        assert_eq!(asm::assemble(&source).unwrap(), code);
+    }
+
+    #[test]
+    fn test_linear_com() {
+        let image = Image::load(com_program()).unwrap();
+        let analysis = Analysis::new(&image, SweepMode::Linear);
+        let addresses: Vec<u16> = analysis.code_items().map(|decoded| decoded.address.1).take(5).collect();
+        assert_eq!(addresses, vec![0x100, 0x103, 0x106, 0x108, 0x109]);
+        assert_eq!(analysis.labels().lookup("loc_0109"), Some((0, 0x109)));
+    }
+
+    #[test]
+    fn test_exe() {
+        let mut file = vec![
+            b'M', b'Z', 50, 0, 1, 0, 1, 0,      // 50 bytes in 1 page, 1 relocation
+            2, 0, 0, 0, 0xFF, 0xFF, 0, 0,       // 2 paragraph header, SS=0
+            0x00, 0x01, 0, 0, 0, 0, 0, 0,       // SP=0x100, CS:IP=0000:0000
+            0x1C, 0, 0, 0, 1, 0, 0, 0,          // relocation at 0000:0001
+        ];
+        file.extend(vec![0xB8, 0x00, 0x00, 0xEA, 0x00, 0x00, 0x01, 0x00]); // mov ax, seg; jmp 0001:0000
+        file.extend(vec![0x00; 8]);
+        file.extend(vec![0xCD, 0x20]); // int 0x20
+
+        let image = Image::load(file).unwrap();
+        assert_eq!(image.format, ImageFormat::Exe);
+        assert_eq!(image.bytes.len(), 18);
+        assert_eq!(image.entry, (0, 0));
+        assert_eq!(image.stack, (0, 0x100));
+        assert_eq!(image.relocations, vec![(0, 1)]);
+
+        let analysis = Analysis::new(&image, SweepMode::Recursive);
+        assert_eq!(analysis.labels().lookup("loc_0001_0000"), Some((1, 0)));
+        assert_eq!(data_items(&analysis), vec![(8, vec![0x00; 8])]);
+        assert!(analysis.nasm_source().contains("loc_0001_0000:\n    int 0x20\n"));
+    }
+}
+
+mod semantics_test {
+    use xtreme86::cpu::{Regs, Flag};
+    use xtreme86::cpu::instruction::Instruction;
+    use xtreme86::cpu::instruction::args::Size;
+    use xtreme86::cpu::instruction::semantics::{Access, FlowKind, MemoryAccess};
+    use xtreme86::disasm::Disassembler;
+
+    fn decode(code: &[u8]) -> Instruction {
+        Disassembler::new(code, 0x1000, 0x100).decode_at(0).unwrap().instruction.unwrap()
+    }
+
+    #[test]
+    fn test_registers_and_flags() {
+        // adc ax, bx
+        let semantics = decode(&[0x11, 0xD8]).semantics((0x1000, 0x100));
+        assert!(semantics.reads_reg(Regs::AX) && semantics.reads_reg(Regs::BX));
+        assert_eq!(semantics.regs_written, vec![Regs::AX]);
+        assert!(semantics.flags_read.contains(Flag::Carry));
+        assert!(semantics.flags_written.contains(Flag::Overflow) && semantics.flags_written.contains(Flag::Zero));
+        assert!(semantics.memory.is_empty());
+        assert_eq!(semantics.flow, FlowKind::Fallthrough);
+
+        // inc cl leaves the carry alone
+        let semantics = decode(&[0xFE, 0xC1]).semantics((0x1000, 0x100));
+        assert_eq!(semantics.regs_written, vec![Regs::CX]);
+        assert!(!semantics.flags_written.contains(Flag::Carry));
+
+        // mul bx
+        let semantics = decode(&[0xF7, 0xE3]).semantics((0x1000, 0x100));
+        assert!(semantics.writes_reg(Regs::AX) && semantics.writes_reg(Regs::DX));
+        assert!(!semantics.reads_reg(Regs::DX));
+    }
+
+    #[test]
+    fn test_memory() {
+        // mov [bp + 2], ax
+        let semantics = decode(&[0x89, 0x46, 0x02]).semantics((0x1000, 0x100));
+        assert_eq!(semantics.memory, vec![MemoryAccess { segment: Regs::SS, size: Size::Word, access: Access::Write }]);
+        assert!(semantics.reads_reg(Regs::BP) && semantics.reads_reg(Regs::AX));
+        assert!(semantics.writes_memory() && !semantics.reads_memory());
+
+        // lea si, [bx + di] only computes an address
+        let semantics = decode(&[0x8D, 0x31]).semantics((0x1000, 0x100));
+        assert!(semantics.memory.is_empty());
+        assert_eq!(semantics.regs_written, vec![Regs::SI]);
+
+        // es movsb
+        let semantics = decode(&[0x26, 0xA4]).semantics((0x1000, 0x100));
+        assert_eq!(semantics.memory, vec![
+            MemoryAccess { segment: Regs::ES, size: Size::Byte, access: Access::Read },
+            MemoryAccess { segment: Regs::ES, size: Size::Byte, access: Access::Write },
+        ]);
+        assert!(semantics.flags_read.contains(Flag::Direction));
+
+        // push ax
+        let semantics = decode(&[0x50]).semantics((0x1000, 0x100));
+        assert!(semantics.reads_reg(Regs::AX) && semantics.writes_reg(Regs::SP));
+        assert_eq!(semantics.memory, vec![MemoryAccess { segment: Regs::SS, size: Size::Word, access: Access::Write }]);
+    }
+
+    #[test]
+    fn test_stack_sizes() {
+        let bytes = |code: &[u8], access: Access| decode(code).semantics((0x1000, 0x100)).memory.iter()
+            .filter(|memory| memory.segment == Regs::SS && memory.access == access)
+            .map(|memory| match memory.size { Size::Byte => 1, Size::Word => 2, Size::DWord => 4 })
+            .sum::<usize>();
+
+        // pusha / popa
+        assert_eq!(bytes(&[0x60], Access::Write), 16);
+        assert_eq!(bytes(&[0x61], Access::Read), 16);
+        // iret and int 0x21
+        assert_eq!(bytes(&[0xCF], Access::Read), 6);
+        assert_eq!(bytes(&[0xCD, 0x21], Access::Write), 6);
+        // retf / call far
+        assert_eq!(bytes(&[0xCB], Access::Read), 4);
+        assert_eq!(bytes(&[0x9A, 0x00, 0x00, 0x00, 0x20], Access::Write), 4);
+        // enter 8, 0 only pushes BP
+        assert_eq!(bytes(&[0xC8, 0x08, 0x00, 0x00], Access::Write), 2);
+        // enter 8, 3 copies two frame pointers and pushes the new one
+        assert_eq!(bytes(&[0xC8, 0x08, 0x00, 0x03], Access::Read), 4);
+        assert_eq!(bytes(&[0xC8, 0x08, 0x00, 0x03], Access::Write), 8);
+    }
+
+    #[test]
+    fn test_flow() {
+        // jz $+0x12
+        let semantics = decode(&[0x74, 0x10]).semantics((0x1000, 0x100));
+        assert_eq!(semantics.flow, FlowKind::ConditionalJump);
+        assert_eq!(semantics.targets, vec![(0x1000, 0x112)]);
+        assert!(semantics.flags_read.contains(Flag::Zero));
+        assert!(semantics.flow.falls_through());
+
+        // call 0x0200
+        let semantics = decode(&[0xE8, 0xFD, 0x00]).semantics((0x1000, 0x100));
+        assert_eq!(semantics.flow, FlowKind::Call);
+        assert_eq!(semantics.targets, vec![(0x1000, 0x200)]);
+
+        // jmp 0x2000:0x0010
+        let semantics = decode(&[0xEA, 0x10, 0x00, 0x00, 0x20]).semantics((0x1000, 0x100));
+        assert_eq!(semantics.flow, FlowKind::Jump);
+        assert_eq!(semantics.targets, vec![(0x2000, 0x10)]);
+        assert!(semantics.writes_reg(Regs::CS));
+        assert!(!semantics.flow.falls_through());
+
+        // jmp bx has no static target
+        let semantics = decode(&[0xFF, 0xE3]).semantics((0x1000, 0x100));
+        assert_eq!(semantics.flow, FlowKind::Jump);
+        assert!(semantics.targets.is_empty());
+        assert!(semantics.reads_reg(Regs::BX));
+
+        assert_eq!(decode(&[0xC3]).semantics((0x1000, 0x100)).flow, FlowKind::Return);
+        assert_eq!(decode(&[0xCD, 0x21]).semantics((0x1000, 0x100)).flow, FlowKind::Interrupt);
+
+        // rep stosw
+        let semantics = decode(&[0xF3, 0xAB]).semantics((0x1000, 0x100));
+        assert!(semantics.reads_reg(Regs::CX) && semantics.writes_reg(Regs::DI));
+        assert_eq!(semantics.memory[0].segment, Regs::ES);
+    }
+}
+
+mod assembler_test {
+    use crate::load_binary;
+    use xtreme86::asm::{self, Assembler};
+    use xtreme86::cpu::Model;
+
+    // What nasm -f bin made of the assets in src
+    const NASM_OUTPUT: [(&str, &str); 8] = [
+        ("alu", "B40090B00990B3099000D83790B80500F983D00590F983D80A90B8FFFF30C090F7D090F6D890B80E0AD50A90B805012C0A3F90B079B33500D82790B0DAD40A90B8EE002F90"),
+        ("cmp", "B80900BA090039D0740E90B81000BA1000F7C28000740190B8300090EBED"),
+        ("fib", "B90A00BB0000C70600000000C70602000100908B0783C3020307894702E2F3"),
+        ("int", "B80600CD0090B80A006206000090B8FFFF40CE90B80500CF"),
+        ("jmp", "EB06B80500B80600B80600"),
+        ("jmp_cond", "B8050083E8057404B8050090B8160090"),
+        ("lea", "BB05008D470490B0FF9890BA00009990"),
+        ("proc", "B80000E8050090E80A0090C8050000B81600C9C36061C3"),
+    ];
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|index| u8::from_str_radix(&text[index..index + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_matches_nasm() {
+        for (name, bytes) in NASM_OUTPUT.iter() {
+            let source = String::from_utf8(load_binary(&format!("src/{}.asm", name))).unwrap();
+            assert_eq!(asm::assemble(&source).unwrap(), hex(bytes), "{}.asm", name);
+        }
+    }
+
+    #[test]
+    fn test_instructions() {
+        assert_eq!(asm::assemble("mov ax, 6\nint 0x21").unwrap(), vec![0xB8, 0x06, 0x00, 0xCD, 0x21]);
+        assert_eq!(asm::assemble("mov [es:di+2], al").unwrap(), vec![0x26, 0x88, 0x45, 0x02]);
+        assert_eq!(asm::assemble("es mov al, [bx]").unwrap(), vec![0x26, 0x8A, 0x07]);
+        assert_eq!(asm::assemble("add word [bp-2], 0x1234").unwrap(), vec![0x81, 0x46, 0xFE, 0x34, 0x12]);
+        assert_eq!(asm::assemble("mov ds, ax\nmov ax, [0x10]").unwrap(), vec![0x8E, 0xD8, 0xA1, 0x10, 0x00]);
+        assert_eq!(asm::assemble("rep movsb\njmp 0x1234:0x5678").unwrap(), vec![0xF3, 0xA4, 0xEA, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(asm::assemble("push 5\nimul ax, bx, 300\nshl cx, 3").unwrap(),
+                   vec![0x6A, 0x05, 0x69, 0xC3, 0x2C, 0x01, 0xC1, 0xE1, 0x03]);
+    }
+
+    #[test]
+    fn test_labels_and_data() {
+        let assembly = Assembler::new().assemble("
+            org 0x100
+        start:
+            mov dx, message
+            jmp .done
+            times 200 nop
+        .done:
+            ret
+        message db 'Hi', 0x0D, 0x0A, '$'
+            dw start
+        ").unwrap();
+        assert_eq!(assembly.label("start"), Some(0x100));
+        assert_eq!(assembly.label("start.done"), Some(0x100 + 3 + 3 + 200));
+        // The jump is too far to be short
+        assert_eq!(&assembly.bytes[3..6], &[0xE9, 200, 0x00]);
+        assert_eq!(&assembly.bytes[207..], &[0x48, 0x69, 0x0D, 0x0A, 0x24, 0x00, 0x01]);
+        assert_eq!(&assembly.bytes[1..3], &(assembly.label("message").unwrap() as u16).to_le_bytes());
+        assert_eq!(assembly.symbols(0x1000).lookup("message"), Some((0x1000, 0x1CF)));
+
+        let padded = asm::assemble("db 1\ntimes 4-($-$$) db 0xFF").unwrap();
+        assert_eq!(padded, vec![0x01, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(asm::assemble("nop\njmp nowhere").unwrap_err(), "line 2: symbol nowhere is not defined");
+        assert_eq!(asm::assemble("mov [bx], 5").unwrap_err(), "line 1: operation size not specified");
+        assert_eq!(asm::assemble("cpu 8086\npusha").unwrap_err(), "line 2: pusha is not supported on the 8086");
+        assert!(Assembler::with_cpu(Model::I8086).assemble("enter 4, 0").is_err());
+        assert!(asm::assemble("jz far_away\ntimes 200 nop\nfar_away:").is_err());
+        assert!(asm::assemble("frobnicate ax").is_err());
+    }
+}
+
+mod cfg_test {
+    use xtreme86::asm::Assembler;
+    use xtreme86::disasm::cfg::{ControlFlowGraph, Edge, EdgeKind};
+    use xtreme86::disasm::image::Image;
+
+    const SOURCE: &str = "
+        org 0x100
+        start:
+            mov cx, 5
+        .again:
+            call work
+            loop .again
+            xor bx, bx
+        .scan:
+            inc bx
+            cmp bx, 10
+            jne .scan
+            mov ah, 9
+            int 0x21
+            int 0x20
+        work:
+            ret
+    ";
+
+    fn build() -> (ControlFlowGraph, impl Fn(&str) -> (u16, u16)) {
+        let assembly = Assembler::new().assemble(SOURCE).unwrap();
+        let image = Image::from_com(assembly.bytes.clone()).unwrap();
+        let graph = ControlFlowGraph::new(&image, &[image.entry]);
+        (graph, move |name: &str| (0, assembly.label(name).unwrap() as u16))
+    }
+
+    fn edge(graph: &ControlFlowGraph, from: (u16, u16), to: (u16, u16)) -> Option<Edge> {
+        graph.edges().iter().find(|edge| edge.from == from && edge.to == to).copied()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let (graph, label) = build();
+        let starts: Vec<u16> = graph.blocks().map(|block| block.start.1).collect();
+        // The call and the interrupt end their blocks
+        assert_eq!(starts, vec![0x100, 0x103, 0x106, 0x108, 0x10A, 0x110, 0x114, 0x116]);
+        assert_eq!(graph.block(label("start.scan")).unwrap().instructions.len(), 3);
+        assert_eq!(graph.block_containing((0, 0x10B)).unwrap().start, label("start.scan"));
+        assert!(graph.block_containing((0, 0x10C)).is_none());
+        assert_eq!(graph.block(label("work")).unwrap().end(), (0, 0x117));
+    }
+
+    #[test]
+    fn test_edges() {
+        let (graph, label) = build();
+        let again = label("start.again");
+        let scan = label("start.scan");
+
+        assert_eq!(edge(&graph, (0, 0x100), again).unwrap().kind, EdgeKind::Fallthrough);
+        assert_eq!(edge(&graph, again, label("work")).unwrap().kind, EdgeKind::Call);
+        assert_eq!(edge(&graph, again, (0, 0x106)).unwrap().kind, EdgeKind::Fallthrough);
+        assert_eq!(edge(&graph, (0, 0x106), again).unwrap().kind, EdgeKind::Branch);
+        assert_eq!(edge(&graph, scan, scan).unwrap().kind, EdgeKind::Branch);
+        assert_eq!(edge(&graph, (0, 0x110), (0, 0x114)).unwrap().kind, EdgeKind::Interrupt(0x21));
+        // int 20h and ret go nowhere
+        assert!(graph.edges().iter().all(|edge| edge.from != (0, 0x114) && edge.from != label("work")));
+        assert_eq!(graph.successors(scan), vec![scan, (0, 0x110)]);
+        assert_eq!(graph.predecessors(again), vec![(0, 0x100), (0, 0x106)]);
+    }
+
+    #[test]
+    fn test_loops() {
+        let (graph, label) = build();
+        let again = label("start.again");
+        let scan = label("start.scan");
+
+        let back: Vec<Edge> = graph.edges().iter().filter(|edge| edge.back).copied().collect();
+        assert_eq!(back.len(), 2);
+        assert_eq!(graph.loops().len(), 2);
+
+        let counted = &graph.loops()[0];
+        assert_eq!((counted.header, counted.latch), (again, (0, 0x106)));
+        assert!(counted.counted);
+        assert_eq!(counted.blocks.iter().copied().collect::<Vec<_>>(), vec![again, (0, 0x106)]);
+
+        let scanning = &graph.loops()[1];
+        assert_eq!((scanning.header, scanning.latch), (scan, scan));
+        assert!(!scanning.counted);
+        assert_eq!(scanning.blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_dot() {
+        let assembly = Assembler::new().assemble(SOURCE).unwrap();
+        let image = Image::from_com(assembly.bytes.clone()).unwrap();
+        let graph = ControlFlowGraph::new(&image, &[image.entry]);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("    \"0000:0100\" [label=\"0000:0100:\\l    mov cx, 0x5\\l\", style=bold];\n"));
+        assert!(dot.contains("    \"0000:0103\" -> \"0000:0116\" [label=\"call\", color=blue];\n"));
+        assert!(dot.contains("    \"0000:010A\" -> \"0000:010A\" [label=\"taken\", color=darkgreen, style=dashed];\n"));
+        assert!(dot.contains("    \"0000:0110\" -> \"0000:0114\" [label=\"int 0x21\", color=purple];\n"));
+
+        let dot = graph.to_dot_with_symbols(&assembly.symbols(0));
+        assert!(dot.contains("[label=\"work:\\l    ret\\l\"];"));
+        assert!(dot.contains("    call near work\\l"));
+    }
+}
+
+mod decoder_property_test {
+    use xtreme86::asm::assemble;
+    use xtreme86::cpu::{Model, Regs};
+    use xtreme86::cpu::instruction::args::DstArg;
+    use xtreme86::cpu::instruction::{InstructionDecoder, DecodeError};
+    use xtreme86::cpu::instruction::opcode::{Opcode, NumArgs};
+    use xtreme86::disasm::{Disassembler, DisassembledInstruction};
+    use xtreme86::disasm::format::FormatOptions;
+
+    // xorshift, so the tails are arbitrary but the same on every run
+    struct Random(u64);
+
+    impl Random {
+        fn bytes(&mut self, count: usize) -> Vec<u8> {
+            (0..count).map(|_| {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0 as u8
+            }).collect()
+        }
+    }
+
+    // Every pair of leading bytes, so every opcode and every ModR/M byte after it and after each prefix
+    fn inputs() -> impl Iterator<Item = Vec<u8>> {
+        let mut random = Random(0x2545F4914F6CDD1D);
+        (0..=0xFFFFu32).map(move |start| [vec![(start >> 8) as u8, start as u8], random.bytes(6)].concat())
+    }
+
+    #[test]
+    fn test_decode_never_panics() {
+        let options = FormatOptions::nasm();
+        for bytes in inputs() {
+            for length in 0..=bytes.len() {
+                let slice = &bytes[..length];
+                if let Ok(instruction) = InstructionDecoder::new(Opcode::table(), slice).get() {
+                    assert!(instruction.length <= length, "{:02X?} decoded past the end", slice);
+                    options.format(&instruction, (0, 0x100));
+                    instruction.semantics((0, 0x100));
+                }
+                let listing: Vec<_> = Disassembler::new(slice, 0, 0x100).collect();
+                assert_eq!(listing.iter().map(|decoded| decoded.length).sum::<usize>(), length);
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncated_instruction() {
+        // mov ax, 0x1234; add word [bx + si + 0x5678], 0x9ABC; test byte [0x1234], 0x56
+        for code in [vec![0xB8, 0x34, 0x12], vec![0x81, 0x80, 0x78, 0x56, 0xBC, 0x9A], vec![0x26, 0xF6, 0x06, 0x34, 0x12, 0x56]] {
+            assert_eq!(InstructionDecoder::new(Opcode::table(), &code).get().unwrap().length, code.len());
+            for length in 0..code.len() {
+                let error = InstructionDecoder::new(Opcode::table(), &code[..length]).get().unwrap_err();
+                assert_eq!(error, DecodeError::Truncated { consumed: length }, "{:02X?}", &code[..length]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_modrm() {
+        // inc/dec are the only FE operations, and lea and far indirect calls and jumps can't take a register
+        for code in [[0xFE, 0x10], [0xFE, 0x38], [0x8D, 0xC0], [0xFF, 0xD8], [0xFF, 0xE8]] {
+            let error = InstructionDecoder::new(Opcode::table(), &code).get().unwrap_err();
+            assert_eq!(error, DecodeError::InvalidModRM { opcode: code[0], mod_reg_rm: code[1], consumed: 2 });
+        }
+        assert!(InstructionDecoder::new(Opcode::table(), &[0xFE, 0x08]).get().is_ok());
+        assert!(InstructionDecoder::new(Opcode::table(), &[0xFF, 0xD0]).get().is_ok());
+        assert!(InstructionDecoder::new(Opcode::table(), &[0xFF, 0x1F]).get().is_ok());
+
+        // mov ax, <sreg 5> with a displacement
+        let error = InstructionDecoder::new(Opcode::table(), &[0x2E, 0x8C, 0xA8, 0x34, 0x12]).get().unwrap_err();
+        assert_eq!(error, DecodeError::InvalidModRM { opcode: 0x8C, mod_reg_rm: 0xA8, consumed: 5 });
+        assert_eq!(error.to_string(), "invalid ModR/M 0xA8 for opcode 0x8C");
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let error = InstructionDecoder::new(Opcode::table(), &[0x3E, 0xF1, 0x16]).get().unwrap_err();
+        assert_eq!(error, DecodeError::UnknownOpcode { opcode: 0xF1, consumed: 2 });
+        assert_eq!(error.to_string(), "unknown opcode 0xF1");
+
+        // The 8086 runs everything in the table as some instruction, but the 186 doesn't know the
+        // 286's two byte opcodes. The segment prefix counts.
+        let sidt = [0x26, 0x0F, 0x01, 0x0F];
+        let error = InstructionDecoder::with_model(Opcode::table(), &sidt, Model::I80186).get().unwrap_err();
+        assert_eq!(error, DecodeError::Unsupported { opcode: 0x0F, model: Model::I80186, consumed: 2 });
+        assert_eq!(error.to_string(), "opcode 0x0F is not supported on the 80186");
+        assert_eq!(error.consumed(), 2);
+        assert!(InstructionDecoder::with_model(Opcode::table(), &sidt, Model::I80286).get().is_ok());
+        assert!(InstructionDecoder::with_model(Opcode::table(), &sidt, Model::I8086).get().is_ok());
+
+        for code in [0x60, 0x61, 0x62, 0x68, 0x6A, 0x6C, 0x6F, 0xC0, 0xC1, 0xC8, 0xC9] {
+            assert!(!Model::I8088.supports(code) && Model::I80186.supports(code), "0x{:02X}", code);
+        }
+        assert!(Model::I8086.supports(0xC2));
+
+        let listing: Vec<_> = Disassembler::new(&[0xF1, 0x90], 0, 0x100).collect();
+        assert_eq!(listing[0].error, Some(DecodeError::UnknownOpcode { opcode: 0xF1, consumed: 1 }));
+        assert_eq!(listing[1].error, None);
+    }
+
+    #[test]
+    fn test_no_aliasing() {
+        // These used to decode as a neighbour with the low bits cleared, like 0x63 as bound
+        for code in [0x63, 0x64, 0x9B, 0xD6, 0xF1, 0xF4] {
+            let error = InstructionDecoder::new(Opcode::table(), &[code, 0xC0, 0x00, 0x00]).get().unwrap_err();
+            assert_eq!(error, DecodeError::UnknownOpcode { opcode: code, consumed: 1 });
+        }
+        // 0x0F starts a two byte opcode on the 286
+        let error = InstructionDecoder::new(Opcode::table(), &[0x0F, 0xC0, 0x00, 0x00]).get().unwrap_err();
+        assert_eq!(error, DecodeError::UnknownOpcode { opcode: 0x0F, consumed: 2 });
+        // A repeated prefix is redundant, not another opcode
+        let instruction = InstructionDecoder::new(Opcode::table(), &[0x26, 0x26, 0x90]).get().unwrap();
+        assert_eq!(instruction.length, 3);
+        // REP runs anything but a string instruction once, and other prefixes can follow it
+        let instruction = InstructionDecoder::new(Opcode::table(), &[0xF3, 0x90]).get().unwrap();
+        assert_eq!((instruction.opcode, instruction.length), (0x90, 2));
+        for code in [[0xF3, 0x26, 0xA4], [0x26, 0xF3, 0xA4]] {
+            let instruction = InstructionDecoder::new(Opcode::table(), &code).get().unwrap();
+            assert!(matches!(instruction.dst, Some(DstArg::Opcode(0xA4))));
+            assert_eq!((instruction.opcode, instruction.segment_override, instruction.length), (0xF3, Some(Regs::ES), 3));
+        }
+    }
+
+    #[test]
+    fn test_group_sub_opcodes() {
+        // test, sal alias, pop, mov and the last of FF have no instruction on their reg field
+        for code in [[0xF6, 0x08], [0xF7, 0xC8], [0xD0, 0xF0], [0x8F, 0xC8], [0xC6, 0x08], [0xFF, 0x38], [0xFE, 0xD0]] {
+            let error = InstructionDecoder::new(Opcode::table(), &[code[0], code[1], 0x00, 0x00]).get().unwrap_err();
+            assert!(matches!(error, DecodeError::InvalidModRM { opcode, mod_reg_rm, .. } if opcode == code[0] && mod_reg_rm == code[1]), "{:02X?}", code);
+        }
+
+        let text = |code: &[u8]| Disassembler::with_options(code, 0, 0x100, FormatOptions::nasm()).decode_at(0).unwrap().text;
+        assert_eq!(text(&[0x82, 0xC3, 0x05]), "add bl, 0x5");
+        assert_eq!(text(&[0xFF, 0xD3]), "call bx");
+        assert_eq!(text(&[0xFF, 0x37]), "push word [bx]");
+        assert_eq!(text(&[0xD3, 0xF8]), "sar ax, cl");
+        assert_eq!(text(&[0xF7, 0xDB]), "neg bx");
+        assert!(Opcode::table().iter().flatten().filter(|op| op.group.is_some()).all(|op| !matches!(op.num_args, NumArgs::Zero)));
+    }
+
+    fn reassemble(decoded: &DisassembledInstruction) -> DisassembledInstruction {
+        let source = format!("org 0x100\n{}\n", decoded.text);
+        let encoded = assemble(&source).unwrap_or_else(|error| panic!("{:02X?} {}: {}", decoded.bytes, decoded.text, error));
+        let again = Disassembler::with_options(&encoded, 0, 0x100, FormatOptions::nasm()).decode_at(0).unwrap();
+        assert_eq!(again.length, encoded.len(), "{:02X?} => {:02X?}", decoded.bytes, encoded);
+        again
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for bytes in inputs() {
+            let decoded = Disassembler::with_options(&bytes, 0, 0x100, FormatOptions::nasm()).decode_at(0).unwrap();
+            if decoded.is_data() {
+                continue;
+            }
+
+            // The assembler picks the shortest encoding, so [bx+si+0x0] comes back as [bx+si] and
+            // xchg ax, ax as nop. What it does encode has to come back exactly.
+            let encodable = reassemble(&decoded);
+            let mnemonic = |decoded: &DisassembledInstruction| decoded.text.split(' ').next().unwrap().to_string();
+            if decoded.bytes != [0x87, 0xC0] {
+                assert_eq!(mnemonic(&encodable), mnemonic(&decoded), "{:02X?}", decoded.bytes);
+            }
+            let again = reassemble(&encodable);
+            assert_eq!((&again.text, &again.bytes), (&encodable.text, &encodable.bytes));
+        }
+    }
+}
+
+mod conformance_test {
+    use crate::load_binary;
+    use crate::conformance::{parse_cases, Mismatch, Runner};
+    use xtreme86::cpu::{Model, Regs};
+
+    fn fixture(filename: &str) -> String {
+        String::from_utf8(load_binary(&format!("singlestep/{}", filename))).unwrap()
+    }
+
+    #[test]
+    fn test_parse_cases() {
+        let cases = parse_cases(&fixture("cases.json")).unwrap();
+        assert_eq!(cases.len(), 4);
+        assert_eq!(cases[0].name, "mov ax, 1234h");
+        assert_eq!(cases[0].bytes, vec![0xB8, 0x34, 0x12]);
+        assert_eq!(cases[0].opcode(), "B8");
+        assert_eq!(cases[2].opcode(), "88");
+
+        let group = r#"[{"name": "and byte [bx], 1h", "bytes": [128, 39, 1],
+            "initial": {"regs": {"cs": 0, "ip": 256}, "ram": [[256, 128], [257, 39], [258, 1]]},
+            "final": {"regs": {"ip": 259}, "ram": []}}]"#;
+        assert_eq!(parse_cases(group).unwrap()[0].opcode(), "80.4");
+        assert!(parse_cases(r#"[{"name": "x"}]"#).unwrap_err().starts_with("case 0"));
+    }
+
+    #[test]
+    fn test_run_fixtures() {
+        let report = Runner::new().run_file(format!("{}/tests/assets/singlestep/cases.json", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        assert_eq!((report.passed(), report.failed()), (3, 1));
+
+        // and leaves AF undefined, the vectors were recorded with it set
+        let failures = &report.opcodes["24"].failures;
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].mismatches, vec![Mismatch::Register { reg: Regs::FLAGS, expected: 0xF012, actual: 0xF002 }]);
+        assert!(report.to_string().contains("24    0/1 passed"));
+        assert!(report.to_string().contains("flags: expected F012"));
+
+        let mut runner = Runner::new();
+        runner.load_metadata(&fixture("metadata.json")).unwrap();
+        assert_eq!(runner.model(), Model::I8088);
+        assert_eq!(runner.flags_mask("24"), 0x0FC5);
+        assert_eq!(runner.flags_mask("80.4"), 0x0FC5);
+        assert_eq!(runner.flags_mask("B8"), 0x0FD5);
+        let report = runner.run_json(&fixture("cases.json")).unwrap();
+        assert_eq!((report.passed(), report.failed()), (4, 0));
+    }
+
+    #[test]
+    fn test_divide_errors() {
+        // The 8088 pushes the address after the divide and faults on -128 and -32768 quotients
+        let mut runner = Runner::new();
+        runner.set_model(Model::I8088);
+        let report = runner.run_json(&fixture("div.json")).unwrap();
+        assert_eq!((report.passed(), report.failed()), (11, 0));
+
+        // The 286 pushes the divide's own address and returns those quotients
+        runner.set_model(Model::I80286);
+        let report = runner.run_json(&fixture("div.json")).unwrap();
+        assert_eq!((report.passed(), report.failed()), (4, 7));
+        let failures = &report.opcodes["F6.7"].failures;
+        assert_eq!(failures.len(), 2);
+        assert!(failures[0].mismatches.contains(&Mismatch::Register { reg: Regs::IP, expected: 0x400, actual: 0x102 }));
+    }
+
+    #[test]
+    fn test_memory_mismatch() {
+        let mut cases = parse_cases(&fixture("cases.json")).unwrap();
+        let store = cases.remove(2);
+        let mut wrong = store.clone();
+        wrong.expected.ram = vec![(0x30030, 0x5B)];
+
+        assert!(Runner::new().run_case(&store).is_empty());
+        let mismatches = Runner::new().run_case(&wrong);
+        assert_eq!(mismatches, vec![Mismatch::Memory { address: 0x30030, expected: 0x5B, actual: 0x5A }]);
+        assert_eq!(mismatches[0].to_string(), "[30030]: expected 5B, got 5A");
+    }
+}
+
+mod prefetch_test {
+    use crate::cpu::{Regs, CPU};
+    use xtreme86::asm;
+    use xtreme86::cpu::Model;
+    use xtreme86::cpu::prefetch::PrefetchQueue;
+
+    // mul bl leaves AL alone and runs long enough for the queue to fill up, then stosb
+    // overwrites the inc ax at offset 7 with inc cx
+    fn run(model: Model, padding: &[u8], prefetch: bool) -> CPU {
+        let mut code = vec![0xF6, 0xE3, 0xAA];
+        code.extend_from_slice(padding);
+        code.extend_from_slice(&[0x40, 0x90]);
+        assert_eq!(code.len(), 9);
+
+        let mut comp = CPU::with_model(0xFFFF, model);
+        comp.load(code, 0);
+        comp.set_reg(Regs::SP, 0x8000);
+        comp.set_reg(Regs::AX, 0x41);
+        comp.set_reg(Regs::BX, 1);
+        comp.set_reg(Regs::DI, 7);
+        if prefetch {
+            comp.enable_prefetch_queue();
+        }
+        comp.run_to_nop(0);
+        assert_eq!(comp.probe_mem(7), 0x41);
+        comp
+    }
+
+    fn ran_stale_code(comp: &CPU) -> bool {
+        match (comp.read_reg(Regs::AX).unwrap(), comp.read_reg(Regs::CX).unwrap()) {
+            (0x42, 0) => true,
+            (0x41, 1) => false,
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_queue_size() {
+        const CLD: [u8; 4] = [0xFC; 4];
+        // The classic check, the patched byte is five bytes ahead
+        assert!(!ran_stale_code(&run(Model::I8088, &CLD, true)));
+        assert!(ran_stale_code(&run(Model::I8086, &CLD, true)));
+        assert!(!ran_stale_code(&run(Model::I8086, &CLD, false)));
+
+        let mut comp = CPU::with_model(0xFFFF, Model::I8088);
+        comp.enable_prefetch_queue();
+        assert_eq!(comp.prefetch_queue().unwrap().size(), 4);
+        assert_eq!(PrefetchQueue::for_model(Model::I80186).size(), 6);
+    }
+
+    #[test]
+    fn test_queue_flushed_by_jump() {
+        // jmp short $+2 between the store and the patched byte
+        let comp = run(Model::I8086, &[0xEB, 0x00, 0xFC, 0xFC], true);
+        assert!(!ran_stale_code(&comp));
+        let comp = run(Model::I8086, &[0xFC, 0xFC, 0xFC, 0xFC], true);
+        assert_eq!(comp.prefetch_queue().unwrap().head(), (0, 9));
+    }
+
+    // Clocks until the nop after `source` is decoded, and what the queue holds then
+    fn run_source(source: &str) -> (usize, usize) {
+        let mut comp = CPU::with_model(0xFFFF, Model::I8088);
+        comp.load(asm::assemble(&format!("{}\nnop\nnop\nnop\nnop\nnop", source)).unwrap(), 0);
+        comp.set_reg(Regs::SP, 0x8000);
+        comp.set_reg(Regs::CX, 4);
+        comp.enable_prefetch_queue();
+        comp.run_to_nop(0);
+        (comp.cycles(), comp.prefetch_queue().unwrap().len())
+    }
+
+    #[test]
+    fn test_bus_contention() {
+        // The shift leaves the bus to the queue, which fills up. movsw runs longer but holds the
+        // bus for 16 of its clocks, a bus cycle for each byte it moves on the 8088.
+        let (alu_clocks, alu_queued) = run_source("shl ax, cl");
+        let (memory_clocks, memory_queued) = run_source("movsw");
+        assert!(memory_clocks > alu_clocks);
+        assert_eq!(alu_queued, 3);
+        assert_eq!(memory_queued, 2);
+    }
+}
+
+mod timing_test {
+    use crate::cpu::{Regs, CPU};
+    use xtreme86::asm;
+    use xtreme86::cpu::Model;
+    use xtreme86::cpu::instruction::InstructionDecoder;
+    use xtreme86::cpu::instruction::opcode::Opcode;
+
+    fn run(model: Model, source: &str, setup: &dyn Fn(&mut CPU)) -> usize {
+        let mut comp = CPU::with_model(0xFFFF, model);
+        comp.load(asm::assemble(&format!("{}\nnop", source)).unwrap(), 0);
+        comp.set_reg(Regs::SP, 0x8000);
+        setup(&mut comp);
+        comp.run_to_nop(0);
+        // The nop was decoded but not executed, which took two clocks
+        comp.cycles() - 2
+    }
+
+    fn clocks(model: Model, source: &str) -> usize {
+        run(model, source, &|_| ())
+    }
+
+    fn table(model: Model, source: &str) -> usize {
+        let code = asm::assemble(source).unwrap();
+        InstructionDecoder::with_model(Opcode::table(), &code, model).get().unwrap().next_cycles
+    }
+
+    #[test]
+    fn test_effective_address() {
+        assert_eq!(table(Model::I8086, "add ax, bx"), 3);
+        assert_eq!(table(Model::I8086, "mov ax, [bx]"), 8 + 5);
+        assert_eq!(table(Model::I8086, "mov cx, [0x10]"), 8 + 6);
+        assert_eq!(table(Model::I8086, "mov ax, [0x10]"), 10);
+        assert_eq!(table(Model::I8086, "mov ax, [bx+si+4]"), 8 + 11);
+        assert_eq!(table(Model::I8086, "mov ax, [bx+di+4]"), 8 + 12);
+        assert_eq!(table(Model::I8086, "add [bp+2], ax"), 16 + 9);
+        assert_eq!(table(Model::I8086, "mov ax, [es:bx]"), 8 + 5 + 2);
+        assert_eq!(table(Model::I80186, "mov ax, [bx+si+4]"), 9);
+        assert_eq!(table(Model::I80286, "mov ax, [bx+si+4]"), 5 + 1);
+        assert_eq!(table(Model::I8086, "mov ax, [0x10]"), table(Model::I8088, "mov ax, [0x10]"));
+    }
+
+    #[test]
+    fn test_word_penalty() {
+        assert_eq!(clocks(Model::I8086, "mov cx, [0x10]"), 14);
+        assert_eq!(clocks(Model::I8086, "mov cx, [0x11]"), 14 + 4);
+        assert_eq!(clocks(Model::I8086, "mov cl, [0x11]"), 14);
+        assert_eq!(clocks(Model::I8088, "mov cx, [0x10]"), 14 + 4);
+        assert_eq!(clocks(Model::I80286, "mov cx, [0x11]"), 5 + 2);
+    }
+
+    #[test]
+    fn test_branches() {
+        let flags = |zero: bool| move |comp: &mut CPU| comp.set_reg(Regs::FLAGS, if zero { 0x40 } else { 0 });
+        assert_eq!(run(Model::I8086, "jz $+2", &flags(false)), 4);
+        assert_eq!(run(Model::I8086, "jz $+2", &flags(true)), 16);
+        assert_eq!(run(Model::I80186, "jz $+2", &flags(true)), 13);
+        assert_eq!(run(Model::I80286, "jz $+2", &flags(true)), 7);
+        assert_eq!(clocks(Model::I8086, "jmp short $+2"), 15);
+    }
+
+    #[test]
+    fn test_delay_loop() {
+        // mov cx is 4 clocks, each taken loop 17 and the last one 5
+        assert_eq!(clocks(Model::I8086, "mov cx, 10\nl: loop l"), 4 + 9 * 17 + 5);
+        let per_iteration = |model| clocks(model, "mov cx, 20\nl: loop l") - clocks(model, "mov cx, 10\nl: loop l");
+        assert_eq!(per_iteration(Model::I8086), 10 * 17);
+        assert_eq!(per_iteration(Model::I80186), 10 * 16);
+        assert_eq!(per_iteration(Model::I80286), 10 * 8);
+    }
+
+    #[test]
+    fn test_rep() {
+        let count = |cx: u16| move |comp: &mut CPU| {
+            comp.set_reg(Regs::CX, cx);
+            comp.set_reg(Regs::DI, 0x1000);
+        };
+        assert_eq!(run(Model::I8086, "rep stosb", &count(100)), 9 + 100 * 10);
+        assert_eq!(run(Model::I80286, "rep stosb", &count(100)), 4 + 100 * 3);
+        assert_eq!(run(Model::I8086, "rep stosb", &count(0)), 9);
+        // Every word stored costs the 8088 another four clocks
+        assert_eq!(run(Model::I8088, "rep stosw", &count(100)), 9 + 100 * (10 + 4));
+    }
+
+    #[test]
+    fn test_shift_by_cl() {
+        let count = |cl: u16| move |comp: &mut CPU| comp.set_reg(Regs::CX, cl);
+        assert_eq!(run(Model::I8086, "shl ax, cl", &count(5)), 8 + 5 * 4);
+        assert_eq!(run(Model::I80186, "shl ax, cl", &count(5)), 5 + 5);
+        assert_eq!(clocks(Model::I8086, "shl ax, 1"), 2);
+    }
+}
+
+mod i286_test {
+    use crate::cpu::{Regs, CPU};
+    use xtreme86::asm;
+    use xtreme86::cpu::Model;
+    use xtreme86::peripheral::Peripheral;
+
+    // lidt [0x500], int 0x21, with handlers for 0x21 at 0100:0040 and 8 at 0100:0050
+    fn new_286(limit: u16) -> CPU {
+        let source = "CPU 286\nlidt [0x500]\nint 0x21\nnop\n\
+            times 0x40-($-$$) db 0\nmov ax, 0x21\nnop\n\
+            times 0x50-($-$$) db 0\nmov ax, 8\nnop";
+        let mut comp = CPU::with_model(0xFFFFF, Model::I80286);
+        comp.load(asm::assemble(source).unwrap(), 0x1000);
+        comp.set_reg(Regs::CS, 0x100);
+        comp.set_reg(Regs::SS, 0x800);
+        comp.set_reg(Regs::SP, 0x100);
+        comp.write_bytes(0x500, vec![limit as u8, (limit >> 8) as u8, 0x00, 0x20, 0x01]).unwrap();
+        for (num, ip) in [(0x21, 0x40), (8, 0x50)] {
+            comp.write_word(0x12000 + num * 4, ip).unwrap();
+            comp.write_word(0x12000 + num * 4 + 2, 0x100).unwrap();
+        }
+        comp
+    }
+
+    #[test]
+    fn test_divide_error() {
+        // div bl faults with the return address on itself, the idiv gives -128
+        let source = "idiv bl\nmov bl, 0\ndiv bl\nnop\ntimes 0x40-($-$$) db 0\nnop";
+        let mut comp = CPU::with_model(0xFFFFF, Model::I80286);
+        comp.load(asm::assemble(source).unwrap(), 0x1000);
+        comp.write_word(0, 0x40).unwrap();
+        comp.write_word(2, 0x100).unwrap();
+        comp.set_reg(Regs::CS, 0x100);
+        comp.set_reg(Regs::SS, 0x800);
+        comp.set_reg(Regs::SP, 0x100);
+        comp.set_reg(Regs::AX, 0xFF80);
+        comp.set_reg(Regs::BX, 1);
+        comp.run_to_nop(0);
+
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 0x41);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x0080);
+        assert_eq!(comp.probe_mem_word(0x80FA), 4);
+    }
+
+    #[test]
+    fn test_lidt() {
+        let mut comp = new_286(0x3FF);
+        comp.run_to_nop(0);
+        assert_eq!(comp.interrupt_table(), (0x12000, 0x3FF));
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x21);
+
+        let mut comp = CPU::with_model(0xFFFFF, Model::I80286);
+        comp.load(asm::assemble("CPU 286\nlidt [0x500]\nsidt [0x510]\nnop").unwrap(), 0);
+        comp.write_bytes(0x500, vec![0x7F, 0x00, 0x00, 0x20, 0x01, 0x00]).unwrap();
+        comp.run_to_nop(0);
+        assert_eq!(comp.interrupt_table(), (0x12000, 0x7F));
+        assert_eq!((comp.probe_mem_word(0x510), comp.probe_mem_word(0x512), comp.probe_mem_word(0x514)), (0x7F, 0x2000, 0xFF01));
+    }
+
+    #[test]
+    fn test_vector_past_limit() {
+        // Vectors 0 to 0x13, so 0x21 raises interrupt 8
+        let mut comp = new_286(0x4F);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 8);
+        assert!(!comp.is_shut_down());
+    }
+
+    #[test]
+    fn test_frame_wraps_in_stack_segment() {
+        // Segment limits aren't checked, so pushing the frame across the bottom of SS is no fault
+        let mut comp = new_286(0x3FF);
+        comp.set_reg(Regs::SP, 2);
+        comp.run_to_nop(0);
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0x21);
+        assert!(!comp.is_shut_down());
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0xFFFC);
+        assert_eq!((comp.probe_mem_word(0x17FFC), comp.probe_mem_word(0x17FFE)), (7, 0x100));
+    }
+
+    #[derive(Clone)]
+    struct Debugger;
+
+    impl Peripheral for Debugger {
+        fn init(&self, comp: &mut CPU, index: usize) {
+            comp.hook_interrupt(index, 3);
+        }
+
+        fn handle_interrupt(&mut self, _: &mut CPU, _: u8) -> usize { 0 }
+
+        fn handle_mem_read_byte(&mut self, _: u16) -> u8 { 0 }
+
+        fn handle_mem_read_word(&mut self, _: u16) -> u16 { 0 }
+
+        fn handle_mem_write_byte(&mut self, _: u16, _: u8) {}
+
+        fn handle_mem_write_word(&mut self, _: u16, _: u16) {}
+    }
+
+    #[test]
+    fn test_table_past_ram() {
+        // The table is at FF0000, past the megabyte of RAM, where the vectors read as FFFF:FFFF
+        let mut comp = CPU::with_model(0xFFFFF, Model::I80286);
+        comp.load(asm::assemble("CPU 286\nlidt [0x500]\nnop\nint 3\nnop").unwrap(), 0);
+        comp.write_bytes(0x500, vec![0xFF, 0x03, 0x00, 0x00, 0xFF]).unwrap();
+        comp.set_reg(Regs::SP, 0x8000);
+        comp.run_to_nop(0);
+        assert_eq!(comp.interrupt_table(), (0xFF0000, 0x3FF));
+
+        comp.hook_peripheral(Box::new(Debugger));
+        comp.run_to_nop_from_ip();
+        // The int returned straight away and the nop after it was decoded
+        assert!(!comp.is_shut_down());
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 9);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0x8000);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        // Interrupt 8 is past the limit as well
+        let mut comp = new_286(0x1F);
+        comp.run_to_nop(0);
+        assert!(comp.is_shut_down());
+        assert_eq!(comp.read_reg(Regs::AX).unwrap(), 0);
+        assert_eq!(comp.read_reg(Regs::SP).unwrap(), 0x100);
+
+        let cycles = comp.cycles();
+        comp.step();
+        assert_eq!(comp.cycles(), cycles + 1);
+        // Nothing runs past the int
+        assert_eq!(comp.read_reg(Regs::IP).unwrap(), 7);
+
+        comp.recover_from_shutdown();
+        assert!(!comp.is_shut_down());
+        assert_eq!(comp.interrupt_table(), (0, 0x3FF));
+    }
+}
+
+mod interrupt_test {
+    use crate::cpu::{Regs, CPU};
+    use std::sync::{Arc, Mutex};
+    use xtreme86::asm;
+    use xtreme86::cpu::Model;
+    use xtreme86::peripheral::Peripheral;
+
+    // SS, SP, IP and AX
+    type State = (u16, u16, u16, u16);
+
+    // Raises interrupt 8 after every instruction and handles it itself, writing down SS, SP, IP
+    // and AX each time it gets in. SP is below the FLAGS, CS and IP the interrupt pushed.
+    #[derive(Clone)]
+    struct Timer {
+        taken: Arc<Mutex<Vec<State>>>,
+    }
+
+    impl Peripheral for Timer {
+        fn init(&self, comp: &mut CPU, index: usize) {
+            comp.hook_interrupt(index, 8);
+        }
+
+        fn handle_interrupt(&mut self, comp: &mut CPU, _: u8) -> usize {
+            let state = [Regs::SS, Regs::SP, Regs::IP, Regs::AX].map(|reg| comp.read_reg(reg).unwrap());
+            self.taken.lock().unwrap().push((state[0], state[1], state[2], state[3]));
+            0
+        }
+
+        fn handle_mem_read_byte(&mut self, _: u16) -> u8 { 0 }
+
+        fn handle_mem_read_word(&mut self, _: u16) -> u16 { 0 }
+
+        fn handle_mem_write_byte(&mut self, _: u16, _: u8) {}
+
+        fn handle_mem_write_word(&mut self, _: u16, _: u16) {}
+
+        fn tick(&mut self, comp: &mut CPU) {
+            comp.request_interrupt(8);
+        }
+    }
+
+    fn new_cpu_from_asm(source: &str, flags: u16) -> CPU {
+        let mut computer = CPU::with_model(0x7FFFF, Model::I8086);
+        computer.load(asm::assemble(&format!("{}\nnop", source)).unwrap(), 0x10000);
+        computer.set_reg(Regs::CS, 0x1000);
+        computer.set_reg(Regs::SS, 0x2000);
+        computer.set_reg(Regs::SP, 0x200);
+        computer.set_reg(Regs::FLAGS, flags);
+        computer
+    }
+
+    fn run_with_timer(source: &str, flags: u16) -> (CPU, Vec<State>) {
+        let mut computer = new_cpu_from_asm(source, flags);
+        let taken = Arc::new(Mutex::new(Vec::new()));
+        computer.hook_peripheral(Box::new(Timer { taken: taken.clone() }));
+        computer.run_to_nop(0);
+        let taken = taken.lock().unwrap().clone();
+        (computer, taken)
+    }
+
+    #[test]
+    fn test_mov_ss_shadow() {
+        let (computer, taken) = run_with_timer("mov ax, 0x3000\nmov bx, 0x100\nmov ss, ax\nmov sp, bx\nmov ds, ax", 0x0200);
+        assert_eq!(taken, vec![
+            (0x2000, 0x1FA, 3, 0x3000),
+            (0x2000, 0x1FA, 6, 0x3000),
+            (0x3000, 0xFA, 10, 0x3000),
+            (0x3000, 0xFA, 12, 0x3000),
+        ]);
+        // Nothing went onto the new stack below the old SP
+        for address in 0x301FA..0x30200 {
+            assert_eq!(computer.probe_mem(address), 0);
+        }
+        assert_eq!(computer.probe_mem_word(0x300FA), 12);
+    }
+
+    #[test]
+    fn test_pop_ss_shadow() {
+        let (_, taken) = run_with_timer("mov ax, 0x3000\nmov bx, 0x100\npush ax\npop ss\nmov sp, bx", 0x0200);
+        assert_eq!(taken, vec![
+            (0x2000, 0x1FA, 3, 0x3000),
+            (0x2000, 0x1FA, 6, 0x3000),
+            (0x2000, 0x1F8, 7, 0x3000),
+            (0x3000, 0xFA, 10, 0x3000),
+        ]);
+    }
+
+    #[test]
+    fn test_sti_shadow() {
+        let (computer, taken) = run_with_timer("inc ax\nsti\ninc ax\ninc ax", 0);
+        assert_eq!(taken, vec![(0x2000, 0x1FA, 3, 2), (0x2000, 0x1FA, 4, 3)]);
+        assert_eq!(computer.read_reg(Regs::FLAGS).unwrap() & 0x0200, 0x0200);
+
+        let (_, taken) = run_with_timer("inc ax\ninc ax\ncli", 0);
+        assert!(taken.is_empty());
+    }
+
+    #[test]
+    fn test_hooked_interrupt_stack() {
+        // Taken after each of the incs, the hook pops what the interrupt pushed
+        let (computer, taken) = run_with_timer("inc ax\ninc ax\ninc ax", 0x0200);
+        assert_eq!(taken.len(), 3);
+        assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0x200);
+        assert_eq!(computer.read_reg(Regs::AX).unwrap(), 3);
+        assert_eq!(computer.read_reg(Regs::FLAGS).unwrap() & 0x0200, 0x0200);
+    }
+
+    #[test]
+    fn test_interrupt_handler() {
+        // The handler reads FLAGS into CX
+        let mut computer = new_cpu_from_asm("inc ax\nsti\ninc ax\nmov dx, ax", 0);
+        computer.load(asm::assemble("pushf\npop cx\niret").unwrap(), 0x10020);
+        computer.load(vec![0x20, 0x00, 0x00, 0x10], 0x20);
+        computer.request_interrupt(8);
+        computer.run_to_nop(0);
+
+        assert_eq!(computer.pending_interrupt(), None);
+        assert_eq!(computer.read_reg(Regs::CX).unwrap() & 0x0300, 0);
+        assert_eq!(computer.read_reg(Regs::FLAGS).unwrap() & 0x0200, 0x0200);
+        assert_eq!(computer.read_reg(Regs::DX).unwrap(), 2);
+        assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0x200);
+        assert_eq!(computer.probe_mem_word(0x201FA), 3);
+        assert_eq!(computer.probe_mem_word(0x201FC), 0x1000);
+        assert_eq!(computer.probe_mem_word(0x201FE) & 0x0200, 0x0200);
+    }
+}
+
+mod reset_test {
+    use crate::cpu::{Regs, CPU};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use xtreme86::asm;
+    use xtreme86::cpu::Model;
+    use xtreme86::peripheral::Peripheral;
+
+    // Hooks int 0x10 and counts the resets it sees
+    #[derive(Clone)]
+    struct Video {
+        resets: Arc<AtomicUsize>,
+    }
+
+    impl Peripheral for Video {
+        fn init(&self, comp: &mut CPU, index: usize) {
+            comp.hook_interrupt(index, 0x10);
+        }
+
+        fn handle_interrupt(&mut self, _: &mut CPU, _: u8) -> usize { 0 }
+
+        fn handle_mem_read_byte(&mut self, _: u16) -> u8 { 0 }
+
+        fn handle_mem_read_word(&mut self, _: u16) -> u16 { 0 }
+
+        fn handle_mem_write_byte(&mut self, _: u16, _: u8) {}
+
+        fn handle_mem_write_word(&mut self, _: u16, _: u16) {}
+
+        fn reset(&mut self, comp: &mut CPU, index: usize) {
+            self.resets.fetch_add(1, Ordering::SeqCst);
+            comp.hook_interrupt(index, 0x10);
+        }
+    }
+
+    // Code at F000:0000 and a far jump to it at the reset vector
+    fn bios(source: &str) -> Vec<u8> {
+        let mut image = asm::assemble(&format!("{}\nnop", source)).unwrap();
+        image.resize(0xFFF0, 0xFF);
+        image.extend_from_slice(&[0xEA, 0x00, 0x00, 0x00, 0xF0]);
+        image.resize(0x10000, 0xFF);
+        image
+    }
+
+    #[test]
+    fn test_reset_state() {
+        let mut computer = CPU::with_model(0xFFFFF, Model::I8086);
+        for reg in [Regs::AX, Regs::SP, Regs::SS, Regs::DS, Regs::CS, Regs::IP, Regs::FLAGS] {
+            computer.set_reg(reg, 0x1234);
+        }
+        computer.load(vec![0x55], 0x500);
+        computer.reset(false);
+
+        assert_eq!(computer.read_reg(Regs::CS).unwrap(), 0xFFFF);
+        for reg in [Regs::AX, Regs::SP, Regs::SS, Regs::DS, Regs::IP, Regs::FLAGS] {
+            assert_eq!(computer.read_reg(reg).unwrap(), 0);
+        }
+        assert_eq!(computer.probe_mem(0x500), 0x55);
+
+        computer.reset(true);
+        assert_eq!(computer.probe_mem(0x500), 0);
+
+        let mut computer = CPU::with_model(0xFFFFF, Model::I80286);
+        computer.reset(false);
+        assert_eq!(computer.read_reg(Regs::CS).unwrap(), 0xF000);
+        assert_eq!(computer.read_reg(Regs::IP).unwrap(), 0xFFF0);
+    }
+
+    #[test]
+    fn test_rom_boot() {
+        // The write to the ROM goes nowhere
+        let mut computer = CPU::with_model(0xFFFF, Model::I8086);
+        computer.map_rom(&bios("mov ax, cs\nmov ds, ax\nmov byte [0], 0x55\nmov bl, [0]")).unwrap();
+        computer.load(vec![0x55], 0x500);
+        computer.reset(true);
+        assert_eq!(computer.probe_mem(0x500), 0);
+        computer.run_to_nop_from_ip();
+
+        assert_eq!(computer.read_reg(Regs::CS).unwrap(), 0xF000);
+        assert_eq!(computer.read_reg(Regs::BX).unwrap(), 0x8C);
+        assert_eq!(computer.probe_mem(0xF0000), 0x8C);
+
+        assert!(computer.map_rom(&[]).is_err());
+        assert!(computer.map_rom(&vec![0; 0x10001]).is_err());
+    }
+
+    #[test]
+    fn test_rom_data_access() {
+        // RAM grows to the megabyte the ROM ends, data goes anywhere below it
+        let mut computer = CPU::with_model(0xFFFF, Model::I8086);
+        computer.map_rom(&bios("xor ax, ax\nmov ds, ax\nmov byte [0x10], 0x55\nmov cl, [0x10]\n\
+            mov byte [0x8001], 0xAA\nmov bl, [cs:0x100]\nmov byte [cs:0x100], 0\nmov dl, [cs:0x100]")).unwrap();
+        computer.reset(true);
+        computer.run_to_nop_from_ip();
+
+        assert_eq!(computer.probe_mem(0x10), 0x55);
+        assert_eq!(computer.probe_mem(0x8001), 0xAA);
+        assert_eq!(computer.read_reg(Regs::CX).unwrap() & 0xFF, 0x55);
+        // The ROM reads back its filler, before and after the write
+        assert_eq!(computer.read_reg(Regs::BX).unwrap() & 0xFF, 0xFF);
+        assert_eq!(computer.read_reg(Regs::DX).unwrap() & 0xFF, 0xFF);
+        assert_eq!(computer.probe_mem(0xF0100), 0xFF);
+    }
+
+    #[test]
+    fn test_peripheral_reset() {
+        let resets = Arc::new(AtomicUsize::new(0));
+        let mut computer = CPU::with_model(0xFFFFF, Model::I8086);
+        let index = computer.hook_peripheral(Box::new(Video { resets: resets.clone() }));
+        computer.reset(true);
+
+        assert_eq!(resets.load(Ordering::SeqCst), 1);
+        assert_eq!(computer.probe_mem_word(0x40), index as u16);
+        assert_eq!(computer.probe_mem_word(0x42), 0xFFFF);
+    }
+}
+
+mod com_test {
+    use crate::cpu::{Regs, CPU};
+    use xtreme86::asm;
+
+    // Copies its command tail after itself, reads the memory top and returns to int 0x20, whose
+    // handler at 2000:0000 is a nop
+    fn run(command_tail: &str) -> CPU {
+        let source = "org 0x100\nmov si, 0x81\nmov cl, [0x80]\nxor ch, ch\nmov di, buffer\nrep movsb\nmov ax, [0x02]\nret\nbuffer:";
+        let mut computer = CPU::new(0x7FFFF);
+        for reg in [Regs::AX, Regs::BX, Regs::CX, Regs::DX, Regs::BP, Regs::SI, Regs::DI, Regs::FLAGS] {
+            computer.set_reg(reg, 0x1234);
+        }
+        computer.load(vec![0x00, 0x00, 0x00, 0x20], 0x80);
+        computer.load(vec![0x90], 0x20000);
+        computer.load_com(&asm::assemble(source).unwrap(), 0x1000, command_tail).unwrap();
+        computer
+    }
+
+    #[test]
+    fn test_load_com() {
+        let computer = run(" a:foo.txt *.c");
+        for reg in [Regs::CS, Regs::DS, Regs::ES, Regs::SS] {
+            assert_eq!(computer.read_reg(reg).unwrap(), 0x1000);
+        }
+        assert_eq!(computer.read_reg(Regs::IP).unwrap(), 0x100);
+        assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0xFFFE);
+        assert_eq!(computer.probe_mem_word(0x1FFFE), 0);
+        for reg in [Regs::AX, Regs::BX, Regs::CX, Regs::DX, Regs::BP, Regs::SI, Regs::DI, Regs::FLAGS] {
+            assert_eq!(computer.read_reg(reg).unwrap(), 0, "{:?}", reg);
+        }
+
+        assert_eq!((computer.probe_mem(0x10000), computer.probe_mem(0x10001)), (0xCD, 0x20));
+        assert_eq!(computer.probe_mem_word(0x10002), 0x7FFF);
+        assert_eq!(computer.probe_mem(0x10080), 14);
+        assert_eq!(computer.probe_mem(0x1008F), 0x0D);
+        let fcb = |address: usize| (address..address + 12).map(|address| computer.probe_mem(address)).collect::<Vec<u8>>();
+        assert_eq!(fcb(0x1005C), b"\x01FOO     TXT");
+        assert_eq!(fcb(0x1006C), b"\x00????????C  ");
+    }
+
+    #[test]
+    fn test_run_com() {
+        let mut computer = run(" hello");
+        computer.run_to_nop_from_ip();
+
+        assert_eq!((computer.read_reg(Regs::CS).unwrap(), computer.read_reg(Regs::IP).unwrap()), (0x2000, 1));
+        assert_eq!(computer.read_reg(Regs::AX).unwrap(), 0x7FFF);
+        let copied = (0..6).map(|i| computer.probe_mem(0x10112 + i)).collect::<Vec<u8>>();
+        assert_eq!(copied, b" hello");
+    }
+
+    #[test]
+    fn test_reload_com() {
+        // Stop the first program with its mov si, 0x81 decoded but not run and an interrupt pending
+        let mut computer = run(" first");
+        computer.step();
+        computer.request_interrupt(0x21);
+
+        computer.load_com(&asm::assemble("org 0x100\nmov ax, 5\nnop").unwrap(), 0x1000, "").unwrap();
+        assert_eq!(computer.pending_interrupt(), None);
+        computer.run_to_nop_from_ip();
+        assert_eq!(computer.read_reg(Regs::SI).unwrap(), 0);
+        assert_eq!((computer.read_reg(Regs::CS).unwrap(), computer.read_reg(Regs::IP).unwrap()), (0x1000, 0x104));
+        assert_eq!(computer.read_reg(Regs::AX).unwrap(), 5);
+        assert_eq!(computer.read_reg(Regs::SP).unwrap(), 0xFFFE);
+    }
+
+    #[test]
+    fn test_load_com_errors() {
+        let mut computer = CPU::new(0x7FFFF);
+        assert!(computer.load_com(&[0x90], 0x7800, "").is_err());
+        assert!(computer.load_com(&vec![0x90; 0xFF00], 0x1000, "").is_err());
+        assert!(computer.load_com(&[0x90], 0x1000, &"x".repeat(127)).is_err());
+        assert!(computer.load_com(&[0x90], 0x1000, &"x".repeat(126)).is_ok());
+        assert_eq!(computer.probe_mem(0x100FF), 0x0D);
+    }
 }